@@ -0,0 +1,302 @@
+//! Admin operations: verifiers, configuration, treasury payouts, subscribers and snapshots.
+
+use candid::Principal;
+use registry_common::env::Environment;
+use registry_common::snapshot::SnapshotManifest;
+use registry_common::validation::ValidationLimits;
+
+use crate::audit::{self, AuditDetails, AuditEventKind};
+use crate::snapshot::SnapshotImage;
+use crate::subscriptions::{self, Subscription, SubscriptionInput};
+use crate::tax::{self, TaxPolicy};
+use crate::treasury::{self, FeePolicy};
+use crate::{config, images, is_admin, marketplace, snapshot, titles, VERIFIERS};
+
+pub fn add_verifier(env: &impl Environment, verifier: Principal) -> Result<String, String> {
+    let principal = env.caller();
+
+    // Verifiers can lift legal holds and verify land, so only admins may appoint them
+    if !is_admin(env, &principal) {
+        return Err("Only admins can add verifiers".to_string());
+    }
+
+    VERIFIERS.with(|verifiers| {
+        let mut verifiers = verifiers.borrow_mut();
+        verifiers.insert(verifier, true);
+    });
+
+    audit::record(env, principal, AuditEventKind::VerifierAdded, AuditDetails {
+        subject: Some(verifier),
+        ..Default::default()
+    });
+
+    Ok("Verifier added successfully".to_string())
+}
+
+pub fn remove_verifier(env: &impl Environment, verifier: Principal) -> Result<String, String> {
+    let principal = env.caller();
+
+    if !is_admin(env, &principal) {
+        return Err("Only admins can remove verifiers".to_string());
+    }
+
+    VERIFIERS.with(|verifiers| {
+        let mut verifiers = verifiers.borrow_mut();
+        verifiers.remove(&verifier);
+    });
+
+    audit::record(env, principal, AuditEventKind::VerifierRemoved, AuditDetails {
+        subject: Some(verifier),
+        ..Default::default()
+    });
+
+    Ok("Verifier removed successfully".to_string())
+}
+
+pub fn subscribe_events(env: &impl Environment, input: SubscriptionInput) -> Result<Subscription, String> {
+    let principal = env.caller();
+
+    if !is_admin(env, &principal) {
+        return Err("Only admins can register event subscribers".to_string());
+    }
+
+    let subscription = subscriptions::subscribe(env, principal, input)?;
+
+    audit::record(env, principal, AuditEventKind::SubscriptionAdded, AuditDetails {
+        subject: Some(subscription.canister_id),
+        after: Some(format!("subscription={} method={} kinds={:?}", subscription.id, subscription.method, subscription.event_kinds)),
+        ..Default::default()
+    });
+
+    Ok(subscription)
+}
+
+pub fn unsubscribe_events(env: &impl Environment, subscription_id: u64) -> Result<Subscription, String> {
+    let principal = env.caller();
+
+    if !is_admin(env, &principal) {
+        return Err("Only admins can remove event subscribers".to_string());
+    }
+
+    let subscription = subscriptions::unsubscribe(subscription_id)?;
+
+    audit::record(env, principal, AuditEventKind::SubscriptionRemoved, AuditDetails {
+        subject: Some(subscription.canister_id),
+        before: Some(format!("subscription={} cursor={} delivered={}", subscription.id, subscription.cursor, subscription.delivered)),
+        ..Default::default()
+    });
+
+    Ok(subscription)
+}
+
+pub fn set_validation_limits(env: &impl Environment, limits: ValidationLimits) -> Result<ValidationLimits, String> {
+    let principal = env.caller();
+
+    if !is_admin(env, &principal) {
+        return Err("Only admins can change validation limits".to_string());
+    }
+
+    let before = config::validation_limits();
+    config::set_validation_limits(&limits)?;
+
+    audit::record(env, principal, AuditEventKind::ConfigUpdated, AuditDetails {
+        before: Some(format!("{:?}", before)),
+        after: Some(format!("{:?}", limits)),
+        ..Default::default()
+    });
+
+    Ok(limits)
+}
+
+pub fn set_fee_policy(env: &impl Environment, policy: FeePolicy) -> Result<FeePolicy, String> {
+    let principal = env.caller();
+
+    if !is_admin(env, &principal) {
+        return Err("Only admins can change the fee policy".to_string());
+    }
+
+    let before = treasury::fee_policy();
+    treasury::set_fee_policy(&policy)?;
+
+    audit::record(env, principal, AuditEventKind::ConfigUpdated, AuditDetails {
+        before: Some(format!("{:?}", before)),
+        after: Some(format!("{:?}", policy)),
+        ..Default::default()
+    });
+
+    Ok(policy)
+}
+
+pub fn set_tax_policy(env: &impl Environment, policy: TaxPolicy) -> Result<TaxPolicy, String> {
+    let principal = env.caller();
+
+    if !is_admin(env, &principal) {
+        return Err("Only admins can change the tax policy".to_string());
+    }
+
+    let before = tax::tax_policy();
+    tax::set_tax_policy(&policy)?;
+
+    audit::record(env, principal, AuditEventKind::ConfigUpdated, AuditDetails {
+        before: Some(format!("{:?}", before)),
+        after: Some(format!("{:?}", policy)),
+        ..Default::default()
+    });
+
+    Ok(policy)
+}
+
+pub fn set_image_base_url(env: &impl Environment, base_url: String) -> Result<String, String> {
+    let principal = env.caller();
+
+    if !is_admin(env, &principal) {
+        return Err("Only admins can change the image base URL".to_string());
+    }
+
+    let before = images::base_url(env);
+    let after = images::set_base_url(base_url)?;
+
+    audit::record(env, principal, AuditEventKind::ConfigUpdated, AuditDetails {
+        before: Some(format!("image_base_url={}", before)),
+        after: Some(format!("image_base_url={}", after)),
+        ..Default::default()
+    });
+
+    Ok(after)
+}
+
+pub fn set_title_signing_key(env: &impl Environment, key_name: String) -> Result<String, String> {
+    let principal = env.caller();
+
+    if !is_admin(env, &principal) {
+        return Err("Only admins can change the title signing key".to_string());
+    }
+
+    let before = titles::signing_key();
+    let after = titles::set_key_name(key_name)?;
+
+    audit::record(env, principal, AuditEventKind::ConfigUpdated, AuditDetails {
+        before: Some(format!("title_signing_key={}", before.key_name)),
+        after: Some(format!("title_signing_key={}", after.key_name)),
+        ..Default::default()
+    });
+
+    Ok(after.key_name)
+}
+
+pub fn set_marketplace_canister(env: &impl Environment, canister_id: Principal) -> Result<Principal, String> {
+    let principal = env.caller();
+
+    if !is_admin(env, &principal) {
+        return Err("Only admins can change the marketplace canister".to_string());
+    }
+
+    let before = marketplace::marketplace_canister();
+    let after = marketplace::set_marketplace_canister(canister_id)?;
+
+    audit::record(env, principal, AuditEventKind::ConfigUpdated, AuditDetails {
+        before: Some(format!("marketplace_canister_id={:?}", before.map(|id| id.to_text()))),
+        after: Some(format!("marketplace_canister_id={}", after)),
+        ..Default::default()
+    });
+
+    Ok(after)
+}
+
+pub fn withdraw_from_treasury(env: &impl Environment, to: Principal, amount: u64) -> Result<u64, String> {
+    let principal = env.caller();
+
+    if !is_admin(env, &principal) {
+        return Err("Only admins can withdraw from the treasury".to_string());
+    }
+
+    let before = treasury::balance();
+    let remaining = treasury::withdraw(env, to, amount)?;
+
+    audit::record(env, principal, AuditEventKind::TreasuryWithdrawal, AuditDetails {
+        subject: Some(to),
+        amount: Some(amount),
+        before: Some(format!("treasury={}", before)),
+        after: Some(format!("treasury={}", remaining)),
+        ..Default::default()
+    });
+
+    Ok(remaining)
+}
+
+pub fn create_snapshot(env: &impl Environment) -> Result<SnapshotManifest, String> {
+    let principal = env.caller();
+
+    if !is_admin(env, &principal) {
+        return Err("Only admins can create snapshots".to_string());
+    }
+
+    // Recorded first so the snapshot includes its own creation
+    audit::record(env, principal, AuditEventKind::SnapshotCreated, AuditDetails::default());
+    snapshot::create(env)
+}
+
+pub fn get_snapshot_chunk(env: &impl Environment, snapshot_id: u64, index: u32) -> Result<Vec<u8>, String> {
+    if !is_admin(env, &env.caller()) {
+        return Err("Only admins can download snapshots".to_string());
+    }
+
+    snapshot::chunk(snapshot_id, index)
+}
+
+pub fn get_snapshot_images(env: &impl Environment, snapshot_id: u64) -> Result<Vec<SnapshotImage>, String> {
+    if !is_admin(env, &env.caller()) {
+        return Err("Only admins can download snapshots".to_string());
+    }
+
+    snapshot::images(snapshot_id)
+}
+
+pub fn get_snapshot_image_chunk(env: &impl Environment, snapshot_id: u64, image_id: u64, index: u32) -> Result<Vec<u8>, String> {
+    if !is_admin(env, &env.caller()) {
+        return Err("Only admins can download snapshots".to_string());
+    }
+
+    snapshot::image_chunk(snapshot_id, image_id, index)
+}
+
+pub fn begin_restore(env: &impl Environment, manifest: SnapshotManifest) -> Result<(), String> {
+    if !is_admin(env, &env.caller()) {
+        return Err("Only admins can restore snapshots".to_string());
+    }
+
+    snapshot::begin_restore(manifest)
+}
+
+pub fn upload_restore_chunk(env: &impl Environment, index: u32, chunk: Vec<u8>) -> Result<u32, String> {
+    if !is_admin(env, &env.caller()) {
+        return Err("Only admins can restore snapshots".to_string());
+    }
+
+    snapshot::upload_chunk(index, chunk)
+}
+
+pub fn upload_restore_image_chunk(env: &impl Environment, image_id: u64, index: u32, chunk: Vec<u8>) -> Result<u32, String> {
+    if !is_admin(env, &env.caller()) {
+        return Err("Only admins can restore snapshots".to_string());
+    }
+
+    snapshot::upload_image_chunk(image_id, index, chunk)
+}
+
+pub fn finish_restore(env: &impl Environment) -> Result<SnapshotManifest, String> {
+    let principal = env.caller();
+
+    if !is_admin(env, &principal) {
+        return Err("Only admins can restore snapshots".to_string());
+    }
+
+    let manifest = snapshot::finish_restore()?;
+
+    audit::record(env, principal, AuditEventKind::StateRestored, AuditDetails {
+        after: Some(format!("snapshot={}", manifest.snapshot_id)),
+        ..Default::default()
+    });
+
+    Ok(manifest)
+}
@@ -0,0 +1,197 @@
+use candid::{CandidType, Principal};
+use ic_stable_structures::Storable;
+use registry_common::env::Environment;
+use serde::{Serialize, Deserialize as SerdeDeserialize};
+use std::borrow::Cow;
+
+use crate::{inheritance, LandParcel, AUDIT_LOG};
+
+// Upper bound on the number of events returned by a single page
+const MAX_PAGE_SIZE: u64 = 100;
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug, PartialEq)]
+pub enum AuditEventKind {
+    LandRegistered,
+    LandVerified,
+    LandRejected,
+    LandListedForSale,
+    LandPurchased,
+    RoyaltyUpdated,
+    OwnershipTransferred,
+    VerifierAdded,
+    VerifierRemoved,
+    SubscriptionAdded,
+    SubscriptionRemoved,
+    ConfigUpdated,
+    TreasuryWithdrawal,
+    WalletInitialized,
+    WalletFunded,
+    FundsTransferred,
+    SampleDataLoaded,
+    DataCleared,
+    SnapshotCreated,
+    StateRestored,
+    InheritancePlanUpdated,
+    InheritancePlanRemoved,
+    InheritanceNoticeIssued,
+    InheritanceClaimed,
+    LandFrozen,
+    LandUnfrozen,
+    TaxStandingChanged,
+    ReclamationSaleStarted,
+    TitleCertificateIssued,
+    ImageUploaded,
+    ImageRemoved,
+    LandDetailsUpdated,
+    LandSaleCancelled,
+    LandPriceUpdated,
+    LandListingExpired,
+    OfferPlaced,
+    OfferWithdrawn,
+    OfferRejected,
+    OfferCountered,
+    OfferAccepted,
+    OfferExpired,
+    OfferCancelled,
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone)]
+pub struct AuditEvent {
+    pub id: u64,
+    pub timestamp: u64,
+    pub caller: Principal,
+    pub kind: AuditEventKind,
+    pub land_id: Option<u64>,
+    pub subject: Option<Principal>, // Counterparty of the action, e.g. new owner or verifier
+    pub amount: Option<u64>, // in e8s, e.g. price, fee or refund
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+impl Storable for AuditEvent {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+/// Optional details attached to an audit event.
+#[derive(Default)]
+pub struct AuditDetails {
+    pub land_id: Option<u64>,
+    pub subject: Option<Principal>,
+    pub amount: Option<u64>,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Default)]
+pub struct AuditFilter {
+    pub land_id: Option<u64>,
+    pub principal: Option<Principal>, // Matches either the caller or the subject
+    pub kind: Option<AuditEventKind>,
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize)]
+pub struct AuditEventPage {
+    pub events: Vec<AuditEvent>,
+    pub next_cursor: Option<u64>,
+}
+
+impl AuditFilter {
+    fn matches(&self, event: &AuditEvent) -> bool {
+        if let Some(land_id) = self.land_id {
+            if event.land_id != Some(land_id) {
+                return false;
+            }
+        }
+
+        if let Some(principal) = self.principal {
+            if event.caller != principal && event.subject != Some(principal) {
+                return false;
+            }
+        }
+
+        if let Some(kind) = &self.kind {
+            if &event.kind != kind {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Appends an event to the audit log. Events are never updated or removed.
+pub fn record(env: &impl Environment, caller: Principal, kind: AuditEventKind, details: AuditDetails) -> u64 {
+    // Any recorded action shows the caller still controls their principal
+    inheritance::record_activity(caller, env.time());
+
+    AUDIT_LOG.with(|log| {
+        let mut log = log.borrow_mut();
+        let id = log.last_key_value().map(|(id, _)| id + 1).unwrap_or(1);
+
+        log.insert(id, AuditEvent {
+            id,
+            timestamp: env.time(),
+            caller,
+            kind,
+            land_id: details.land_id,
+            subject: details.subject,
+            amount: details.amount,
+            before: details.before,
+            after: details.after,
+        });
+
+        id
+    })
+}
+
+/// Short human readable summary of the mutable parts of a parcel.
+pub fn summarize_land(land: &LandParcel) -> String {
+    let price = match land.price {
+        Some(price) => price.to_string(),
+        None => "none".to_string(),
+    };
+
+    format!("owner={} status={:?} price={}", land.owner, land.status, price)
+}
+
+/// Returns up to `limit` matching events with an id greater than `cursor`, oldest first.
+pub fn events_page(filter: &AuditFilter, cursor: Option<u64>, limit: u64) -> AuditEventPage {
+    let limit = limit.clamp(1, MAX_PAGE_SIZE) as usize;
+    let start = cursor.map(|cursor| cursor + 1).unwrap_or(0);
+
+    AUDIT_LOG.with(|log| {
+        let log = log.borrow();
+        let mut events = Vec::new();
+        let mut next_cursor = None;
+
+        for (_, event) in log.range(start..) {
+            if !filter.matches(&event) {
+                continue;
+            }
+
+            // Only hand out a cursor when there is at least one more match
+            if events.len() == limit {
+                next_cursor = events.last().map(|event: &AuditEvent| event.id);
+                break;
+            }
+
+            events.push(event);
+        }
+
+        AuditEventPage { events, next_cursor }
+    })
+}
+
+pub fn event_count() -> u64 {
+    AUDIT_LOG.with(|log| {
+        log.borrow().len()
+    })
+}
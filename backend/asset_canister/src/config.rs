@@ -0,0 +1,26 @@
+use registry_common::validation::ValidationLimits;
+
+use crate::CONFIG;
+
+const VALIDATION_LIMITS_KEY: &str = "validation_limits";
+
+pub fn validation_limits() -> ValidationLimits {
+    CONFIG.with(|config| {
+        config
+            .borrow()
+            .get(&VALIDATION_LIMITS_KEY.to_string())
+            .and_then(|value| serde_json::from_str(&value).ok())
+            .unwrap_or_default()
+    })
+}
+
+pub fn set_validation_limits(limits: &ValidationLimits) -> Result<(), String> {
+    limits.check()?;
+
+    let value = serde_json::to_string(limits).map_err(|e| format!("Failed to encode limits: {}", e))?;
+    CONFIG.with(|config| {
+        config.borrow_mut().insert(VALIDATION_LIMITS_KEY.to_string(), value);
+    });
+
+    Ok(())
+}
@@ -0,0 +1,392 @@
+//! Demo-only endpoints: sample data, data wipes and a test-funds faucet.
+//! Compiled only with the `demo` feature so they never ship in a production build.
+
+use candid::Principal;
+use registry_common::env::Environment;
+
+use crate::audit::{self, AuditDetails, AuditEventKind};
+use crate::escrow::HoldPurpose;
+use crate::images::IMAGE_ID_KEY;
+use crate::pricing;
+use crate::wallets::{self, WalletDetails, WalletTransactionKind};
+use crate::{get_next_land_id, LandParcel, LandStatus, LandTransfer, TransferKind};
+use crate::{ESCROW_HOLDS, FAUCET_USAGE, IMAGES, IMAGE_CHUNKS, IMAGE_UPLOADS, INHERITANCE_PLANS, LANDS, LAND_ID_COUNTER, LAND_REVISIONS, LAST_ACTIVITY, OFFERS, PRICE_HISTORY, TAX_ACCOUNTS, TREASURY_LEDGER, WALLETS, WALLET_TRANSACTIONS};
+
+const NANOS_PER_DAY: u64 = 86_400_000_000_000;
+// Per principal and UTC day
+const FAUCET_DAILY_LIMIT: u64 = 100_000_000_000; // 1000 ICP in e8s
+
+/// Seeds the registry with verified demo parcels and funded demo wallets.
+pub fn initialize_sample_data(env: &impl Environment) -> String {
+    let principal = env.caller();
+
+    // Only allow initialization if no lands exist yet
+    let land_count = LANDS.with(|lands| lands.borrow().len());
+    if land_count > 0 {
+        return "Sample data already exists".to_string();
+    }
+    
+    let current_time = env.time();
+    
+    // Create mock users for demo purposes
+    let mock_user_1 = Principal::from_text("rdmx6-jaaaa-aaaah-qcaiq-cai").unwrap_or(Principal::anonymous());
+    let mock_user_2 = Principal::from_text("rrkah-fqaaa-aaaah-qcaiq-cai").unwrap_or(Principal::anonymous());
+    
+    // Initialize wallets for mock users with demo funds
+    let demo_funds = [
+        (mock_user_1, 100_000_000_000u64), // 1000 ICP
+        (mock_user_2, 150_000_000_000u64), // 1500 ICP
+        (principal, 200_000_000_000u64), // 2000 ICP for current user
+    ];
+    for (user, amount) in demo_funds {
+        wallets::credit(env, user, amount, WalletTransactionKind::Deposit, WalletDetails {
+            memo: Some("Demo funds".to_string()),
+            ..Default::default()
+        });
+    }
+    
+    // Create sample lands with different owners
+    let sample_lands = vec![
+        // Land owned by current user
+        LandParcel {
+            id: get_next_land_id(),
+            owner: principal,
+            coordinates: "40.7128, -74.0060".to_string(), // New York coordinates
+            size: 1000.0,
+            description: "Prime Manhattan virtual land parcel with stunning city views".to_string(),
+            status: LandStatus::Verified,
+            verified_by: Some(principal),
+            created_at: current_time,
+            updated_at: current_time,
+            history: vec![LandTransfer {
+                from: Principal::anonymous(),
+                to: principal,
+                timestamp: current_time,
+                verified_by: Some(principal),
+                sale: None,
+                transfer_kind: Some(TransferKind::Registration),
+            }],
+            price: None,
+            metadata: "Commercial zoning, high traffic area".to_string(),
+            registration_fee: None,
+            royalty: None,
+            freeze: None,
+            listing_expires_at: None,
+            preview_image_url: Some("https://images.unsplash.com/photo-1518020382113-a7e8fc38eac9".to_string()),
+        },
+        // Land owned by mock user 1 - FOR SALE
+        LandParcel {
+            id: get_next_land_id(),
+            owner: mock_user_1,
+            coordinates: "34.0522, -118.2437".to_string(), // Los Angeles coordinates
+            size: 2500.0,
+            description: "Luxury beachfront property in virtual Malibu - Ready for purchase!".to_string(),
+            status: LandStatus::ForSale,
+            verified_by: Some(principal),
+            created_at: current_time,
+            updated_at: current_time,
+            history: vec![LandTransfer {
+                from: Principal::anonymous(),
+                to: mock_user_1,
+                timestamp: current_time,
+                verified_by: Some(principal),
+                sale: None,
+                transfer_kind: Some(TransferKind::Registration),
+            }],
+            price: Some(5_000_000_000u64), // 50 ICP in e8s
+            metadata: "Residential zoning, beachfront access".to_string(),
+            registration_fee: None,
+            royalty: None,
+            freeze: None,
+            listing_expires_at: None,
+            preview_image_url: Some("https://images.unsplash.com/photo-1469474968028-56623f02e42e".to_string()),
+        },
+        // Land owned by mock user 2 - FOR SALE
+        LandParcel {
+            id: get_next_land_id(),
+            owner: mock_user_2,
+            coordinates: "37.7749, -122.4194".to_string(), // San Francisco coordinates
+            size: 800.0,
+            description: "Tech district land perfect for virtual offices and startups - Available now!".to_string(),
+            status: LandStatus::ForSale,
+            verified_by: Some(principal),
+            created_at: current_time,
+            updated_at: current_time,
+            history: vec![LandTransfer {
+                from: Principal::anonymous(),
+                to: mock_user_2,
+                timestamp: current_time,
+                verified_by: Some(principal),
+                sale: None,
+                transfer_kind: Some(TransferKind::Registration),
+            }],
+            price: Some(7_500_000_000u64), // 75 ICP in e8s
+            metadata: "Commercial zoning, tech district".to_string(),
+            registration_fee: None,
+            royalty: None,
+            freeze: None,
+            listing_expires_at: None,
+            preview_image_url: Some("https://images.unsplash.com/photo-1574949645342-19a5733c3e7b".to_string()),
+        },
+        // Another land owned by current user
+        LandParcel {
+            id: get_next_land_id(),
+            owner: principal,
+            coordinates: "51.5074, -0.1278".to_string(), // London coordinates
+            size: 1200.0,
+            description: "Historic London virtual district with heritage buildings".to_string(),
+            status: LandStatus::Verified,
+            verified_by: Some(principal),
+            created_at: current_time,
+            updated_at: current_time,
+            history: vec![LandTransfer {
+                from: Principal::anonymous(),
+                to: principal,
+                timestamp: current_time,
+                verified_by: Some(principal),
+                sale: None,
+                transfer_kind: Some(TransferKind::Registration),
+            }],
+            price: None,
+            metadata: "Mixed-use zoning, historic district".to_string(),
+            registration_fee: None,
+            royalty: None,
+            freeze: None,
+            listing_expires_at: None,
+            preview_image_url: Some("https://images.unsplash.com/photo-1513635269975-59663e0ac1ad".to_string()),
+        },
+    ];
+    
+    let loaded = sample_lands.len();
+    LANDS.with(|lands| {
+        let mut lands = lands.borrow_mut();
+        for land in sample_lands {
+            if let Some(price) = land.price {
+                pricing::record_listing(env, &land, price);
+            }
+            lands.insert(land.id, land);
+        }
+    });
+
+    audit::record(env, principal, AuditEventKind::SampleDataLoaded, AuditDetails {
+        after: Some(format!("lands={} demo_wallets=3", loaded)),
+        ..Default::default()
+    });
+    
+    "Successfully initialized 4 sample land parcels with demo wallets".to_string()
+}
+
+pub fn add_more_sample_lands(env: &impl Environment) -> String {
+    let principal = env.caller();
+    let current_time = env.time();
+    
+    // Create mock users for demo purposes
+    let mock_user_1 = Principal::from_text("rdmx6-jaaaa-aaaah-qcaiq-cai").unwrap_or(Principal::anonymous());
+    let mock_user_2 = Principal::from_text("rrkah-fqaaa-aaaah-qcaiq-cai").unwrap_or(Principal::anonymous());
+    
+    // Additional sample lands for sale
+    let additional_lands = vec![
+        // Miami Beach land for sale
+        LandParcel {
+            id: get_next_land_id(),
+            owner: mock_user_1,
+            coordinates: "25.7617, -80.1918".to_string(), // Miami coordinates
+            size: 1500.0,
+            description: "Stunning Miami Beach virtual property with ocean views and nightlife access".to_string(),
+            status: LandStatus::ForSale,
+            verified_by: Some(principal),
+            created_at: current_time,
+            updated_at: current_time,
+            history: vec![LandTransfer {
+                from: Principal::anonymous(),
+                to: mock_user_1,
+                timestamp: current_time,
+                verified_by: Some(principal),
+                sale: None,
+                transfer_kind: Some(TransferKind::Registration),
+            }],
+            price: Some(12_000_000_000u64), // 120 ICP in e8s  
+            metadata: "Entertainment district, beach access".to_string(),
+            registration_fee: None,
+            royalty: None,
+            freeze: None,
+            listing_expires_at: None,
+            preview_image_url: Some("https://images.unsplash.com/photo-1506905925346-21bda4d32df4".to_string()),
+        },
+        // Tokyo Downtown land for sale
+        LandParcel {
+            id: get_next_land_id(),
+            owner: mock_user_2,
+            coordinates: "35.6762, 139.6503".to_string(), // Tokyo coordinates
+            size: 600.0,
+            description: "Premium Tokyo downtown virtual space in the heart of the financial district".to_string(),
+            status: LandStatus::ForSale,
+            verified_by: Some(principal),
+            created_at: current_time,
+            updated_at: current_time,
+            history: vec![LandTransfer {
+                from: Principal::anonymous(),
+                to: mock_user_2,
+                timestamp: current_time,
+                verified_by: Some(principal),
+                sale: None,
+                transfer_kind: Some(TransferKind::Registration),
+            }],
+            price: Some(8_500_000_000u64), // 85 ICP in e8s
+            metadata: "Financial district, high-rise development".to_string(),
+            registration_fee: None,
+            royalty: None,
+            freeze: None,
+            listing_expires_at: None,
+            preview_image_url: Some("https://images.unsplash.com/photo-1540959733332-eab4deabeeaf".to_string()),
+        },
+        // Las Vegas Strip land for sale
+        LandParcel {
+            id: get_next_land_id(),
+            owner: mock_user_1,
+            coordinates: "36.1699, -115.1398".to_string(), // Las Vegas coordinates
+            size: 2000.0,
+            description: "Virtual Las Vegas Strip property perfect for entertainment venues and casinos".to_string(),
+            status: LandStatus::ForSale,
+            verified_by: Some(principal),
+            created_at: current_time,
+            updated_at: current_time,
+            history: vec![LandTransfer {
+                from: Principal::anonymous(),
+                to: mock_user_1,
+                timestamp: current_time,
+                verified_by: Some(principal),
+                sale: None,
+                transfer_kind: Some(TransferKind::Registration),
+            }],
+            price: Some(15_000_000_000u64), // 150 ICP in e8s
+            metadata: "Entertainment zoning, casino district".to_string(),
+            registration_fee: None,
+            royalty: None,
+            freeze: None,
+            listing_expires_at: None,
+            preview_image_url: Some("https://images.unsplash.com/photo-1605833556294-ea9d2d702878".to_string()),
+        },
+    ];
+    
+    let loaded = additional_lands.len();
+    LANDS.with(|lands| {
+        let mut lands = lands.borrow_mut();
+        for land in additional_lands {
+            if let Some(price) = land.price {
+                pricing::record_listing(env, &land, price);
+            }
+            lands.insert(land.id, land);
+        }
+    });
+
+    audit::record(env, principal, AuditEventKind::SampleDataLoaded, AuditDetails {
+        after: Some(format!("lands={}", loaded)),
+        ..Default::default()
+    });
+    
+    "Successfully added 3 additional land parcels for sale (Miami Beach $12K, Tokyo Downtown $8.5K, Las Vegas Strip $15K)".to_string()
+}
+
+/// Wipes parcels, balances and their histories. Verifiers, configuration and the audit log are
+/// kept, and so are the escrow holds of marketplace purchases and auctions, which the marketplace
+/// still settles or releases by id.
+pub fn clear_all_data(env: &impl Environment) -> String {
+    let principal = env.caller();
+    let before = format!(
+        "lands={} wallets={}",
+        LANDS.with(|lands| lands.borrow().len()),
+        WALLETS.with(|wallets| wallets.borrow().len()),
+    );
+
+    LANDS.with(|lands| lands.borrow_mut().clear_new());
+    // Hold and subscription ids are never reused, so only the land and image counters restart
+    LAND_ID_COUNTER.with(|counter| {
+        let mut counter = counter.borrow_mut();
+        counter.remove(&0);
+        counter.remove(&IMAGE_ID_KEY);
+    });
+    WALLETS.with(|wallets| wallets.borrow_mut().clear_new());
+    WALLET_TRANSACTIONS.with(|transactions| transactions.borrow_mut().clear_new());
+    PRICE_HISTORY.with(|history| history.borrow_mut().clear_new());
+    TREASURY_LEDGER.with(|ledger| ledger.borrow_mut().clear_new());
+    FAUCET_USAGE.with(|usage| usage.borrow_mut().clear_new());
+    INHERITANCE_PLANS.with(|plans| plans.borrow_mut().clear_new());
+    LAST_ACTIVITY.with(|activity| activity.borrow_mut().clear_new());
+    TAX_ACCOUNTS.with(|accounts| accounts.borrow_mut().clear_new());
+    IMAGES.with(|images| images.borrow_mut().clear_new());
+    IMAGE_CHUNKS.with(|chunks| chunks.borrow_mut().clear_new());
+    IMAGE_UPLOADS.with(|uploads| uploads.borrow_mut().clear_new());
+    LAND_REVISIONS.with(|revisions| revisions.borrow_mut().clear_new());
+    OFFERS.with(|offers| offers.borrow_mut().clear_new());
+    ESCROW_HOLDS.with(|holds| {
+        let mut holds = holds.borrow_mut();
+        let offer_holds: Vec<u64> = holds
+            .iter()
+            .filter(|(_, hold)| matches!(hold.purpose, HoldPurpose::Offer(_)))
+            .map(|(id, _)| id)
+            .collect();
+        for hold_id in offer_holds {
+            holds.remove(&hold_id);
+        }
+    });
+    
+    // The audit log itself is deliberately left intact
+    audit::record(env, principal, AuditEventKind::DataCleared, AuditDetails {
+        before: Some(before),
+        ..Default::default()
+    });
+    
+    "All data cleared successfully".to_string()
+}
+
+/// Remaining amount the user can still claim from the faucet today.
+pub fn faucet_allowance(env: &impl Environment, user: &Principal) -> u64 {
+    let today = env.time() / NANOS_PER_DAY;
+    let claimed_today = FAUCET_USAGE.with(|usage| {
+        match usage.borrow().get(user) {
+            Some((day, claimed)) if day == today => claimed,
+            _ => 0,
+        }
+    });
+
+    FAUCET_DAILY_LIMIT.saturating_sub(claimed_today)
+}
+
+/// Test funds for demo deployments, capped per principal and day.
+pub fn add_funds_to_wallet(env: &impl Environment, amount: u64) -> Result<u64, String> {
+    let user = env.caller();
+
+    if user == Principal::anonymous() {
+        return Err("Anonymous users cannot have wallets".to_string());
+    }
+
+    if amount == 0 {
+        return Err("Amount must be greater than zero".to_string());
+    }
+
+    let allowance = faucet_allowance(env, &user);
+    if amount > allowance {
+        return Err(format!("Daily faucet limit reached. You can claim {} more e8s today", allowance));
+    }
+
+    let today = env.time() / NANOS_PER_DAY;
+    let claimed_today = FAUCET_DAILY_LIMIT - allowance + amount;
+    FAUCET_USAGE.with(|usage| {
+        usage.borrow_mut().insert(user, (today, claimed_today));
+    });
+    
+    let current_balance = wallets::balance(&user);
+    let new_balance = wallets::credit(env, user, amount, WalletTransactionKind::Deposit, WalletDetails {
+        memo: Some("Faucet".to_string()),
+        ..Default::default()
+    });
+
+    audit::record(env, user, AuditEventKind::WalletFunded, AuditDetails {
+        amount: Some(amount),
+        before: Some(format!("balance={}", current_balance)),
+        after: Some(format!("balance={}", new_balance)),
+        ..Default::default()
+    });
+    Ok(new_balance)
+}
@@ -0,0 +1,108 @@
+//! Funds set aside from a wallet until a pending deal either completes or falls through.
+//!
+//! A hold debits the wallet straight away, so held funds cannot be spent twice, and records what
+//! they are held for. Releasing a hold credits the funds back.
+
+use candid::{CandidType, Principal};
+use ic_stable_structures::Storable;
+use registry_common::env::Environment;
+use serde::{Serialize, Deserialize as SerdeDeserialize};
+use std::borrow::Cow;
+
+use crate::wallets::{self, WalletDetails, WalletTransactionKind};
+use crate::{ESCROW_HOLDS, LAND_ID_COUNTER};
+
+// Key of the hold id counter in LAND_ID_COUNTER. Released holds are deleted, but their ids stay on
+// offers and marketplace records, so an id is never handed out twice.
+pub const HOLD_ID_KEY: u8 = 2;
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug, PartialEq)]
+pub enum HoldPurpose {
+    Offer(u64), // Offer id
+    Purchase(u64), // Marketplace transaction id
+    Auction(u64), // Marketplace auction id
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone)]
+pub struct EscrowHold {
+    pub id: u64,
+    pub owner: Principal,
+    pub amount: u64, // in e8s
+    pub land_id: u64,
+    pub purpose: HoldPurpose,
+    pub created_at: u64,
+}
+
+impl Storable for EscrowHold {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+/// Moves `amount` out of the owner's wallet into a new hold. Returns the hold id.
+pub fn hold(env: &impl Environment, owner: Principal, amount: u64, land_id: u64, purpose: HoldPurpose) -> Result<u64, String> {
+    if amount == 0 {
+        return Err("Held amount must be greater than zero".to_string());
+    }
+
+    wallets::debit(env, owner, amount, WalletTransactionKind::EscrowHold, WalletDetails {
+        land_id: Some(land_id),
+        ..Default::default()
+    })?;
+
+    ESCROW_HOLDS.with(|holds| {
+        let mut holds = holds.borrow_mut();
+        let id = next_hold_id(holds.last_key_value().map(|(id, _)| id));
+        holds.insert(id, EscrowHold {
+            id,
+            owner,
+            amount,
+            land_id,
+            purpose,
+            created_at: env.time(),
+        });
+        Ok(id)
+    })
+}
+
+pub fn get(hold_id: u64) -> Option<EscrowHold> {
+    ESCROW_HOLDS.with(|holds| holds.borrow().get(&hold_id))
+}
+
+/// Returns held funds to their owner. Returns the owner's new balance.
+pub fn release(env: &impl Environment, hold_id: u64) -> Result<u64, String> {
+    let hold = ESCROW_HOLDS.with(|holds| holds.borrow_mut().remove(&hold_id)).ok_or("Escrow hold not found")?;
+
+    Ok(wallets::credit(env, hold.owner, hold.amount, WalletTransactionKind::EscrowRelease, WalletDetails {
+        land_id: Some(hold.land_id),
+        ..Default::default()
+    }))
+}
+
+// Holds created before the counter existed are taken into account
+fn next_hold_id(last_stored: Option<u64>) -> u64 {
+    LAND_ID_COUNTER.with(|counter| {
+        let mut counter = counter.borrow_mut();
+        let next_id = counter.get(&HOLD_ID_KEY).unwrap_or(0).max(last_stored.unwrap_or(0)) + 1;
+        counter.insert(HOLD_ID_KEY, next_id);
+        next_id
+    })
+}
+
+/// Total a principal currently has on hold.
+pub fn held_by(owner: &Principal) -> u64 {
+    ESCROW_HOLDS.with(|holds| {
+        holds
+            .borrow()
+            .iter()
+            .filter(|(_, hold)| hold.owner == *owner)
+            .map(|(_, hold)| hold.amount)
+            .sum()
+    })
+}
@@ -0,0 +1,476 @@
+//! Preview and gallery images stored in stable memory and served over HTTP.
+//!
+//! Owners upload an image in chunks: `begin_upload` declares its size, MIME type and SHA-256,
+//! `upload_chunk` appends the bytes in order and `finish_upload` checks them against the
+//! declaration. Finished images are immutable and served from `/images/{land_id}/{image_id}`, so
+//! they can be cached indefinitely. Images are capped so that one fits in a single HTTP response.
+
+use candid::{CandidType, Principal};
+use ic_stable_structures::Storable;
+use registry_common::env::Environment;
+use registry_common::snapshot::sha256_hex;
+use serde::{Serialize, Deserialize as SerdeDeserialize};
+use std::borrow::Cow;
+
+use crate::audit::{self, AuditDetails, AuditEventKind};
+use crate::{lands, LandParcel, CONFIG, IMAGES, IMAGE_CHUNKS, IMAGE_UPLOADS, LANDS, LAND_ID_COUNTER};
+
+pub const MAX_IMAGE_SIZE: u64 = 1_536 * 1024; // 1.5 MiB
+pub const MAX_CHUNK_SIZE: usize = 1024 * 1024;
+const MAX_GALLERY_IMAGES: usize = 8;
+const MAX_PENDING_UPLOADS: usize = 4; // Per principal
+// Unfinished uploads are discarded after this long
+const UPLOAD_TIMEOUT_NS: u64 = 60 * 60 * 1_000_000_000;
+// Key of the image id counter in LAND_ID_COUNTER; land ids use key 0
+pub const IMAGE_ID_KEY: u8 = 1;
+const BASE_URL_KEY: &str = "image_base_url";
+const MAX_BASE_URL_LEN: usize = 256;
+const CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug, PartialEq)]
+pub enum ImageRole {
+    Preview, // Replaces the parcel's previous preview and sets `preview_image_url`
+    Gallery,
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug)]
+pub struct ImageInfo {
+    pub id: u64,
+    pub land_id: u64,
+    pub role: ImageRole,
+    pub mime_type: String,
+    pub size: u64,
+    pub sha256: String, // Hex, also served as the ETag
+    pub chunk_count: u32,
+    pub uploaded_by: Principal,
+    pub uploaded_at: u64,
+    pub url: String,
+}
+
+impl Storable for ImageInfo {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize)]
+pub struct ImageUploadInput {
+    pub land_id: u64,
+    pub role: ImageRole,
+    pub mime_type: String,
+    pub size: u64,
+    pub sha256: String, // Hex SHA-256 of the whole image
+}
+
+// An upload in progress. Its chunks are written to IMAGE_CHUNKS under the final image id.
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone)]
+pub struct ImageUpload {
+    pub id: u64,
+    pub land_id: u64,
+    pub owner: Principal,
+    pub role: ImageRole,
+    pub mime_type: String,
+    pub size: u64,
+    pub sha256: String,
+    pub received: u64,
+    pub chunk_count: u32,
+    pub started_at: u64,
+}
+
+impl Storable for ImageUpload {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(CandidType, SerdeDeserialize)]
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+#[derive(CandidType, SerdeDeserialize, Debug)]
+pub struct HttpResponse {
+    pub status_code: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+pub fn begin_upload(env: &impl Environment, input: ImageUploadInput) -> Result<u64, String> {
+    let owner = env.caller();
+    let now = env.time();
+
+    let land = owned_land(owner, input.land_id)?;
+
+    if !is_supported(&input.mime_type) {
+        return Err("Images must be PNG, JPEG, GIF or WebP".to_string());
+    }
+
+    if input.size == 0 || input.size > MAX_IMAGE_SIZE {
+        return Err(format!("Images must be between 1 and {} bytes", MAX_IMAGE_SIZE));
+    }
+
+    if input.sha256.len() != 64 || !input.sha256.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err("Image hash must be a hex encoded SHA-256".to_string());
+    }
+
+    if input.role == ImageRole::Gallery && count_gallery(land.id) >= MAX_GALLERY_IMAGES {
+        return Err(format!("A parcel can have at most {} gallery images", MAX_GALLERY_IMAGES));
+    }
+
+    discard_stale_uploads(now);
+    let pending = IMAGE_UPLOADS.with(|uploads| {
+        uploads.borrow().iter().filter(|(_, upload)| upload.owner == owner).count()
+    });
+    if pending >= MAX_PENDING_UPLOADS {
+        return Err("Too many unfinished uploads; finish or wait for them to expire".to_string());
+    }
+
+    let id = next_image_id();
+    let upload = ImageUpload {
+        id,
+        land_id: land.id,
+        owner,
+        role: input.role,
+        mime_type: input.mime_type,
+        size: input.size,
+        sha256: input.sha256.to_ascii_lowercase(),
+        received: 0,
+        chunk_count: 0,
+        started_at: now,
+    };
+
+    IMAGE_UPLOADS.with(|uploads| {
+        uploads.borrow_mut().insert(id, upload);
+    });
+
+    Ok(id)
+}
+
+/// Appends the next chunk. Returns the number of bytes received so far.
+pub fn upload_chunk(env: &impl Environment, upload_id: u64, index: u32, chunk: Vec<u8>) -> Result<u64, String> {
+    let mut upload = pending_upload(env, upload_id)?;
+
+    if index != upload.chunk_count {
+        return Err(format!("Expected chunk {}", upload.chunk_count));
+    }
+
+    if chunk.is_empty() || chunk.len() > MAX_CHUNK_SIZE {
+        return Err(format!("Chunks must be between 1 and {} bytes", MAX_CHUNK_SIZE));
+    }
+
+    if upload.received + chunk.len() as u64 > upload.size {
+        return Err("Chunk exceeds the declared image size".to_string());
+    }
+
+    if index == 0 && !matches_signature(&upload.mime_type, &chunk) {
+        return Err(format!("Image content is not {}", upload.mime_type));
+    }
+
+    upload.received += chunk.len() as u64;
+    upload.chunk_count += 1;
+
+    IMAGE_CHUNKS.with(|chunks| {
+        chunks.borrow_mut().insert((upload_id, index), chunk);
+    });
+    IMAGE_UPLOADS.with(|uploads| {
+        uploads.borrow_mut().insert(upload_id, upload.clone());
+    });
+
+    Ok(upload.received)
+}
+
+pub fn finish_upload(env: &impl Environment, upload_id: u64) -> Result<ImageInfo, String> {
+    let upload = pending_upload(env, upload_id)?;
+
+    if upload.received != upload.size {
+        return Err(format!("Received {} of {} bytes", upload.received, upload.size));
+    }
+
+    let mut land = owned_land(upload.owner, upload.land_id)?;
+
+    // The parcel may have gained gallery images since the upload began
+    if upload.role == ImageRole::Gallery && count_gallery(land.id) >= MAX_GALLERY_IMAGES {
+        discard_upload(upload_id);
+        return Err(format!("A parcel can have at most {} gallery images", MAX_GALLERY_IMAGES));
+    }
+
+    if sha256_hex(&read_bytes(upload_id)) != upload.sha256 {
+        discard_upload(upload_id);
+        return Err("Image content does not match the declared hash".to_string());
+    }
+
+    let now = env.time();
+    let image = ImageInfo {
+        id: upload_id,
+        land_id: land.id,
+        role: upload.role,
+        mime_type: upload.mime_type,
+        size: upload.size,
+        sha256: upload.sha256,
+        chunk_count: upload.chunk_count,
+        uploaded_by: upload.owner,
+        uploaded_at: now,
+        url: format!("{}/images/{}/{}", base_url(env), land.id, upload_id),
+    };
+
+    IMAGE_UPLOADS.with(|uploads| uploads.borrow_mut().remove(&upload_id));
+
+    let mut before = None;
+    if image.role == ImageRole::Preview {
+        let previous: Vec<u64> = images_of(land.id)
+            .into_iter()
+            .filter(|existing| existing.role == ImageRole::Preview)
+            .map(|existing| existing.id)
+            .collect();
+        for id in previous {
+            delete_image(land.id, id);
+        }
+
+        before = land.preview_image_url.replace(image.url.clone());
+        land.updated_at = now;
+        LANDS.with(|lands| {
+            lands.borrow_mut().insert(land.id, land.clone());
+        });
+    }
+
+    IMAGES.with(|images| {
+        images.borrow_mut().insert((land.id, image.id), image.clone());
+    });
+
+    audit::record(env, upload.owner, AuditEventKind::ImageUploaded, AuditDetails {
+        land_id: Some(land.id),
+        before,
+        after: Some(image.url.clone()),
+        ..Default::default()
+    });
+
+    Ok(image)
+}
+
+pub fn remove_image(env: &impl Environment, land_id: u64, image_id: u64) -> Result<ImageInfo, String> {
+    let owner = env.caller();
+    let mut land = owned_land(owner, land_id)?;
+
+    let image = IMAGES.with(|images| images.borrow().get(&(land_id, image_id))).ok_or("Image not found")?;
+    delete_image(land_id, image_id);
+
+    if land.preview_image_url.as_deref() == Some(image.url.as_str()) {
+        land.preview_image_url = None;
+        land.updated_at = env.time();
+        LANDS.with(|lands| {
+            lands.borrow_mut().insert(land_id, land);
+        });
+    }
+
+    audit::record(env, owner, AuditEventKind::ImageRemoved, AuditDetails {
+        land_id: Some(land_id),
+        before: Some(image.url.clone()),
+        ..Default::default()
+    });
+
+    Ok(image)
+}
+
+/// The parcel's images, preview first, then gallery images in upload order.
+pub fn images_of(land_id: u64) -> Vec<ImageInfo> {
+    let mut images: Vec<ImageInfo> = IMAGES.with(|images| {
+        images
+            .borrow()
+            .range((land_id, 0)..=(land_id, u64::MAX))
+            .map(|(_, image)| image)
+            .collect()
+    });
+    images.sort_by_key(|image| (image.role != ImageRole::Preview, image.id));
+    images
+}
+
+pub fn base_url(env: &impl Environment) -> String {
+    CONFIG.with(|config| config.borrow().get(&BASE_URL_KEY.to_string()))
+        // Responses are not certified, so they have to go through the raw domain
+        .unwrap_or_else(|| format!("https://{}.raw.icp0.io", env.canister_id()))
+}
+
+pub fn set_base_url(base_url: String) -> Result<String, String> {
+    let base_url = base_url.trim_end_matches('/').to_string();
+
+    if base_url.len() > MAX_BASE_URL_LEN || !(base_url.starts_with("https://") || base_url.starts_with("http://")) {
+        return Err(format!("Base URL must be an http(s) URL of at most {} characters", MAX_BASE_URL_LEN));
+    }
+
+    CONFIG.with(|config| {
+        config.borrow_mut().insert(BASE_URL_KEY.to_string(), base_url.clone());
+    });
+
+    Ok(base_url)
+}
+
+pub fn http_request(request: &HttpRequest) -> HttpResponse {
+    if request.method != "GET" && request.method != "HEAD" {
+        return text_response(405, "Method not allowed");
+    }
+
+    let path = request.url.split(['?', '#']).next().unwrap_or_default();
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let key = match segments.as_slice() {
+        ["images", land_id, image_id] => match (land_id.parse::<u64>(), image_id.parse::<u64>()) {
+            (Ok(land_id), Ok(image_id)) => (land_id, image_id),
+            _ => return text_response(404, "Not found"),
+        },
+        _ => return text_response(404, "Not found"),
+    };
+
+    let Some(image) = IMAGES.with(|images| images.borrow().get(&key)) else {
+        return text_response(404, "Not found");
+    };
+
+    let etag = format!("\"{}\"", image.sha256);
+    let mut headers = vec![
+        ("Content-Type".to_string(), image.mime_type.clone()),
+        ("Cache-Control".to_string(), CACHE_CONTROL.to_string()),
+        ("ETag".to_string(), etag.clone()),
+        ("X-Content-Type-Options".to_string(), "nosniff".to_string()),
+    ];
+
+    let not_modified = request.headers.iter().any(|(name, value)| {
+        name.eq_ignore_ascii_case("if-none-match") && value.split(',').any(|tag| tag.trim() == etag)
+    });
+    if not_modified {
+        return HttpResponse { status_code: 304, headers, body: Vec::new() };
+    }
+
+    // A restored image is served only once all of its chunks have been uploaded again
+    if chunk_count_of(image.id) != image.chunk_count {
+        return text_response(503, "Image is being restored");
+    }
+
+    headers.push(("Content-Length".to_string(), image.size.to_string()));
+    let body = if request.method == "HEAD" { Vec::new() } else { read_bytes(image.id) };
+
+    HttpResponse { status_code: 200, headers, body }
+}
+
+fn owned_land(owner: Principal, land_id: u64) -> Result<LandParcel, String> {
+    let land = LANDS.with(|lands| lands.borrow().get(&land_id)).ok_or("Land parcel not found")?;
+
+    if land.owner != owner {
+        return Err("Only the owner can change a parcel's images".to_string());
+    }
+
+    lands::ensure_not_frozen(&land)?;
+    Ok(land)
+}
+
+fn pending_upload(env: &impl Environment, upload_id: u64) -> Result<ImageUpload, String> {
+    let upload = IMAGE_UPLOADS.with(|uploads| uploads.borrow().get(&upload_id)).ok_or("Upload not found")?;
+
+    if upload.owner != env.caller() {
+        return Err("Upload not found".to_string());
+    }
+
+    if env.time() > upload.started_at + UPLOAD_TIMEOUT_NS {
+        discard_upload(upload_id);
+        return Err("Upload has expired".to_string());
+    }
+
+    Ok(upload)
+}
+
+fn next_image_id() -> u64 {
+    LAND_ID_COUNTER.with(|counter| {
+        let mut counter = counter.borrow_mut();
+        let next_id = counter.get(&IMAGE_ID_KEY).unwrap_or(0) + 1;
+        counter.insert(IMAGE_ID_KEY, next_id);
+        next_id
+    })
+}
+
+fn count_gallery(land_id: u64) -> usize {
+    images_of(land_id).iter().filter(|image| image.role == ImageRole::Gallery).count()
+}
+
+pub fn read_bytes(image_id: u64) -> Vec<u8> {
+    IMAGE_CHUNKS.with(|chunks| {
+        chunks
+            .borrow()
+            .range((image_id, 0)..=(image_id, u32::MAX))
+            .flat_map(|(_, chunk)| chunk)
+            .collect()
+    })
+}
+
+pub fn chunk_count_of(image_id: u64) -> u32 {
+    IMAGE_CHUNKS.with(|chunks| chunks.borrow().range((image_id, 0)..=(image_id, u32::MAX)).count() as u32)
+}
+
+pub fn delete_chunks(image_id: u64) {
+    IMAGE_CHUNKS.with(|chunks| {
+        let mut chunks = chunks.borrow_mut();
+        let keys: Vec<(u64, u32)> = chunks.range((image_id, 0)..=(image_id, u32::MAX)).map(|(key, _)| key).collect();
+        for key in keys {
+            chunks.remove(&key);
+        }
+    });
+}
+
+fn delete_image(land_id: u64, image_id: u64) {
+    IMAGES.with(|images| images.borrow_mut().remove(&(land_id, image_id)));
+    delete_chunks(image_id);
+}
+
+fn discard_upload(upload_id: u64) {
+    IMAGE_UPLOADS.with(|uploads| uploads.borrow_mut().remove(&upload_id));
+    delete_chunks(upload_id);
+}
+
+fn discard_stale_uploads(now: u64) {
+    let stale: Vec<u64> = IMAGE_UPLOADS.with(|uploads| {
+        uploads
+            .borrow()
+            .iter()
+            .filter(|(_, upload)| now > upload.started_at + UPLOAD_TIMEOUT_NS)
+            .map(|(id, _)| id)
+            .collect()
+    });
+
+    for id in stale {
+        discard_upload(id);
+    }
+}
+
+fn is_supported(mime_type: &str) -> bool {
+    matches!(mime_type, "image/png" | "image/jpeg" | "image/gif" | "image/webp")
+}
+
+// Checks the magic bytes so the declared type cannot be used to serve other content
+fn matches_signature(mime_type: &str, bytes: &[u8]) -> bool {
+    match mime_type {
+        "image/png" => bytes.starts_with(b"\x89PNG\r\n\x1a\n"),
+        "image/jpeg" => bytes.starts_with(&[0xff, 0xd8, 0xff]),
+        "image/gif" => bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a"),
+        "image/webp" => bytes.len() >= 12 && bytes.starts_with(b"RIFF") && &bytes[8..12] == b"WEBP",
+        _ => false,
+    }
+}
+
+fn text_response(status_code: u16, message: &str) -> HttpResponse {
+    HttpResponse {
+        status_code,
+        headers: vec![("Content-Type".to_string(), "text/plain".to_string())],
+        body: message.as_bytes().to_vec(),
+    }
+}
@@ -0,0 +1,294 @@
+//! Beneficiaries for parcels whose owner has stopped using their principal.
+//!
+//! Owners name beneficiaries for a single parcel or for their whole portfolio. Every audited
+//! action counts as activity. Once an owner has been inactive for longer than their plan allows, a
+//! timer issues a notice. If the owner still has not returned when the grace period ends, any of
+//! the beneficiaries can claim the parcel.
+
+use candid::{CandidType, Principal};
+use ic_stable_structures::Storable;
+use registry_common::env::{CanisterEnv, Environment};
+use serde::{Serialize, Deserialize as SerdeDeserialize};
+use std::borrow::Cow;
+use std::time::Duration;
+
+use crate::audit::{self, AuditDetails, AuditEventKind};
+use crate::{lands, offers, tax, LandParcel, LandStatus, LandTransfer, TransferKind, INHERITANCE_PLANS, LANDS, LAST_ACTIVITY};
+
+// How often inactive owners are looked for
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const NANOS_PER_DAY: u64 = 86_400_000_000_000;
+const MIN_INACTIVITY_DAYS: u32 = 30;
+const MAX_INACTIVITY_DAYS: u32 = 3_650;
+// Time an owner has to react to a notice before beneficiaries can claim
+pub const GRACE_PERIOD_NS: u64 = 30 * NANOS_PER_DAY;
+const MAX_BENEFICIARIES: usize = 5;
+// Plan key for portfolio-wide plans; land ids start at 1
+pub const PORTFOLIO: u64 = 0;
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone)]
+pub struct InheritancePlan {
+    pub owner: Principal,
+    pub land_id: Option<u64>, // None covers every parcel without a plan of its own
+    pub beneficiaries: Vec<Principal>,
+    pub inactivity_days: u32,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub notice_issued_at: Option<u64>, // Set once the owner has been inactive for too long
+}
+
+impl Storable for InheritancePlan {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize)]
+pub struct InheritancePlanInput {
+    pub land_id: Option<u64>,
+    pub beneficiaries: Vec<Principal>,
+    pub inactivity_days: u32,
+}
+
+pub fn record_activity(principal: Principal, now: u64) {
+    LAST_ACTIVITY.with(|activity| {
+        activity.borrow_mut().insert(principal, now);
+    });
+}
+
+pub fn last_activity(principal: &Principal) -> Option<u64> {
+    LAST_ACTIVITY.with(|activity| activity.borrow().get(principal))
+}
+
+/// Lets an owner show they are still around without changing anything else.
+pub fn check_in(env: &impl Environment) -> Result<u64, String> {
+    let principal = env.caller();
+
+    if principal == Principal::anonymous() {
+        return Err("Anonymous users cannot check in".to_string());
+    }
+
+    let now = env.time();
+    record_activity(principal, now);
+    Ok(now)
+}
+
+pub fn set_plan(env: &impl Environment, input: InheritancePlanInput) -> Result<InheritancePlan, String> {
+    let owner = env.caller();
+
+    if owner == Principal::anonymous() {
+        return Err("Anonymous users cannot name beneficiaries".to_string());
+    }
+
+    validate_input(owner, &input)?;
+
+    if let Some(land_id) = input.land_id {
+        let land = LANDS.with(|lands| lands.borrow().get(&land_id)).ok_or("Land parcel not found")?;
+        if land.owner != owner {
+            return Err("Only the owner can name beneficiaries for a parcel".to_string());
+        }
+
+        lands::ensure_not_frozen(&land)?;
+    }
+
+    let key = (owner, input.land_id.unwrap_or(PORTFOLIO));
+    let now = env.time();
+    let existing = INHERITANCE_PLANS.with(|plans| plans.borrow().get(&key));
+
+    let plan = InheritancePlan {
+        owner,
+        land_id: input.land_id,
+        beneficiaries: input.beneficiaries,
+        inactivity_days: input.inactivity_days,
+        created_at: existing.as_ref().map(|plan| plan.created_at).unwrap_or(now),
+        updated_at: now,
+        notice_issued_at: None,
+    };
+
+    INHERITANCE_PLANS.with(|plans| {
+        plans.borrow_mut().insert(key, plan.clone());
+    });
+
+    audit::record(env, owner, AuditEventKind::InheritancePlanUpdated, AuditDetails {
+        land_id: plan.land_id,
+        before: existing.map(|existing| summarize(&existing)),
+        after: Some(summarize(&plan)),
+        ..Default::default()
+    });
+
+    Ok(plan)
+}
+
+pub fn remove_plan(env: &impl Environment, land_id: Option<u64>) -> Result<InheritancePlan, String> {
+    let owner = env.caller();
+    let key = (owner, land_id.unwrap_or(PORTFOLIO));
+
+    let plan = INHERITANCE_PLANS.with(|plans| plans.borrow_mut().remove(&key))
+        .ok_or("No inheritance plan found")?;
+
+    audit::record(env, owner, AuditEventKind::InheritancePlanRemoved, AuditDetails {
+        land_id,
+        before: Some(summarize(&plan)),
+        ..Default::default()
+    });
+
+    Ok(plan)
+}
+
+/// The owner's plans, portfolio plan first.
+pub fn plans_of(owner: Principal) -> Vec<InheritancePlan> {
+    INHERITANCE_PLANS.with(|plans| {
+        plans
+            .borrow()
+            .range((owner, PORTFOLIO)..=(owner, u64::MAX))
+            .map(|(_, plan)| plan)
+            .collect()
+    })
+}
+
+/// Transfers a parcel to the calling beneficiary once the owner's grace period has run out.
+pub fn claim(env: &impl Environment, land_id: u64) -> Result<LandParcel, String> {
+    let beneficiary = env.caller();
+    let now = env.time();
+
+    let mut land = LANDS.with(|lands| lands.borrow().get(&land_id)).ok_or("Land parcel not found")?;
+    let plan = plan_for(&land).ok_or("No inheritance plan covers this parcel")?;
+    lands::ensure_not_frozen(&land)?;
+    tax::ensure_not_reclaiming(land_id)?;
+
+    if !plan.beneficiaries.contains(&beneficiary) {
+        return Err("Only a named beneficiary can claim this parcel".to_string());
+    }
+
+    let issued_at = plan.notice_issued_at.ok_or("The owner has not been inactive long enough")?;
+    if owner_last_seen(&plan) >= issued_at {
+        return Err("The owner has been active since the inactivity notice".to_string());
+    }
+
+    let claimable_at = issued_at + GRACE_PERIOD_NS;
+    if now < claimable_at {
+        return Err(format!("The grace period runs until {}", claimable_at));
+    }
+
+    let before = audit::summarize_land(&land);
+    let owner = land.owner;
+    land.history.push(LandTransfer {
+        from: owner,
+        to: beneficiary,
+        timestamp: now,
+        verified_by: land.verified_by,
+        sale: None,
+        transfer_kind: Some(TransferKind::Inheritance),
+    });
+    land.owner = beneficiary;
+    if matches!(land.status, LandStatus::ForSale) {
+        lands::end_listing(&mut land);
+    }
+    land.updated_at = now;
+
+    LANDS.with(|lands| {
+        lands.borrow_mut().insert(land_id, land.clone());
+    });
+    offers::cancel_on_transfer(env, land_id);
+
+    // A portfolio plan stays in place for the owner's remaining parcels
+    if plan.land_id.is_some() {
+        INHERITANCE_PLANS.with(|plans| plans.borrow_mut().remove(&(owner, land_id)));
+    }
+
+    audit::record(env, beneficiary, AuditEventKind::InheritanceClaimed, AuditDetails {
+        land_id: Some(land_id),
+        subject: Some(owner),
+        before: Some(before),
+        after: Some(audit::summarize_land(&land)),
+        ..Default::default()
+    });
+
+    Ok(land)
+}
+
+pub fn start_inactivity_timer() {
+    ic_cdk_timers::set_timer_interval(CHECK_INTERVAL, || issue_notices(&CanisterEnv));
+}
+
+/// Issues notices to owners who have been inactive past their threshold, withdraws notices from
+/// owners who have since returned and drops parcel plans whose parcel has changed hands.
+pub fn issue_notices(env: &impl Environment) {
+    let now = env.time();
+    let plans: Vec<((Principal, u64), InheritancePlan)> = INHERITANCE_PLANS.with(|plans| plans.borrow().iter().collect());
+
+    for (key, mut plan) in plans {
+        if let Some(land_id) = plan.land_id {
+            let still_owned = LANDS.with(|lands| lands.borrow().get(&land_id))
+                .is_some_and(|land| land.owner == plan.owner);
+            if !still_owned {
+                INHERITANCE_PLANS.with(|plans| plans.borrow_mut().remove(&key));
+                continue;
+            }
+        }
+
+        let last_seen = owner_last_seen(&plan);
+        match plan.notice_issued_at {
+            Some(issued_at) if last_seen >= issued_at => plan.notice_issued_at = None,
+            None if now.saturating_sub(last_seen) >= plan.inactivity_days as u64 * NANOS_PER_DAY => {
+                plan.notice_issued_at = Some(now);
+
+                audit::record(env, env.canister_id(), AuditEventKind::InheritanceNoticeIssued, AuditDetails {
+                    land_id: plan.land_id,
+                    subject: Some(plan.owner),
+                    after: Some(format!("claimable_after={}", now + GRACE_PERIOD_NS)),
+                    ..Default::default()
+                });
+            },
+            _ => continue,
+        }
+
+        INHERITANCE_PLANS.with(|plans| plans.borrow_mut().insert(key, plan));
+    }
+}
+
+// A parcel plan takes precedence over the owner's portfolio plan
+fn plan_for(land: &LandParcel) -> Option<InheritancePlan> {
+    INHERITANCE_PLANS.with(|plans| {
+        let plans = plans.borrow();
+        plans.get(&(land.owner, land.id)).or_else(|| plans.get(&(land.owner, PORTFOLIO)))
+    })
+}
+
+// Setting up the plan counts as activity even if it predates activity tracking
+fn owner_last_seen(plan: &InheritancePlan) -> u64 {
+    last_activity(&plan.owner).unwrap_or(0).max(plan.updated_at)
+}
+
+fn validate_input(owner: Principal, input: &InheritancePlanInput) -> Result<(), String> {
+    if input.beneficiaries.is_empty() || input.beneficiaries.len() > MAX_BENEFICIARIES {
+        return Err(format!("Name between 1 and {} beneficiaries", MAX_BENEFICIARIES));
+    }
+
+    for (index, beneficiary) in input.beneficiaries.iter().enumerate() {
+        if *beneficiary == owner || *beneficiary == Principal::anonymous() {
+            return Err("Beneficiaries must be other, non-anonymous principals".to_string());
+        }
+
+        if input.beneficiaries[..index].contains(beneficiary) {
+            return Err("Beneficiaries must not be listed twice".to_string());
+        }
+    }
+
+    if !(MIN_INACTIVITY_DAYS..=MAX_INACTIVITY_DAYS).contains(&input.inactivity_days) {
+        return Err(format!("Inactivity period must be between {} and {} days", MIN_INACTIVITY_DAYS, MAX_INACTIVITY_DAYS));
+    }
+
+    Ok(())
+}
+
+fn summarize(plan: &InheritancePlan) -> String {
+    let beneficiaries: Vec<String> = plan.beneficiaries.iter().map(|beneficiary| beneficiary.to_text()).collect();
+    format!("beneficiaries=[{}] inactivity_days={}", beneficiaries.join(", "), plan.inactivity_days)
+}
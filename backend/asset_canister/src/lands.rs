@@ -0,0 +1,548 @@
+//! Parcel lifecycle: registration, verification, listing, sale and transfer.
+//!
+//! Every function takes the calling environment explicitly, so the flows can be exercised
+//! natively in tests. The `#[update]` endpoints in `lib.rs` only forward to these.
+
+use candid::Principal;
+use registry_common::env::{CanisterEnv, Environment};
+use std::time::Duration;
+
+use crate::audit::{self, AuditDetails, AuditEventKind};
+use crate::sales::{self, MarketplaceFee, RoyaltyConfig};
+use crate::{get_next_land_id, is_admin, is_verifier, offers, pricing, revisions, tax, treasury, validate_land_input};
+
+use crate::{FreezeInfo, LandInput, LandParcel, LandStatus, LandTransfer, TransferKind, LANDS};
+
+const MAX_FREEZE_REASON_LEN: usize = 1_024;
+const MAX_LISTING_DURATION_NS: u64 = 365 * 86_400_000_000_000;
+// How often expired listings are taken off the market
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+pub fn register_land(env: &impl Environment, land_input: LandInput) -> Result<LandParcel, String> {
+    let principal = env.caller();
+
+    if principal == Principal::anonymous() {
+        return Err("Anonymous users cannot register land".to_string());
+    }
+
+    validate_land_input(&land_input)?;
+    if let Some(royalty) = &land_input.royalty {
+        sales::validate_royalty(royalty)?;
+    }
+
+    let fee = treasury::fee_policy().registration_fee;
+    treasury::check_can_pay(principal, fee)?;
+
+    let land_id = get_next_land_id();
+    let current_time = env.time();
+    treasury::charge_registration_fee(env, principal, land_id, fee)?;
+
+    let land_parcel = LandParcel {
+        id: land_id,
+        owner: principal,
+        coordinates: land_input.coordinates,
+        size: land_input.size,
+        description: land_input.description,
+        status: LandStatus::Pending,
+        verified_by: None,
+        created_at: current_time,
+        updated_at: current_time,
+        history: vec![LandTransfer {
+            from: Principal::anonymous(), // Initial registration
+            to: principal,
+            timestamp: current_time,
+            verified_by: None,
+            sale: None,
+            transfer_kind: Some(TransferKind::Registration),
+        }],
+        price: land_input.price,
+        metadata: land_input.metadata,
+        preview_image_url: None,
+        registration_fee: (fee > 0).then_some(fee),
+        royalty: land_input.royalty,
+        freeze: None,
+        listing_expires_at: None,
+    };
+
+    LANDS.with(|lands| {
+        let mut lands = lands.borrow_mut();
+        lands.insert(land_id, land_parcel.clone());
+    });
+
+    audit::record(env, principal, AuditEventKind::LandRegistered, AuditDetails {
+        land_id: Some(land_id),
+        amount: (fee > 0).then_some(fee),
+        after: Some(audit::summarize_land(&land_parcel)),
+        ..Default::default()
+    });
+
+    Ok(land_parcel)
+}
+
+pub fn verify_land(env: &impl Environment, land_id: u64) -> Result<LandParcel, String> {
+    let principal = env.caller();
+
+    if !is_verifier(&principal) {
+        return Err("Only verifiers can verify land parcels".to_string());
+    }
+
+    LANDS.with(|lands| {
+        let mut lands = lands.borrow_mut();
+
+        match lands.get(&land_id) {
+            Some(mut land) => {
+                if !matches!(land.status, LandStatus::Pending) {
+                    return Err("Land is not pending verification".to_string());
+                }
+
+                let before = audit::summarize_land(&land);
+                land.status = LandStatus::Verified;
+                land.verified_by = Some(principal);
+                land.updated_at = env.time();
+                lands.insert(land_id, land.clone());
+
+                audit::record(env, principal, AuditEventKind::LandVerified, AuditDetails {
+                    land_id: Some(land_id),
+                    subject: Some(land.owner),
+                    before: Some(before),
+                    after: Some(audit::summarize_land(&land)),
+                    ..Default::default()
+                });
+                Ok(land)
+            },
+            None => Err("Land parcel not found".to_string()),
+        }
+    })
+}
+
+pub fn reject_land_verification(env: &impl Environment, land_id: u64) -> Result<LandParcel, String> {
+    let principal = env.caller();
+
+    if !is_verifier(&principal) {
+        return Err("Only verifiers can reject land verification".to_string());
+    }
+
+    LANDS.with(|lands| {
+        let mut lands = lands.borrow_mut();
+
+        match lands.get(&land_id) {
+            Some(mut land) => {
+                if !matches!(land.status, LandStatus::Pending) {
+                    return Err("Land is not pending verification".to_string());
+                }
+
+                let before = audit::summarize_land(&land);
+                land.status = LandStatus::Rejected;
+                land.verified_by = Some(principal);
+                land.updated_at = env.time();
+                lands.insert(land_id, land.clone());
+
+                let refunded = match land.registration_fee {
+                    Some(fee) if !revisions::was_resubmitted(land_id) => {
+                        treasury::refund_registration_fee(env, land.owner, land_id, fee)
+                    },
+                    _ => 0,
+                };
+
+                audit::record(env, principal, AuditEventKind::LandRejected, AuditDetails {
+                    land_id: Some(land_id),
+                    subject: Some(land.owner),
+                    amount: (refunded > 0).then_some(refunded),
+                    before: Some(before),
+                    after: Some(audit::summarize_land(&land)),
+                });
+                Ok(land)
+            },
+            None => Err("Land parcel not found".to_string()),
+        }
+    })
+}
+
+pub fn set_land_for_sale(env: &impl Environment, land_id: u64, price: u64, expires_at: Option<u64>) -> Result<LandParcel, String> {
+    let principal = env.caller();
+
+    if price == 0 {
+        return Err("Price must be greater than zero".to_string());
+    }
+
+    if let Some(expires_at) = expires_at {
+        let now = env.time();
+        if expires_at <= now || expires_at - now > MAX_LISTING_DURATION_NS {
+            return Err("Listing expiry must be in the future and at most a year away".to_string());
+        }
+    }
+
+    LANDS.with(|lands| {
+        let mut lands = lands.borrow_mut();
+
+        match lands.get(&land_id) {
+            Some(mut land) => {
+                if land.owner != principal {
+                    return Err("Only the owner can set land for sale".to_string());
+                }
+
+                ensure_not_frozen(&land)?;
+
+                if !matches!(land.status, LandStatus::Verified) {
+                    return Err("Only verified land can be put for sale".to_string());
+                }
+
+                let before = audit::summarize_land(&land);
+                land.status = LandStatus::ForSale;
+                land.price = Some(price);
+                land.listing_expires_at = expires_at;
+                land.updated_at = env.time();
+                lands.insert(land_id, land.clone());
+                pricing::record_listing(env, &land, price);
+
+                audit::record(env, principal, AuditEventKind::LandListedForSale, AuditDetails {
+                    land_id: Some(land_id),
+                    amount: Some(price),
+                    before: Some(before),
+                    after: Some(audit::summarize_land(&land)),
+                    ..Default::default()
+                });
+                Ok(land)
+            },
+            None => Err("Land parcel not found".to_string()),
+        }
+    })
+}
+
+/// Takes the parcel off the market again.
+pub fn cancel_land_sale(env: &impl Environment, land_id: u64) -> Result<LandParcel, String> {
+    let principal = env.caller();
+    let mut land = owned_listing(principal, land_id)?;
+
+    let before = audit::summarize_land(&land);
+    end_listing(&mut land);
+    land.updated_at = env.time();
+    LANDS.with(|lands| {
+        lands.borrow_mut().insert(land_id, land.clone());
+    });
+
+    audit::record(env, principal, AuditEventKind::LandSaleCancelled, AuditDetails {
+        land_id: Some(land_id),
+        before: Some(before),
+        after: Some(audit::summarize_land(&land)),
+        ..Default::default()
+    });
+
+    Ok(land)
+}
+
+/// Changes the asking price of a listed parcel. The listing keeps its expiry.
+pub fn update_land_price(env: &impl Environment, land_id: u64, price: u64) -> Result<LandParcel, String> {
+    let principal = env.caller();
+
+    if price == 0 {
+        return Err("Price must be greater than zero".to_string());
+    }
+
+    let mut land = owned_listing(principal, land_id)?;
+
+    let before = audit::summarize_land(&land);
+    land.price = Some(price);
+    land.updated_at = env.time();
+    LANDS.with(|lands| {
+        lands.borrow_mut().insert(land_id, land.clone());
+    });
+    pricing::record_listing(env, &land, price);
+
+    audit::record(env, principal, AuditEventKind::LandPriceUpdated, AuditDetails {
+        land_id: Some(land_id),
+        amount: Some(price),
+        before: Some(before),
+        after: Some(audit::summarize_land(&land)),
+        ..Default::default()
+    });
+
+    Ok(land)
+}
+
+pub fn start_expiry_timer() {
+    ic_cdk_timers::set_timer_interval(EXPIRY_INTERVAL, || expire_listings(&CanisterEnv));
+}
+
+/// Takes listings whose expiry has passed off the market.
+pub fn expire_listings(env: &impl Environment) {
+    let now = env.time();
+    let expired: Vec<LandParcel> = LANDS.with(|lands| {
+        lands
+            .borrow()
+            .iter()
+            .map(|(_, land)| land)
+            .filter(|land| matches!(land.status, LandStatus::ForSale))
+            .filter(|land| land.listing_expires_at.is_some_and(|expires_at| expires_at <= now))
+            .collect()
+    });
+
+    for mut land in expired {
+        let before = audit::summarize_land(&land);
+        end_listing(&mut land);
+        land.updated_at = now;
+        LANDS.with(|lands| {
+            lands.borrow_mut().insert(land.id, land.clone());
+        });
+
+        audit::record(env, env.canister_id(), AuditEventKind::LandListingExpired, AuditDetails {
+            land_id: Some(land.id),
+            subject: Some(land.owner),
+            before: Some(before),
+            after: Some(audit::summarize_land(&land)),
+            ..Default::default()
+        });
+    }
+}
+
+/// Returns a listed parcel to `Verified` and clears its price and expiry.
+pub fn end_listing(land: &mut LandParcel) {
+    land.status = LandStatus::Verified;
+    land.price = None;
+    land.listing_expires_at = None;
+}
+
+// A parcel the caller has listed and may still change. Reclamation sales belong to the registry.
+fn owned_listing(principal: Principal, land_id: u64) -> Result<LandParcel, String> {
+    let land = LANDS.with(|lands| lands.borrow().get(&land_id)).ok_or("Land parcel not found")?;
+
+    if land.owner != principal {
+        return Err("Only the owner can change a listing".to_string());
+    }
+
+    if !matches!(land.status, LandStatus::ForSale) {
+        return Err("Land is not for sale".to_string());
+    }
+
+    ensure_not_frozen(&land)?;
+    tax::ensure_not_reclaiming(land_id)?;
+    Ok(land)
+}
+
+pub fn transfer_ownership(env: &impl Environment, land_id: u64, new_owner: Principal) -> Result<LandParcel, String> {
+    let principal = env.caller();
+
+    LANDS.with(|lands| {
+        let mut lands = lands.borrow_mut();
+
+        match lands.get(&land_id) {
+            Some(mut land) => {
+                if land.owner != principal {
+                    return Err("Only the owner can transfer ownership".to_string());
+                }
+
+                ensure_not_frozen(&land)?;
+                tax::ensure_not_reclaiming(land_id)?;
+
+                let transfer = LandTransfer {
+                    from: principal,
+                    to: new_owner,
+                    timestamp: env.time(),
+                    verified_by: land.verified_by,
+                    sale: None,
+                    transfer_kind: Some(TransferKind::Transfer),
+                };
+
+                let before = audit::summarize_land(&land);
+                land.owner = new_owner;
+                end_listing(&mut land); // Reset to verified after transfer
+                land.updated_at = env.time();
+                land.history.push(transfer);
+
+                lands.insert(land_id, land.clone());
+                offers::cancel_on_transfer(env, land_id);
+
+                audit::record(env, principal, AuditEventKind::OwnershipTransferred, AuditDetails {
+                    land_id: Some(land_id),
+                    subject: Some(new_owner),
+                    before: Some(before),
+                    after: Some(audit::summarize_land(&land)),
+                    ..Default::default()
+                });
+                Ok(land)
+            },
+            None => Err("Land parcel not found".to_string()),
+        }
+    })
+}
+
+pub fn buy_land(env: &impl Environment, land_id: u64) -> Result<LandParcel, String> {
+    let buyer = env.caller();
+
+    if buyer == Principal::anonymous() {
+        return Err("Anonymous users cannot buy land".to_string());
+    }
+
+    let land = LANDS.with(|lands| lands.borrow().get(&land_id)).ok_or("Land parcel not found")?;
+
+    if !matches!(land.status, LandStatus::ForSale) {
+        return Err("Land is not for sale".to_string());
+    }
+
+    ensure_not_frozen(&land)?;
+
+    if land.owner == buyer {
+        return Err("You cannot buy your own land".to_string());
+    }
+
+    if land.listing_expires_at.is_some_and(|expires_at| expires_at <= env.time()) {
+        return Err("Listing has expired".to_string());
+    }
+
+    let price = land.price.ok_or("Price not set for this land")?;
+    complete_sale(env, land, buyer, price, None)
+}
+
+/// Pays for the parcel out of the buyer's wallet and hands it over. Shared by every way of buying a
+/// parcel; callers check that the sale is allowed.
+pub fn complete_sale(env: &impl Environment, mut land: LandParcel, buyer: Principal, price: u64, fee: Option<&MarketplaceFee>) -> Result<LandParcel, String> {
+    let land_id = land.id;
+    let seller = land.owner;
+    let settlement = sales::settle_sale(env, buyer, seller, land_id, price, land.royalty.as_ref(), fee)?;
+    tax::settle_on_sale(env, land_id, seller);
+
+    // Transfer ownership
+    let transfer = LandTransfer {
+        from: seller,
+        to: buyer,
+        timestamp: env.time(),
+        verified_by: land.verified_by,
+        sale: Some(settlement.clone()),
+        transfer_kind: Some(TransferKind::Sale),
+    };
+
+    let before = audit::summarize_land(&land);
+    land.owner = buyer;
+    end_listing(&mut land); // Change back to verified after purchase
+    land.updated_at = env.time();
+    land.history.push(transfer);
+
+    LANDS.with(|lands| {
+        lands.borrow_mut().insert(land_id, land.clone());
+    });
+    offers::cancel_on_transfer(env, land_id);
+    pricing::record_sale(env, &land, price);
+
+    audit::record(env, buyer, AuditEventKind::LandPurchased, AuditDetails {
+        land_id: Some(land_id),
+        subject: Some(seller),
+        amount: Some(price),
+        before: Some(before),
+        after: Some(format!("{} royalty={}", audit::summarize_land(&land), settlement.royalty_amount)),
+    });
+    Ok(land)
+}
+
+pub fn set_land_royalty(env: &impl Environment, land_id: u64, royalty: Option<RoyaltyConfig>) -> Result<LandParcel, String> {
+    let principal = env.caller();
+
+    if !is_admin(env, &principal) {
+        return Err("Only admins can change land royalties".to_string());
+    }
+
+    if let Some(royalty) = &royalty {
+        sales::validate_royalty(royalty)?;
+    }
+
+    LANDS.with(|lands| {
+        let mut lands = lands.borrow_mut();
+
+        match lands.get(&land_id) {
+            Some(mut land) => {
+                let before = format!("{:?}", land.royalty);
+                land.royalty = royalty;
+                land.updated_at = env.time();
+                lands.insert(land_id, land.clone());
+
+                audit::record(env, principal, AuditEventKind::RoyaltyUpdated, AuditDetails {
+                    land_id: Some(land_id),
+                    subject: land.royalty.as_ref().map(|royalty| royalty.recipient),
+                    before: Some(before),
+                    after: Some(format!("{:?}", land.royalty)),
+                    ..Default::default()
+                });
+                Ok(land)
+            },
+            None => Err("Land parcel not found".to_string()),
+        }
+    })
+}
+
+/// Places a legal hold on a parcel. Frozen parcels cannot be listed, sold, transferred or inherited.
+pub fn freeze_land(env: &impl Environment, land_id: u64, reason: String) -> Result<LandParcel, String> {
+    let principal = env.caller();
+
+    if !is_admin(env, &principal) && !is_verifier(&principal) {
+        return Err("Only admins and verifiers can freeze land parcels".to_string());
+    }
+
+    let reason = reason.trim().to_string();
+    if reason.is_empty() || reason.len() > MAX_FREEZE_REASON_LEN {
+        return Err(format!("A reason of at most {} bytes is required", MAX_FREEZE_REASON_LEN));
+    }
+
+    LANDS.with(|lands| {
+        let mut lands = lands.borrow_mut();
+
+        match lands.get(&land_id) {
+            Some(mut land) => {
+                if land.freeze.is_some() {
+                    return Err("Land parcel is already frozen".to_string());
+                }
+
+                land.freeze = Some(FreezeInfo {
+                    reason: reason.clone(),
+                    authority: principal,
+                    frozen_at: env.time(),
+                });
+                land.updated_at = env.time();
+                lands.insert(land_id, land.clone());
+
+                audit::record(env, principal, AuditEventKind::LandFrozen, AuditDetails {
+                    land_id: Some(land_id),
+                    subject: Some(land.owner),
+                    after: Some(format!("reason={}", reason)),
+                    ..Default::default()
+                });
+                Ok(land)
+            },
+            None => Err("Land parcel not found".to_string()),
+        }
+    })
+}
+
+pub fn unfreeze_land(env: &impl Environment, land_id: u64) -> Result<LandParcel, String> {
+    let principal = env.caller();
+
+    if !is_admin(env, &principal) && !is_verifier(&principal) {
+        return Err("Only admins and verifiers can unfreeze land parcels".to_string());
+    }
+
+    LANDS.with(|lands| {
+        let mut lands = lands.borrow_mut();
+
+        match lands.get(&land_id) {
+            Some(mut land) => {
+                let freeze = land.freeze.take().ok_or("Land parcel is not frozen")?;
+                land.updated_at = env.time();
+                lands.insert(land_id, land.clone());
+
+                audit::record(env, principal, AuditEventKind::LandUnfrozen, AuditDetails {
+                    land_id: Some(land_id),
+                    subject: Some(land.owner),
+                    before: Some(format!("reason={} authority={}", freeze.reason, freeze.authority)),
+                    ..Default::default()
+                });
+                Ok(land)
+            },
+            None => Err("Land parcel not found".to_string()),
+        }
+    })
+}
+
+pub fn ensure_not_frozen(land: &LandParcel) -> Result<(), String> {
+    match &land.freeze {
+        Some(freeze) => Err(format!("Land parcel is frozen: {}", freeze.reason)),
+        None => Ok(()),
+    }
+}
@@ -1,17 +1,74 @@
 use candid::{CandidType, Principal};
-use ic_cdk::api::time;
-use ic_cdk::{caller, query, update};
+use ic_cdk::{init, inspect_message, post_upgrade, query, update};
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
 use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap, Storable};
+use registry_common::env::{CanisterEnv, Environment};
+use registry_common::snapshot::SnapshotManifest;
+use registry_common::title::TitleCertificate;
+use registry_common::validation::{self, LandFields, ValidationLimits};
 use serde::{Serialize, Deserialize as SerdeDeserialize};
 use std::cell::RefCell;
 use std::borrow::Cow;
 
+mod admin;
+mod audit;
+mod config;
+#[cfg(feature = "demo")]
+mod demo;
+mod escrow;
+mod images;
+mod inheritance;
+mod lands;
+mod marketplace;
+mod offers;
+mod pricing;
+mod revisions;
+mod sales;
+mod snapshot;
+mod subscriptions;
+mod tax;
+#[cfg(test)]
+mod tests;
+mod titles;
+mod treasury;
+mod wallets;
+
+use audit::{AuditEvent, AuditEventPage, AuditFilter};
+use escrow::EscrowHold;
+use images::{HttpRequest, HttpResponse, ImageInfo, ImageUpload, ImageUploadInput};
+use inheritance::{InheritancePlan, InheritancePlanInput};
+use offers::Offer;
+use pricing::{MarketFilter, MarketStats, PricePoint, ValuationEstimate};
+use revisions::{LandDetailsUpdate, LandRevision};
+use sales::{MarketplaceFee, RoyaltyConfig, SaleSettlement};
+use snapshot::SnapshotImage;
+use subscriptions::{Subscription, SubscriptionInput};
+use tax::{TaxAccount, TaxPolicy, TaxStanding};
+use treasury::{FeePolicy, TreasuryEntry, TreasuryLedgerPage};
+use wallets::{WalletTransaction, WalletTransactionPage};
+
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 type LandStore = StableBTreeMap<u64, LandParcel, Memory>;
-type LandIdCounter = StableBTreeMap<u8, u64, Memory>;
+type LandIdCounter = StableBTreeMap<u8, u64, Memory>; // Id counters: 0 for lands, 1 for images
 type VerifierStore = StableBTreeMap<Principal, bool, Memory>;
 type WalletStore = StableBTreeMap<Principal, u64, Memory>; // User wallet balances in e8s
+type AuditLog = StableBTreeMap<u64, AuditEvent, Memory>; // Append-only, keyed by event id
+type SubscriptionStore = StableBTreeMap<u64, Subscription, Memory>;
+type ConfigStore = StableBTreeMap<String, String, Memory>;
+type TreasuryLedger = StableBTreeMap<u64, TreasuryEntry, Memory>; // Append-only, keyed by entry id
+type PriceHistory = StableBTreeMap<(u64, u64), PricePoint, Memory>; // Keyed by (land id, sequence)
+type WalletTransactionStore = StableBTreeMap<(Principal, u64), WalletTransaction, Memory>; // Keyed by (owner, sequence)
+type InheritancePlanStore = StableBTreeMap<(Principal, u64), InheritancePlan, Memory>; // Keyed by (owner, land id or 0 for the portfolio)
+type ActivityStore = StableBTreeMap<Principal, u64, Memory>; // Time of each principal's last audited action
+type TaxAccountStore = StableBTreeMap<u64, TaxAccount, Memory>; // Keyed by land id
+type ImageStore = StableBTreeMap<(u64, u64), ImageInfo, Memory>; // Keyed by (land id, image id)
+type ImageChunkStore = StableBTreeMap<(u64, u32), Vec<u8>, Memory>; // Keyed by (image id, chunk index)
+type ImageUploadStore = StableBTreeMap<u64, ImageUpload, Memory>; // Unfinished uploads, keyed by image id
+type RevisionStore = StableBTreeMap<(u64, u64), LandRevision, Memory>; // Keyed by (land id, revision)
+type OfferStore = StableBTreeMap<u64, Offer, Memory>;
+type EscrowStore = StableBTreeMap<u64, EscrowHold, Memory>; // Outstanding holds only
+#[cfg(feature = "demo")]
+type FaucetUsageStore = StableBTreeMap<Principal, (u64, u64), Memory>; // (day, amount claimed that day)
 
 #[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug)]
 pub enum LandStatus {
@@ -22,12 +79,22 @@ pub enum LandStatus {
     Sold,
 }
 
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug, PartialEq)]
+pub enum TransferKind {
+    Registration,
+    Transfer,
+    Sale,
+    Inheritance,
+}
+
 #[derive(CandidType, Serialize, SerdeDeserialize, Clone)]
 pub struct LandTransfer {
     pub from: Principal,
     pub to: Principal,
     pub timestamp: u64,
     pub verified_by: Option<Principal>,
+    pub sale: Option<SaleSettlement>, // Set when the transfer was paid for
+    pub transfer_kind: Option<TransferKind>, // Missing on transfers recorded before kinds were tracked
 }
 
 #[derive(CandidType, Serialize, SerdeDeserialize, Clone)]
@@ -45,6 +112,17 @@ pub struct LandParcel {
     pub price: Option<u64>, // Optional price if for sale
     pub metadata: String, // Additional metadata like zoning, usage rights
     pub preview_image_url: Option<String>,
+    pub registration_fee: Option<u64>, // Fee paid at registration, basis for rejection refunds
+    pub royalty: Option<RoyaltyConfig>, // Paid out of every secondary sale
+    pub freeze: Option<FreezeInfo>, // Set while a legal hold blocks all owner actions
+    pub listing_expires_at: Option<u64>, // Listings past this time are taken off the market
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug)]
+pub struct FreezeInfo {
+    pub reason: String,
+    pub authority: Principal, // Admin or verifier who placed the hold
+    pub frozen_at: u64,
 }
 
 impl Storable for LandParcel {
@@ -78,6 +156,7 @@ pub struct LandInput {
     pub description: String,
     pub metadata: String,
     pub price: Option<u64>,
+    pub royalty: Option<RoyaltyConfig>,
 }
 
 #[derive(CandidType, Serialize, SerdeDeserialize, Clone)]
@@ -113,6 +192,150 @@ thread_local! {
             MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3))),
         )
     );
+
+    static AUDIT_LOG: RefCell<AuditLog> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4))),
+        )
+    );
+
+    static SUBSCRIPTIONS: RefCell<SubscriptionStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(5))),
+        )
+    );
+
+    static CONFIG: RefCell<ConfigStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(6))),
+        )
+    );
+
+    static TREASURY_LEDGER: RefCell<TreasuryLedger> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(7))),
+        )
+    );
+
+    static PRICE_HISTORY: RefCell<PriceHistory> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(8))),
+        )
+    );
+
+    static WALLET_TRANSACTIONS: RefCell<WalletTransactionStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(9))),
+        )
+    );
+
+    static INHERITANCE_PLANS: RefCell<InheritancePlanStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(11))),
+        )
+    );
+
+    static LAST_ACTIVITY: RefCell<ActivityStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(12))),
+        )
+    );
+
+    static TAX_ACCOUNTS: RefCell<TaxAccountStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(13))),
+        )
+    );
+
+    static IMAGES: RefCell<ImageStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(14))),
+        )
+    );
+
+    static IMAGE_CHUNKS: RefCell<ImageChunkStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(15))),
+        )
+    );
+
+    static IMAGE_UPLOADS: RefCell<ImageUploadStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(16))),
+        )
+    );
+
+    static LAND_REVISIONS: RefCell<RevisionStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(17))),
+        )
+    );
+
+    static OFFERS: RefCell<OfferStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(18))),
+        )
+    );
+
+    static ESCROW_HOLDS: RefCell<EscrowStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(19))),
+        )
+    );
+
+    #[cfg(feature = "demo")]
+    static FAUCET_USAGE: RefCell<FaucetUsageStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(10))),
+        )
+    );
+}
+
+#[init]
+fn init() {
+    start_timers();
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    // Timers do not survive upgrades and have to be re-armed
+    start_timers();
+}
+
+fn start_timers() {
+    subscriptions::start_delivery_timer();
+    inheritance::start_inactivity_timer();
+    tax::start_assessment_timer();
+    lands::start_expiry_timer();
+    offers::start_expiry_timer();
+}
+
+// Drop clearly invalid ingress before it is executed and charged for.
+// Inter-canister calls skip this hook, so the endpoints validate again.
+#[inspect_message]
+fn inspect_message() {
+    let method = ic_cdk::api::call::method_name();
+
+    if method == "register_land" {
+        let (land_input,): (LandInput,) = ic_cdk::api::call::arg_data(Default::default());
+        if validate_land_input(&land_input).is_err() {
+            return;
+        }
+    }
+
+    ic_cdk::api::call::accept_message();
+}
+
+fn validate_land_input(land_input: &LandInput) -> Result<(), String> {
+    let fields = LandFields {
+        coordinates: &land_input.coordinates,
+        size: land_input.size,
+        description: &land_input.description,
+        metadata: &land_input.metadata,
+    };
+
+    validation::validate_land(&fields, &config::validation_limits())?;
+    Ok(())
 }
 
 fn get_next_land_id() -> u64 {
@@ -125,6 +348,10 @@ fn get_next_land_id() -> u64 {
     })
 }
 
+fn is_admin(env: &impl Environment, principal: &Principal) -> bool {
+    env.is_controller(principal)
+}
+
 fn is_verifier(principal: &Principal) -> bool {
     VERIFIERS.with(|verifiers| {
         verifiers.borrow().get(principal).unwrap_or(false)
@@ -133,42 +360,7 @@ fn is_verifier(principal: &Principal) -> bool {
 
 #[update]
 fn register_land(land_input: LandInput) -> Result<LandParcel, String> {
-    let principal = caller();
-    
-    if principal == Principal::anonymous() {
-        return Err("Anonymous users cannot register land".to_string());
-    }
-
-    let land_id = get_next_land_id();
-    let current_time = time();
-
-    let land_parcel = LandParcel {
-        id: land_id,
-        owner: principal,
-        coordinates: land_input.coordinates,
-        size: land_input.size,
-        description: land_input.description,
-        status: LandStatus::Pending,
-        verified_by: None,
-        created_at: current_time,
-        updated_at: current_time,
-        history: vec![LandTransfer {
-            from: Principal::anonymous(), // Initial registration
-            to: principal,
-            timestamp: current_time,
-            verified_by: None,
-        }],
-        price: land_input.price,
-        metadata: land_input.metadata,
-        preview_image_url: None,
-    };
-
-    LANDS.with(|lands| {
-        let mut lands = lands.borrow_mut();
-        lands.insert(land_id, land_parcel.clone());
-    });
-
-    Ok(land_parcel)
+    lands::register_land(&CanisterEnv, land_input)
 }
 
 #[query]
@@ -227,146 +419,75 @@ fn get_pending_verification_lands() -> Vec<LandParcel> {
 
 #[update]
 fn verify_land(land_id: u64) -> Result<LandParcel, String> {
-    let principal = caller();
-    
-    if !is_verifier(&principal) {
-        return Err("Only verifiers can verify land parcels".to_string());
-    }
-
-    LANDS.with(|lands| {
-        let mut lands = lands.borrow_mut();
-        
-        match lands.get(&land_id) {
-            Some(mut land) => {
-                if !matches!(land.status, LandStatus::Pending) {
-                    return Err("Land is not pending verification".to_string());
-                }
-                
-                land.status = LandStatus::Verified;
-                land.verified_by = Some(principal);
-                land.updated_at = time();
-                lands.insert(land_id, land.clone());
-                Ok(land)
-            },
-            None => Err("Land parcel not found".to_string()),
-        }
-    })
+    lands::verify_land(&CanisterEnv, land_id)
 }
 
 #[update]
 fn reject_land_verification(land_id: u64) -> Result<LandParcel, String> {
-    let principal = caller();
-    
-    if !is_verifier(&principal) {
-        return Err("Only verifiers can reject land verification".to_string());
-    }
+    lands::reject_land_verification(&CanisterEnv, land_id)
+}
 
-    LANDS.with(|lands| {
-        let mut lands = lands.borrow_mut();
-        
-        match lands.get(&land_id) {
-            Some(mut land) => {
-                if !matches!(land.status, LandStatus::Pending) {
-                    return Err("Land is not pending verification".to_string());
-                }
-                
-                land.status = LandStatus::Rejected;
-                land.verified_by = Some(principal);
-                land.updated_at = time();
-                lands.insert(land_id, land.clone());
-                Ok(land)
-            },
-            None => Err("Land parcel not found".to_string()),
-        }
-    })
+#[update]
+fn set_land_for_sale(land_id: u64, price: u64, expires_at: Option<u64>) -> Result<LandParcel, String> {
+    lands::set_land_for_sale(&CanisterEnv, land_id, price, expires_at)
 }
 
 #[update]
-fn set_land_for_sale(land_id: u64, price: u64) -> Result<LandParcel, String> {
-    let principal = caller();
-    
-    LANDS.with(|lands| {
-        let mut lands = lands.borrow_mut();
-        
-        match lands.get(&land_id) {
-            Some(mut land) => {
-                if land.owner != principal {
-                    return Err("Only the owner can set land for sale".to_string());
-                }
-                
-                if !matches!(land.status, LandStatus::Verified) {
-                    return Err("Only verified land can be put for sale".to_string());
-                }
-                
-                land.status = LandStatus::ForSale;
-                land.price = Some(price);
-                land.updated_at = time();
-                lands.insert(land_id, land.clone());
-                Ok(land)
-            },
-            None => Err("Land parcel not found".to_string()),
-        }
-    })
+fn cancel_land_sale(land_id: u64) -> Result<LandParcel, String> {
+    lands::cancel_land_sale(&CanisterEnv, land_id)
+}
+
+// Name used by the original interface and the frontend
+#[update]
+fn remove_land_from_sale(land_id: u64) -> Result<LandParcel, String> {
+    lands::cancel_land_sale(&CanisterEnv, land_id)
+}
+
+#[update]
+fn update_land_price(land_id: u64, price: u64) -> Result<LandParcel, String> {
+    lands::update_land_price(&CanisterEnv, land_id, price)
+}
+
+#[update]
+fn update_land_details(land_id: u64, update: LandDetailsUpdate) -> Result<LandParcel, String> {
+    revisions::update_land_details(&CanisterEnv, land_id, update)
 }
 
 #[update]
 fn transfer_ownership(land_id: u64, new_owner: Principal) -> Result<LandParcel, String> {
-    let principal = caller();
-    
+    lands::transfer_ownership(&CanisterEnv, land_id, new_owner)
+}
+
+#[update]
+fn freeze_land(land_id: u64, reason: String) -> Result<LandParcel, String> {
+    lands::freeze_land(&CanisterEnv, land_id, reason)
+}
+
+#[update]
+fn unfreeze_land(land_id: u64) -> Result<LandParcel, String> {
+    lands::unfreeze_land(&CanisterEnv, land_id)
+}
+
+#[query]
+fn get_frozen_lands() -> Vec<LandParcel> {
     LANDS.with(|lands| {
-        let mut lands = lands.borrow_mut();
-        
-        match lands.get(&land_id) {
-            Some(mut land) => {
-                if land.owner != principal {
-                    return Err("Only the owner can transfer ownership".to_string());
-                }
-                
-                let transfer = LandTransfer {
-                    from: principal,
-                    to: new_owner,
-                    timestamp: time(),
-                    verified_by: land.verified_by,
-                };
-                
-                land.owner = new_owner;
-                land.status = LandStatus::Verified; // Reset to verified after transfer
-                land.price = None; // Remove price after transfer
-                land.updated_at = time();
-                land.history.push(transfer);
-                
-                lands.insert(land_id, land.clone());
-                Ok(land)
-            },
-            None => Err("Land parcel not found".to_string()),
-        }
+        lands
+            .borrow()
+            .iter()
+            .filter(|(_, land)| land.freeze.is_some())
+            .map(|(_, land)| land)
+            .collect()
     })
 }
 
 #[update]
 fn add_verifier(verifier: Principal) -> Result<String, String> {
-    let principal = caller();
-    
-    // For now, anyone can add verifiers. In production, this should be restricted to admins
-    VERIFIERS.with(|verifiers| {
-        let mut verifiers = verifiers.borrow_mut();
-        verifiers.insert(verifier, true);
-    });
-    
-    Ok("Verifier added successfully".to_string())
+    admin::add_verifier(&CanisterEnv, verifier)
 }
 
 #[update]
 fn remove_verifier(verifier: Principal) -> Result<String, String> {
-    let principal = caller();
-    
-    // For now, anyone can remove verifiers. In production, this should be restricted to admins
-    VERIFIERS.with(|verifiers| {
-        let mut verifiers = verifiers.borrow_mut();
-        verifiers.remove(&verifier);
-    });
-    
-    Ok("Verifier removed successfully".to_string())
+    admin::remove_verifier(&CanisterEnv, verifier)
 }
 
 #[query]
@@ -411,6 +532,11 @@ fn get_total_lands() -> u64 {
     })
 }
 
+#[query]
+fn get_land_revisions(land_id: u64) -> Vec<LandRevision> {
+    revisions::revisions_of(land_id)
+}
+
 #[query]
 fn get_land_history(land_id: u64) -> Option<Vec<LandTransfer>> {
     LANDS.with(|lands| {
@@ -418,351 +544,398 @@ fn get_land_history(land_id: u64) -> Option<Vec<LandTransfer>> {
     })
 }
 
+// Demo endpoints, only compiled with the `demo` feature
+#[cfg(feature = "demo")]
 #[update]
 fn initialize_sample_data() -> String {
-    let principal = caller();
-    
-    // Only allow initialization if no lands exist yet
-    let land_count = LANDS.with(|lands| lands.borrow().len());
-    if land_count > 0 {
-        return "Sample data already exists".to_string();
-    }
-    
-    let current_time = time();
-    
-    // Create mock users for demo purposes
-    let mock_user_1 = Principal::from_text("rdmx6-jaaaa-aaaah-qcaiq-cai").unwrap_or(Principal::anonymous());
-    let mock_user_2 = Principal::from_text("rrkah-fqaaa-aaaah-qcaiq-cai").unwrap_or(Principal::anonymous());
-    
-    // Initialize wallets for mock users with demo funds
-    WALLETS.with(|wallets| {
-        let mut wallets = wallets.borrow_mut();
-        wallets.insert(mock_user_1, 100_000_000_000u64); // 1000 ICP
-        wallets.insert(mock_user_2, 150_000_000_000u64); // 1500 ICP
-        wallets.insert(principal, 200_000_000_000u64); // 2000 ICP for current user
-    });
-    
-    // Create sample lands with different owners
-    let sample_lands = vec![
-        // Land owned by current user
-        LandParcel {
-            id: get_next_land_id(),
-            owner: principal,
-            coordinates: "40.7128, -74.0060".to_string(), // New York coordinates
-            size: 1000.0,
-            description: "Prime Manhattan virtual land parcel with stunning city views".to_string(),
-            status: LandStatus::Verified,
-            verified_by: Some(principal),
-            created_at: current_time,
-            updated_at: current_time,
-            history: vec![LandTransfer {
-                from: Principal::anonymous(),
-                to: principal,
-                timestamp: current_time,
-                verified_by: Some(principal),
-            }],
-            price: None,
-            metadata: "Commercial zoning, high traffic area".to_string(),
-            preview_image_url: Some("https://images.unsplash.com/photo-1518020382113-a7e8fc38eac9".to_string()),
-        },
-        // Land owned by mock user 1 - FOR SALE
-        LandParcel {
-            id: get_next_land_id(),
-            owner: mock_user_1,
-            coordinates: "34.0522, -118.2437".to_string(), // Los Angeles coordinates
-            size: 2500.0,
-            description: "Luxury beachfront property in virtual Malibu - Ready for purchase!".to_string(),
-            status: LandStatus::ForSale,
-            verified_by: Some(principal),
-            created_at: current_time,
-            updated_at: current_time,
-            history: vec![LandTransfer {
-                from: Principal::anonymous(),
-                to: mock_user_1,
-                timestamp: current_time,
-                verified_by: Some(principal),
-            }],
-            price: Some(5_000_000_000u64), // 50 ICP in e8s
-            metadata: "Residential zoning, beachfront access".to_string(),
-            preview_image_url: Some("https://images.unsplash.com/photo-1469474968028-56623f02e42e".to_string()),
-        },
-        // Land owned by mock user 2 - FOR SALE
-        LandParcel {
-            id: get_next_land_id(),
-            owner: mock_user_2,
-            coordinates: "37.7749, -122.4194".to_string(), // San Francisco coordinates
-            size: 800.0,
-            description: "Tech district land perfect for virtual offices and startups - Available now!".to_string(),
-            status: LandStatus::ForSale,
-            verified_by: Some(principal),
-            created_at: current_time,
-            updated_at: current_time,
-            history: vec![LandTransfer {
-                from: Principal::anonymous(),
-                to: mock_user_2,
-                timestamp: current_time,
-                verified_by: Some(principal),
-            }],
-            price: Some(7_500_000_000u64), // 75 ICP in e8s
-            metadata: "Commercial zoning, tech district".to_string(),
-            preview_image_url: Some("https://images.unsplash.com/photo-1574949645342-19a5733c3e7b".to_string()),
-        },
-        // Another land owned by current user
-        LandParcel {
-            id: get_next_land_id(),
-            owner: principal,
-            coordinates: "51.5074, -0.1278".to_string(), // London coordinates
-            size: 1200.0,
-            description: "Historic London virtual district with heritage buildings".to_string(),
-            status: LandStatus::Verified,
-            verified_by: Some(principal),
-            created_at: current_time,
-            updated_at: current_time,
-            history: vec![LandTransfer {
-                from: Principal::anonymous(),
-                to: principal,
-                timestamp: current_time,
-                verified_by: Some(principal),
-            }],
-            price: None,
-            metadata: "Mixed-use zoning, historic district".to_string(),
-            preview_image_url: Some("https://images.unsplash.com/photo-1513635269975-59663e0ac1ad".to_string()),
-        },
-    ];
-    
-    LANDS.with(|lands| {
-        let mut lands = lands.borrow_mut();
-        for land in sample_lands {
-            lands.insert(land.id, land);
-        }
-    });
-    
-    "Successfully initialized 4 sample land parcels with demo wallets".to_string()
+    demo::initialize_sample_data(&CanisterEnv)
 }
 
+#[cfg(feature = "demo")]
 #[update]
 fn add_more_sample_lands() -> String {
-    let principal = caller();
-    let current_time = time();
-    
-    // Create mock users for demo purposes
-    let mock_user_1 = Principal::from_text("rdmx6-jaaaa-aaaah-qcaiq-cai").unwrap_or(Principal::anonymous());
-    let mock_user_2 = Principal::from_text("rrkah-fqaaa-aaaah-qcaiq-cai").unwrap_or(Principal::anonymous());
-    
-    // Additional sample lands for sale
-    let additional_lands = vec![
-        // Miami Beach land for sale
-        LandParcel {
-            id: get_next_land_id(),
-            owner: mock_user_1,
-            coordinates: "25.7617, -80.1918".to_string(), // Miami coordinates
-            size: 1500.0,
-            description: "Stunning Miami Beach virtual property with ocean views and nightlife access".to_string(),
-            status: LandStatus::ForSale,
-            verified_by: Some(principal),
-            created_at: current_time,
-            updated_at: current_time,
-            history: vec![LandTransfer {
-                from: Principal::anonymous(),
-                to: mock_user_1,
-                timestamp: current_time,
-                verified_by: Some(principal),
-            }],
-            price: Some(12_000_000_000u64), // 120 ICP in e8s  
-            metadata: "Entertainment district, beach access".to_string(),
-            preview_image_url: Some("https://images.unsplash.com/photo-1506905925346-21bda4d32df4".to_string()),
-        },
-        // Tokyo Downtown land for sale
-        LandParcel {
-            id: get_next_land_id(),
-            owner: mock_user_2,
-            coordinates: "35.6762, 139.6503".to_string(), // Tokyo coordinates
-            size: 600.0,
-            description: "Premium Tokyo downtown virtual space in the heart of the financial district".to_string(),
-            status: LandStatus::ForSale,
-            verified_by: Some(principal),
-            created_at: current_time,
-            updated_at: current_time,
-            history: vec![LandTransfer {
-                from: Principal::anonymous(),
-                to: mock_user_2,
-                timestamp: current_time,
-                verified_by: Some(principal),
-            }],
-            price: Some(8_500_000_000u64), // 85 ICP in e8s
-            metadata: "Financial district, high-rise development".to_string(),
-            preview_image_url: Some("https://images.unsplash.com/photo-1540959733332-eab4deabeeaf".to_string()),
-        },
-        // Las Vegas Strip land for sale
-        LandParcel {
-            id: get_next_land_id(),
-            owner: mock_user_1,
-            coordinates: "36.1699, -115.1398".to_string(), // Las Vegas coordinates
-            size: 2000.0,
-            description: "Virtual Las Vegas Strip property perfect for entertainment venues and casinos".to_string(),
-            status: LandStatus::ForSale,
-            verified_by: Some(principal),
-            created_at: current_time,
-            updated_at: current_time,
-            history: vec![LandTransfer {
-                from: Principal::anonymous(),
-                to: mock_user_1,
-                timestamp: current_time,
-                verified_by: Some(principal),
-            }],
-            price: Some(15_000_000_000u64), // 150 ICP in e8s
-            metadata: "Entertainment zoning, casino district".to_string(),
-            preview_image_url: Some("https://images.unsplash.com/photo-1605833556294-ea9d2d702878".to_string()),
-        },
-    ];
-    
-    LANDS.with(|lands| {
-        let mut lands = lands.borrow_mut();
-        for land in additional_lands {
-            lands.insert(land.id, land);
-        }
-    });
-    
-    "Successfully added 3 additional land parcels for sale (Miami Beach $12K, Tokyo Downtown $8.5K, Las Vegas Strip $15K)".to_string()
+    demo::add_more_sample_lands(&CanisterEnv)
 }
 
-// Clear all data for demo purposes
+#[cfg(feature = "demo")]
 #[update]
 fn clear_all_data() -> String {
-    LANDS.with(|lands| {
-        lands.replace(StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(0))),
-        ));
-    });
-    
-    LAND_ID_COUNTER.with(|counter| {
-        counter.replace(StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1))),
-        ));
-    });
-    
-    WALLETS.with(|wallets| {
-        wallets.replace(StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3))),
-        ));
-    });
-    
-    "All data cleared successfully".to_string()
+    demo::clear_all_data(&CanisterEnv)
+}
+
+#[cfg(feature = "demo")]
+#[update]
+fn add_funds_to_wallet(amount: u64) -> Result<u64, String> {
+    demo::add_funds_to_wallet(&CanisterEnv, amount)
+}
+
+#[cfg(feature = "demo")]
+#[query]
+fn get_faucet_allowance(user: Principal) -> u64 {
+    demo::faucet_allowance(&CanisterEnv, &user)
 }
 
 // Wallet management functions
 #[query]
 fn get_wallet_balance(user: Principal) -> u64 {
-    WALLETS.with(|wallets| {
-        wallets.borrow().get(&user).unwrap_or(0)
-    })
+    wallets::balance(&user)
 }
 
 #[update]
-fn add_funds_to_wallet(amount: u64) -> Result<u64, String> {
-    let user = caller();
-    
-    if user == Principal::anonymous() {
-        return Err("Anonymous users cannot have wallets".to_string());
-    }
-    
-    WALLETS.with(|wallets| {
-        let mut wallets = wallets.borrow_mut();
-        let current_balance = wallets.get(&user).unwrap_or(0);
-        let new_balance = current_balance + amount;
-        wallets.insert(user, new_balance);
-        Ok(new_balance)
-    })
+fn initialize_user_wallet() -> Result<u64, String> {
+    wallets::initialize_user_wallet(&CanisterEnv)
 }
 
 #[update]
-fn initialize_user_wallet() -> Result<u64, String> {
-    let user = caller();
-    
-    if user == Principal::anonymous() {
-        return Err("Anonymous users cannot have wallets".to_string());
-    }
-    
-    WALLETS.with(|wallets| {
-        let mut wallets = wallets.borrow_mut();
-        
-        // Give new users 50,000,000,000 e8s (500 ICP) as starting balance
-        let starting_balance = 50_000_000_000u64; // 500 ICP in e8s
-        
-        // Only initialize if wallet doesn't exist
-        if wallets.get(&user).is_none() {
-            wallets.insert(user, starting_balance);
-            Ok(starting_balance)
-        } else {
-            let current_balance = wallets.get(&user).unwrap_or(0);
-            Ok(current_balance)
-        }
-    })
+fn transfer_funds(to: Principal, amount: u64, memo: Option<String>) -> Result<u64, String> {
+    wallets::transfer_funds(&CanisterEnv, to, amount, memo)
+}
+
+#[query]
+fn get_wallet_transactions(user: Principal, cursor: Option<u64>, limit: u64) -> WalletTransactionPage {
+    wallets::transactions_page(user, cursor, limit)
 }
 
 #[update]
 fn buy_land(land_id: u64) -> Result<LandParcel, String> {
-    let buyer = caller();
-    
-    if buyer == Principal::anonymous() {
-        return Err("Anonymous users cannot buy land".to_string());
-    }
-    
-    LANDS.with(|lands| {
-        let mut lands = lands.borrow_mut();
-        
-        match lands.get(&land_id) {
-            Some(mut land) => {
-                if !matches!(land.status, LandStatus::ForSale) {
-                    return Err("Land is not for sale".to_string());
-                }
-                
-                if land.owner == buyer {
-                    return Err("You cannot buy your own land".to_string());
-                }
-                
-                let price = land.price.ok_or("Price not set for this land")?;
-                
-                // Handle wallet transaction
-                WALLETS.with(|wallets| {
-                    let mut wallets = wallets.borrow_mut();
-                    
-                    // Check buyer's balance
-                    let buyer_balance = wallets.get(&buyer).unwrap_or(0);
-                    if buyer_balance < price {
-                        return Err(format!("Insufficient funds. You have {} e8s but need {} e8s", buyer_balance, price));
-                    }
-                    
-                    // Get seller's current balance
-                    let seller_balance = wallets.get(&land.owner).unwrap_or(0);
-                    
-                    // Deduct from buyer
-                    wallets.insert(buyer, buyer_balance - price);
-                    
-                    // Credit seller
-                    wallets.insert(land.owner, seller_balance + price);
-                    
-                    // Transfer ownership
-                    let transfer = LandTransfer {
-                        from: land.owner,
-                        to: buyer,
-                        timestamp: time(),
-                        verified_by: land.verified_by,
-                    };
-                    
-                    land.owner = buyer;
-                    land.status = LandStatus::Verified; // Change back to verified after purchase
-                    land.price = None; // Remove price after sale
-                    land.updated_at = time();
-                    land.history.push(transfer);
-                    
-                    lands.insert(land_id, land.clone());
-                    Ok(land)
-                })
-            },
-            None => Err("Land parcel not found".to_string()),
-        }
-    })
+    lands::buy_land(&CanisterEnv, land_id)
+}
+
+#[update]
+fn set_land_royalty(land_id: u64, royalty: Option<RoyaltyConfig>) -> Result<LandParcel, String> {
+    lands::set_land_royalty(&CanisterEnv, land_id, royalty)
+}
+
+// Inheritance
+#[update]
+fn set_inheritance_plan(input: InheritancePlanInput) -> Result<InheritancePlan, String> {
+    inheritance::set_plan(&CanisterEnv, input)
+}
+
+#[update]
+fn remove_inheritance_plan(land_id: Option<u64>) -> Result<InheritancePlan, String> {
+    inheritance::remove_plan(&CanisterEnv, land_id)
+}
+
+#[query]
+fn get_inheritance_plans(owner: Principal) -> Vec<InheritancePlan> {
+    inheritance::plans_of(owner)
+}
+
+#[update]
+fn check_in() -> Result<u64, String> {
+    inheritance::check_in(&CanisterEnv)
+}
+
+#[query]
+fn get_last_activity(user: Principal) -> Option<u64> {
+    inheritance::last_activity(&user)
+}
+
+#[update]
+fn claim_inheritance(land_id: u64) -> Result<LandParcel, String> {
+    inheritance::claim(&CanisterEnv, land_id)
+}
+
+// Price history and valuation
+#[query]
+fn get_price_history(land_id: u64) -> Vec<PricePoint> {
+    pricing::history(land_id)
+}
+
+#[query]
+fn get_last_sale(land_id: u64) -> Option<PricePoint> {
+    pricing::last_sale(land_id)
+}
+
+#[query]
+fn get_market_stats(filter: MarketFilter) -> MarketStats {
+    pricing::market_stats(&filter)
+}
+
+#[query]
+fn estimate_land_value(land_id: u64) -> Result<ValuationEstimate, String> {
+    pricing::estimate(land_id)
+}
+
+// Audit log queries
+#[query]
+fn get_audit_events(filter: AuditFilter, cursor: Option<u64>, limit: u64) -> AuditEventPage {
+    audit::events_page(&filter, cursor, limit)
+}
+
+#[query]
+fn get_audit_event_count() -> u64 {
+    audit::event_count()
+}
+
+// Event subscriptions
+#[update]
+fn subscribe_events(input: SubscriptionInput) -> Result<Subscription, String> {
+    admin::subscribe_events(&CanisterEnv, input)
+}
+
+#[update]
+fn unsubscribe_events(subscription_id: u64) -> Result<Subscription, String> {
+    admin::unsubscribe_events(&CanisterEnv, subscription_id)
+}
+
+#[query]
+fn get_event_subscriptions() -> Vec<Subscription> {
+    subscriptions::list()
+}
+
+// Validation limits
+#[query]
+fn get_validation_limits() -> ValidationLimits {
+    config::validation_limits()
+}
+
+#[update]
+fn set_validation_limits(limits: ValidationLimits) -> Result<ValidationLimits, String> {
+    admin::set_validation_limits(&CanisterEnv, limits)
+}
+
+// Registration fees and treasury
+#[query]
+fn get_fee_policy() -> FeePolicy {
+    treasury::fee_policy()
+}
+
+#[update]
+fn set_fee_policy(policy: FeePolicy) -> Result<FeePolicy, String> {
+    admin::set_fee_policy(&CanisterEnv, policy)
+}
+
+#[query]
+fn get_treasury_balance() -> u64 {
+    treasury::balance()
+}
+
+#[query]
+fn get_treasury_ledger(cursor: Option<u64>, limit: u64) -> TreasuryLedgerPage {
+    treasury::ledger_page(cursor, limit)
+}
+
+#[update]
+fn withdraw_from_treasury(to: Principal, amount: u64) -> Result<u64, String> {
+    admin::withdraw_from_treasury(&CanisterEnv, to, amount)
+}
+
+// Land tax
+#[query]
+fn get_tax_policy() -> TaxPolicy {
+    tax::tax_policy()
+}
+
+#[update]
+fn set_tax_policy(policy: TaxPolicy) -> Result<TaxPolicy, String> {
+    admin::set_tax_policy(&CanisterEnv, policy)
+}
+
+#[query]
+fn get_tax_account(land_id: u64) -> Option<TaxAccount> {
+    tax::account(land_id)
+}
+
+#[query]
+fn get_tax_accounts_by_standing(standing: TaxStanding) -> Vec<TaxAccount> {
+    tax::accounts_in_standing(&standing)
+}
+
+#[update]
+fn pay_land_tax(land_id: u64) -> Result<TaxAccount, String> {
+    tax::pay_arrears(&CanisterEnv, land_id)
+}
+
+#[update]
+fn start_reclamation_sale(land_id: u64, price: u64) -> Result<LandParcel, String> {
+    tax::start_reclamation_sale(&CanisterEnv, land_id, price)
+}
+
+// Offers
+#[update]
+fn place_offer(land_id: u64, amount: u64, expires_at: u64) -> Result<Offer, String> {
+    offers::place_offer(&CanisterEnv, land_id, amount, expires_at)
+}
+
+#[update]
+fn withdraw_offer(offer_id: u64) -> Result<Offer, String> {
+    offers::withdraw_offer(&CanisterEnv, offer_id)
+}
+
+#[update]
+fn reject_offer(offer_id: u64) -> Result<Offer, String> {
+    offers::reject_offer(&CanisterEnv, offer_id)
+}
+
+#[update]
+fn counter_offer(offer_id: u64, amount: u64) -> Result<Offer, String> {
+    offers::counter_offer(&CanisterEnv, offer_id, amount)
+}
+
+#[update]
+fn accept_offer(offer_id: u64) -> Result<LandParcel, String> {
+    offers::accept_offer(&CanisterEnv, offer_id)
+}
+
+#[update]
+fn accept_counter_offer(offer_id: u64) -> Result<LandParcel, String> {
+    offers::accept_counter_offer(&CanisterEnv, offer_id)
+}
+
+#[query]
+fn get_offer(offer_id: u64) -> Option<Offer> {
+    offers::get_offer(offer_id)
+}
+
+#[query]
+fn get_offers_for_land(land_id: u64) -> Vec<Offer> {
+    offers::offers_for_land(land_id)
+}
+
+#[query]
+fn get_offers_by_buyer(buyer: Principal) -> Vec<Offer> {
+    offers::offers_by(buyer)
+}
+
+#[query]
+fn get_escrow_balance(owner: Principal) -> u64 {
+    escrow::held_by(&owner)
+}
+
+// Marketplace sales and auctions. Apart from the listing check, only the configured marketplace
+// canister can call these.
+#[query]
+fn marketplace_check_listing(land_id: u64, seller: Principal) -> Result<LandParcel, String> {
+    marketplace::check_listing(land_id, seller)
+}
+
+#[update]
+fn marketplace_hold_payment(buyer: Principal, amount: u64, land_id: u64, transaction_id: u64) -> Result<u64, String> {
+    marketplace::hold_payment(&CanisterEnv, buyer, amount, land_id, transaction_id)
+}
+
+#[update]
+fn marketplace_hold_bid(bidder: Principal, amount: u64, land_id: u64, auction_id: u64) -> Result<u64, String> {
+    marketplace::hold_bid(&CanisterEnv, bidder, amount, land_id, auction_id)
+}
+
+#[update]
+fn marketplace_release_hold(hold_id: u64) -> Result<u64, String> {
+    marketplace::release_hold(&CanisterEnv, hold_id)
+}
+
+#[update]
+fn marketplace_settle(hold_id: u64, seller: Principal, price: u64, fee: Option<MarketplaceFee>) -> Result<LandParcel, String> {
+    marketplace::settle(&CanisterEnv, hold_id, seller, price, fee)
+}
+
+#[update]
+fn set_marketplace_canister(canister_id: Principal) -> Result<Principal, String> {
+    admin::set_marketplace_canister(&CanisterEnv, canister_id)
+}
+
+#[query]
+fn get_marketplace_canister() -> Option<Principal> {
+    marketplace::marketplace_canister()
+}
+
+// Parcel images
+#[update]
+fn begin_image_upload(input: ImageUploadInput) -> Result<u64, String> {
+    images::begin_upload(&CanisterEnv, input)
+}
+
+#[update]
+fn upload_image_chunk(upload_id: u64, index: u32, chunk: Vec<u8>) -> Result<u64, String> {
+    images::upload_chunk(&CanisterEnv, upload_id, index, chunk)
+}
+
+#[update]
+fn finish_image_upload(upload_id: u64) -> Result<ImageInfo, String> {
+    images::finish_upload(&CanisterEnv, upload_id)
+}
+
+#[update]
+fn remove_land_image(land_id: u64, image_id: u64) -> Result<ImageInfo, String> {
+    images::remove_image(&CanisterEnv, land_id, image_id)
+}
+
+#[query]
+fn get_land_images(land_id: u64) -> Vec<ImageInfo> {
+    images::images_of(land_id)
+}
+
+#[update]
+fn set_image_base_url(base_url: String) -> Result<String, String> {
+    admin::set_image_base_url(&CanisterEnv, base_url)
+}
+
+#[query]
+fn http_request(request: HttpRequest) -> HttpResponse {
+    images::http_request(&request)
+}
+
+// Title certificates
+#[update]
+async fn issue_title_certificate(land_id: u64) -> Result<TitleCertificate, String> {
+    titles::issue(&CanisterEnv, land_id).await
+}
+
+#[query]
+fn get_title_public_key() -> Option<Vec<u8>> {
+    titles::public_key()
+}
+
+#[query]
+fn verify_title_certificate(certificate: TitleCertificate) -> Result<(), String> {
+    titles::verify(&CanisterEnv, &certificate)
+}
+
+#[update]
+fn set_title_signing_key(key_name: String) -> Result<String, String> {
+    admin::set_title_signing_key(&CanisterEnv, key_name)
+}
+
+#[update]
+fn create_snapshot() -> Result<SnapshotManifest, String> {
+    admin::create_snapshot(&CanisterEnv)
+}
+
+#[query]
+fn get_snapshot_chunk(snapshot_id: u64, index: u32) -> Result<Vec<u8>, String> {
+    admin::get_snapshot_chunk(&CanisterEnv, snapshot_id, index)
+}
+
+#[query]
+fn get_snapshot_images(snapshot_id: u64) -> Result<Vec<SnapshotImage>, String> {
+    admin::get_snapshot_images(&CanisterEnv, snapshot_id)
+}
+
+#[query]
+fn get_snapshot_image_chunk(snapshot_id: u64, image_id: u64, index: u32) -> Result<Vec<u8>, String> {
+    admin::get_snapshot_image_chunk(&CanisterEnv, snapshot_id, image_id, index)
+}
+
+#[update]
+fn begin_restore(manifest: SnapshotManifest) -> Result<(), String> {
+    admin::begin_restore(&CanisterEnv, manifest)
+}
+
+#[update]
+fn upload_restore_chunk(index: u32, chunk: Vec<u8>) -> Result<u32, String> {
+    admin::upload_restore_chunk(&CanisterEnv, index, chunk)
+}
+
+#[update]
+fn upload_restore_image_chunk(image_id: u64, index: u32, chunk: Vec<u8>) -> Result<u32, String> {
+    admin::upload_restore_image_chunk(&CanisterEnv, image_id, index, chunk)
+}
+
+#[update]
+fn finish_restore() -> Result<SnapshotManifest, String> {
+    admin::finish_restore(&CanisterEnv)
 }
 
 // Export candid interface
@@ -0,0 +1,135 @@
+//! Calls the marketplace canister makes on behalf of its users.
+//!
+//! The marketplace holds neither parcels nor funds of its own. Payments for listings and auction
+//! bids are escrow holds on the buyers' registry wallets, and a purchase or winning bid is settled
+//! from its hold through `lands::complete_sale` like any other sale. Only the configured
+//! marketplace canister may call these.
+
+use candid::Principal;
+use registry_common::env::Environment;
+
+use crate::escrow::{self, HoldPurpose};
+use crate::sales::{self, MarketplaceFee};
+use crate::{lands, tax, LandParcel, LandStatus, CONFIG, LANDS};
+
+const MARKETPLACE_KEY: &str = "marketplace_canister_id";
+
+pub fn marketplace_canister() -> Option<Principal> {
+    CONFIG.with(|config| config.borrow().get(&MARKETPLACE_KEY.to_string()))
+        .and_then(|canister_id| Principal::from_text(canister_id).ok())
+}
+
+pub fn set_marketplace_canister(canister_id: Principal) -> Result<Principal, String> {
+    if canister_id == Principal::anonymous() {
+        return Err("The marketplace cannot be the anonymous principal".to_string());
+    }
+
+    CONFIG.with(|config| {
+        config.borrow_mut().insert(MARKETPLACE_KEY.to_string(), canister_id.to_text());
+    });
+
+    Ok(canister_id)
+}
+
+/// Checks that `seller` may list the parcel on the marketplace: they own it, it is verified and
+/// not for sale in the registry, and nothing else has a claim on it. Read-only, so anyone may ask.
+pub fn check_listing(land_id: u64, seller: Principal) -> Result<LandParcel, String> {
+    let land = sellable_land(land_id, seller)?;
+
+    if !matches!(land.status, LandStatus::Verified) {
+        return Err("The parcel is already for sale in the registry".to_string());
+    }
+
+    Ok(land)
+}
+
+/// Holds the price of a listing from the buyer's wallet. Returns the hold id.
+pub fn hold_payment(env: &impl Environment, buyer: Principal, amount: u64, land_id: u64, transaction_id: u64) -> Result<u64, String> {
+    hold(env, buyer, amount, land_id, HoldPurpose::Purchase(transaction_id))
+}
+
+/// Holds a bid on an auction of the parcel. Returns the hold id.
+pub fn hold_bid(env: &impl Environment, bidder: Principal, amount: u64, land_id: u64, auction_id: u64) -> Result<u64, String> {
+    hold(env, bidder, amount, land_id, HoldPurpose::Auction(auction_id))
+}
+
+/// Returns a held payment or bid to its owner, for a failed purchase or an outbid or losing bid.
+/// Returns the owner's new balance.
+pub fn release_hold(env: &impl Environment, hold_id: u64) -> Result<u64, String> {
+    ensure_marketplace(env)?;
+    marketplace_hold(hold_id)?;
+
+    escrow::release(env, hold_id)
+}
+
+/// Sells the parcel to the owner of a held payment or winning bid at `price`, which may be below
+/// the held amount for a Dutch auction. The parcel moves and the seller is paid, net of the
+/// marketplace's fee, in one step. If the sale cannot go ahead, the hold is left for the
+/// marketplace to release.
+pub fn settle(env: &impl Environment, hold_id: u64, seller: Principal, price: u64, fee: Option<MarketplaceFee>) -> Result<LandParcel, String> {
+    ensure_marketplace(env)?;
+    let hold = marketplace_hold(hold_id)?;
+
+    if price == 0 || price > hold.amount {
+        return Err("The sale price must be positive and covered by the held funds".to_string());
+    }
+
+    let land = sellable_land(hold.land_id, seller)?;
+    // Checked before the hold is released, which cannot be undone
+    sales::split_with_fee(price, seller, land.royalty.as_ref(), fee.as_ref())?;
+
+    // The released funds cover the price; any excess stays in the buyer's wallet
+    escrow::release(env, hold_id)?;
+    lands::complete_sale(env, land, hold.owner, price, fee.as_ref())
+}
+
+fn hold(env: &impl Environment, owner: Principal, amount: u64, land_id: u64, purpose: HoldPurpose) -> Result<u64, String> {
+    ensure_marketplace(env)?;
+
+    if owner == Principal::anonymous() {
+        return Err("Anonymous users cannot buy land".to_string());
+    }
+
+    let land = LANDS.with(|lands| lands.borrow().get(&land_id)).ok_or("Land parcel not found")?;
+    if land.owner == owner {
+        return Err("You cannot buy your own land".to_string());
+    }
+
+    escrow::hold(env, owner, amount, land_id, purpose)
+}
+
+fn ensure_marketplace(env: &impl Environment) -> Result<(), String> {
+    match marketplace_canister() {
+        Some(marketplace) if marketplace == env.caller() => Ok(()),
+        Some(_) => Err("Only the marketplace canister can call this method".to_string()),
+        None => Err("Marketplace canister ID not configured".to_string()),
+    }
+}
+
+// Marketplace sales need a verified parcel that the seller still owns and nothing else has a
+// claim on
+fn sellable_land(land_id: u64, seller: Principal) -> Result<LandParcel, String> {
+    let land = LANDS.with(|lands| lands.borrow().get(&land_id)).ok_or("Land parcel not found")?;
+
+    if land.owner != seller {
+        return Err("The seller no longer owns this parcel".to_string());
+    }
+
+    if !matches!(land.status, LandStatus::Verified | LandStatus::ForSale) {
+        return Err("Only verified land can be sold through the marketplace".to_string());
+    }
+
+    lands::ensure_not_frozen(&land)?;
+    tax::ensure_not_reclaiming(land_id)?;
+    Ok(land)
+}
+
+fn marketplace_hold(hold_id: u64) -> Result<escrow::EscrowHold, String> {
+    let hold = escrow::get(hold_id).ok_or("Escrow hold not found")?;
+
+    if !matches!(hold.purpose, HoldPurpose::Purchase(_) | HoldPurpose::Auction(_)) {
+        return Err("The hold does not belong to the marketplace".to_string());
+    }
+
+    Ok(hold)
+}
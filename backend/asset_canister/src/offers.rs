@@ -0,0 +1,380 @@
+//! Purchase offers on verified parcels, whether or not they are listed.
+//!
+//! Placing an offer holds the offered amount in escrow until the offer is accepted, rejected,
+//! withdrawn or expires. The owner may counter with a higher price, which the buyer can accept in
+//! turn. Accepting settles through `lands::complete_sale`, the same path `buy_land` uses. Offers
+//! are made to the current owner, so the open ones are cancelled whenever the parcel changes hands.
+
+use candid::{CandidType, Principal};
+use ic_stable_structures::Storable;
+use registry_common::env::{CanisterEnv, Environment};
+use serde::{Serialize, Deserialize as SerdeDeserialize};
+use std::borrow::Cow;
+use std::time::Duration;
+
+use crate::audit::{self, AuditDetails, AuditEventKind};
+use crate::escrow::{self, HoldPurpose};
+use crate::{lands, tax, wallets, LandParcel, LandStatus, LANDS, OFFERS};
+
+// How often expired offers are closed
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+const MAX_OFFER_DURATION_NS: u64 = 30 * 86_400_000_000_000;
+const MAX_OPEN_OFFERS: usize = 20; // Per buyer
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug, PartialEq)]
+pub enum OfferStatus {
+    Open,
+    Countered,
+    Accepted,
+    Rejected,
+    Withdrawn,
+    Expired,
+    Cancelled, // The parcel changed hands another way
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug)]
+pub struct CounterOffer {
+    pub amount: u64,
+    pub owner: Principal, // Only this owner's counter can be accepted
+    pub countered_at: u64,
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone)]
+pub struct Offer {
+    pub id: u64,
+    pub land_id: u64,
+    pub buyer: Principal,
+    pub amount: u64, // in e8s, held in escrow while the offer is open
+    pub hold_id: u64,
+    pub counter: Option<CounterOffer>,
+    pub status: OfferStatus,
+    pub created_at: u64,
+    pub expires_at: u64,
+    pub closed_at: Option<u64>,
+    pub price_paid: Option<u64>, // Set once accepted
+}
+
+impl Storable for Offer {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl Offer {
+    fn is_open(&self) -> bool {
+        matches!(self.status, OfferStatus::Open | OfferStatus::Countered)
+    }
+}
+
+pub fn place_offer(env: &impl Environment, land_id: u64, amount: u64, expires_at: u64) -> Result<Offer, String> {
+    let buyer = env.caller();
+    let now = env.time();
+
+    if buyer == Principal::anonymous() {
+        return Err("Anonymous users cannot make offers".to_string());
+    }
+
+    if amount == 0 {
+        return Err("Offer amount must be greater than zero".to_string());
+    }
+
+    if expires_at <= now || expires_at - now > MAX_OFFER_DURATION_NS {
+        return Err("Offer expiry must be in the future and at most 30 days away".to_string());
+    }
+
+    let land = LANDS.with(|lands| lands.borrow().get(&land_id)).ok_or("Land parcel not found")?;
+    ensure_can_trade(&land)?;
+
+    if land.owner == buyer {
+        return Err("You cannot make an offer on your own land".to_string());
+    }
+
+    let open = offers_by(buyer).into_iter().filter(Offer::is_open).collect::<Vec<_>>();
+    if open.iter().any(|offer| offer.land_id == land_id) {
+        return Err("You already have an open offer on this parcel".to_string());
+    }
+
+    if open.len() >= MAX_OPEN_OFFERS {
+        return Err(format!("You can have at most {} open offers", MAX_OPEN_OFFERS));
+    }
+
+    let id = OFFERS.with(|offers| offers.borrow().last_key_value().map(|(id, _)| id + 1).unwrap_or(1));
+    let hold_id = escrow::hold(env, buyer, amount, land_id, HoldPurpose::Offer(id))?;
+
+    let offer = Offer {
+        id,
+        land_id,
+        buyer,
+        amount,
+        hold_id,
+        counter: None,
+        status: OfferStatus::Open,
+        created_at: now,
+        expires_at,
+        closed_at: None,
+        price_paid: None,
+    };
+    store(&offer);
+
+    audit::record(env, buyer, AuditEventKind::OfferPlaced, AuditDetails {
+        land_id: Some(land_id),
+        subject: Some(land.owner),
+        amount: Some(amount),
+        after: Some(summarize(&offer)),
+        ..Default::default()
+    });
+
+    Ok(offer)
+}
+
+pub fn withdraw_offer(env: &impl Environment, offer_id: u64) -> Result<Offer, String> {
+    let buyer = env.caller();
+    let offer = open_offer(env, offer_id)?;
+
+    if offer.buyer != buyer {
+        return Err("Only the buyer can withdraw an offer".to_string());
+    }
+
+    Ok(close(env, buyer, offer, OfferStatus::Withdrawn, AuditEventKind::OfferWithdrawn))
+}
+
+pub fn reject_offer(env: &impl Environment, offer_id: u64) -> Result<Offer, String> {
+    let owner = env.caller();
+    let offer = open_offer(env, offer_id)?;
+    owned_land(owner, offer.land_id)?;
+
+    Ok(close(env, owner, offer, OfferStatus::Rejected, AuditEventKind::OfferRejected))
+}
+
+/// Proposes a higher price. The buyer's funds stay on hold at the original amount until they
+/// accept the counter or the offer closes.
+pub fn counter_offer(env: &impl Environment, offer_id: u64, amount: u64) -> Result<Offer, String> {
+    let owner = env.caller();
+    let mut offer = open_offer(env, offer_id)?;
+    owned_land(owner, offer.land_id)?;
+
+    if amount <= offer.amount {
+        return Err("A counter offer must be above the offered amount; accept the offer instead".to_string());
+    }
+
+    let before = summarize(&offer);
+    offer.counter = Some(CounterOffer {
+        amount,
+        owner,
+        countered_at: env.time(),
+    });
+    offer.status = OfferStatus::Countered;
+    store(&offer);
+
+    audit::record(env, owner, AuditEventKind::OfferCountered, AuditDetails {
+        land_id: Some(offer.land_id),
+        subject: Some(offer.buyer),
+        amount: Some(amount),
+        before: Some(before),
+        after: Some(summarize(&offer)),
+    });
+
+    Ok(offer)
+}
+
+/// The owner accepts the buyer's offer at the offered amount.
+pub fn accept_offer(env: &impl Environment, offer_id: u64) -> Result<LandParcel, String> {
+    let owner = env.caller();
+    let offer = open_offer(env, offer_id)?;
+    let land = owned_land(owner, offer.land_id)?;
+
+    let amount = offer.amount;
+    settle(env, owner, offer, land, amount)
+}
+
+/// The buyer accepts the owner's counter offer. The difference to the held amount is paid from
+/// their wallet.
+pub fn accept_counter_offer(env: &impl Environment, offer_id: u64) -> Result<LandParcel, String> {
+    let buyer = env.caller();
+    let offer = open_offer(env, offer_id)?;
+
+    if offer.buyer != buyer {
+        return Err("Only the buyer can accept a counter offer".to_string());
+    }
+
+    let counter = offer.counter.clone().ok_or("The owner has not countered this offer")?;
+    let land = LANDS.with(|lands| lands.borrow().get(&offer.land_id)).ok_or("Land parcel not found")?;
+    ensure_can_trade(&land)?;
+
+    if land.owner != counter.owner {
+        return Err("The parcel has changed hands since the counter offer".to_string());
+    }
+
+    let available = wallets::balance(&buyer) + offer.amount;
+    if available < counter.amount {
+        return Err(format!("Insufficient funds. You have {} e8s but need {} e8s", available, counter.amount));
+    }
+
+    settle(env, buyer, offer, land, counter.amount)
+}
+
+pub fn get_offer(offer_id: u64) -> Option<Offer> {
+    OFFERS.with(|offers| offers.borrow().get(&offer_id))
+}
+
+/// Offers on a parcel, newest first.
+pub fn offers_for_land(land_id: u64) -> Vec<Offer> {
+    let mut offers: Vec<Offer> = OFFERS.with(|offers| {
+        offers.borrow().iter().map(|(_, offer)| offer).filter(|offer| offer.land_id == land_id).collect()
+    });
+    offers.reverse();
+    offers
+}
+
+/// Offers a buyer has made, newest first.
+pub fn offers_by(buyer: Principal) -> Vec<Offer> {
+    let mut offers: Vec<Offer> = OFFERS.with(|offers| {
+        offers.borrow().iter().map(|(_, offer)| offer).filter(|offer| offer.buyer == buyer).collect()
+    });
+    offers.reverse();
+    offers
+}
+
+pub fn start_expiry_timer() {
+    ic_cdk_timers::set_timer_interval(EXPIRY_INTERVAL, || expire_offers(&CanisterEnv));
+}
+
+/// Closes open offers past their expiry and returns the held funds.
+pub fn expire_offers(env: &impl Environment) {
+    let now = env.time();
+    let expired: Vec<Offer> = OFFERS.with(|offers| {
+        offers
+            .borrow()
+            .iter()
+            .map(|(_, offer)| offer)
+            .filter(|offer| offer.is_open() && offer.expires_at <= now)
+            .collect()
+    });
+
+    for offer in expired {
+        close(env, env.canister_id(), offer, OfferStatus::Expired, AuditEventKind::OfferExpired);
+    }
+}
+
+/// Cancels the open offers on a parcel that has just changed hands and returns the held funds.
+pub fn cancel_on_transfer(env: &impl Environment, land_id: u64) {
+    for offer in offers_for_land(land_id).into_iter().filter(Offer::is_open) {
+        close(env, env.caller(), offer, OfferStatus::Cancelled, AuditEventKind::OfferCancelled);
+    }
+}
+
+fn settle(env: &impl Environment, caller: Principal, mut offer: Offer, land: LandParcel, price: u64) -> Result<LandParcel, String> {
+    if land.owner == offer.buyer {
+        return Err("The buyer already owns this parcel".to_string());
+    }
+
+    // The released funds cover the price, or the balance check above made sure the rest is there.
+    // The offer is closed first so the sale does not cancel it along with the others.
+    let seller = land.owner;
+    let open = offer.clone();
+    escrow::release(env, offer.hold_id)?;
+
+    let before = summarize(&offer);
+    offer.status = OfferStatus::Accepted;
+    offer.closed_at = Some(env.time());
+    offer.price_paid = Some(price);
+    store(&offer);
+
+    let land = match lands::complete_sale(env, land, offer.buyer, price, None) {
+        Ok(land) => land,
+        Err(e) => {
+            // Put the funds back on hold so the offer stays open as it was
+            let hold_id = escrow::hold(env, open.buyer, open.amount, open.land_id, HoldPurpose::Offer(open.id))?;
+            store(&Offer { hold_id, ..open });
+            return Err(e);
+        },
+    };
+
+    audit::record(env, caller, AuditEventKind::OfferAccepted, AuditDetails {
+        land_id: Some(offer.land_id),
+        subject: Some(if caller == offer.buyer { seller } else { offer.buyer }),
+        amount: Some(price),
+        before: Some(before),
+        after: Some(summarize(&offer)),
+    });
+
+    Ok(land)
+}
+
+fn close(env: &impl Environment, caller: Principal, mut offer: Offer, status: OfferStatus, kind: AuditEventKind) -> Offer {
+    // An open offer always has its hold. If it is gone there is nothing to return, and the offer
+    // still closes rather than staying open with a dead hold.
+    let _ = escrow::release(env, offer.hold_id);
+
+    let before = summarize(&offer);
+    offer.status = status;
+    offer.closed_at = Some(env.time());
+    store(&offer);
+
+    audit::record(env, caller, kind, AuditDetails {
+        land_id: Some(offer.land_id),
+        subject: Some(offer.buyer),
+        amount: Some(offer.amount),
+        before: Some(before),
+        after: Some(summarize(&offer)),
+    });
+
+    offer
+}
+
+fn open_offer(env: &impl Environment, offer_id: u64) -> Result<Offer, String> {
+    let offer = get_offer(offer_id).ok_or("Offer not found")?;
+
+    if !offer.is_open() {
+        return Err(format!("Offer is {:?}", offer.status));
+    }
+
+    // The expiry sweep may not have run yet
+    if offer.expires_at <= env.time() {
+        return Err("Offer has expired".to_string());
+    }
+
+    Ok(offer)
+}
+
+fn owned_land(owner: Principal, land_id: u64) -> Result<LandParcel, String> {
+    let land = LANDS.with(|lands| lands.borrow().get(&land_id)).ok_or("Land parcel not found")?;
+
+    if land.owner != owner {
+        return Err("Only the owner can respond to offers".to_string());
+    }
+
+    ensure_can_trade(&land)?;
+    Ok(land)
+}
+
+// Offers are only possible on verified parcels that nothing else has a claim on
+fn ensure_can_trade(land: &LandParcel) -> Result<(), String> {
+    if !matches!(land.status, LandStatus::Verified | LandStatus::ForSale) {
+        return Err("Offers can only be made on verified land".to_string());
+    }
+
+    lands::ensure_not_frozen(land)?;
+    tax::ensure_not_reclaiming(land.id)
+}
+
+fn store(offer: &Offer) {
+    OFFERS.with(|offers| {
+        offers.borrow_mut().insert(offer.id, offer.clone());
+    });
+}
+
+fn summarize(offer: &Offer) -> String {
+    let counter = match &offer.counter {
+        Some(counter) => counter.amount.to_string(),
+        None => "none".to_string(),
+    };
+
+    format!("status={:?} amount={} counter={}", offer.status, offer.amount, counter)
+}
@@ -0,0 +1,270 @@
+use candid::CandidType;
+use ic_stable_structures::Storable;
+use registry_common::env::Environment;
+use serde::{Serialize, Deserialize as SerdeDeserialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use crate::{LandParcel, LANDS, PRICE_HISTORY};
+
+// Number of comparable sales a valuation is based on
+const COMPARABLES: usize = 5;
+// Below this many same-zoning sales the whole market is used instead
+const MIN_SAME_ZONING_COMPARABLES: usize = 3;
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug, PartialEq)]
+pub enum PricePointKind {
+    Listed,
+    Sold,
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone)]
+pub struct PricePoint {
+    pub land_id: u64,
+    pub kind: PricePointKind,
+    pub price: u64, // in e8s
+    pub timestamp: u64,
+    pub size: f64, // Parcel size at the time, so later edits do not skew history
+    pub zoning: Option<String>,
+}
+
+impl Storable for PricePoint {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone)]
+pub struct BoundingBox {
+    pub min_lat: f64,
+    pub max_lat: f64,
+    pub min_lon: f64,
+    pub max_lon: f64,
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Default)]
+pub struct MarketFilter {
+    pub zoning: Option<String>,
+    pub area: Option<BoundingBox>,
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize)]
+pub struct MarketStats {
+    pub sales: u64,
+    pub median_price_per_unit: Option<f64>, // e8s per square unit
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize)]
+pub struct ValuationEstimate {
+    pub land_id: u64,
+    pub estimated_price: u64, // in e8s
+    pub median_price_per_unit: f64,
+    pub comparables: Vec<u64>, // Land ids of the sales the estimate is based on
+    pub same_zoning: bool, // False when too few sales shared the parcel's zoning
+}
+
+pub fn record_listing(env: &impl Environment, land: &LandParcel, price: u64) {
+    record(env, land, PricePointKind::Listed, price);
+}
+
+pub fn record_sale(env: &impl Environment, land: &LandParcel, price: u64) {
+    record(env, land, PricePointKind::Sold, price);
+}
+
+fn record(env: &impl Environment, land: &LandParcel, kind: PricePointKind, price: u64) {
+    PRICE_HISTORY.with(|history| {
+        let mut history = history.borrow_mut();
+        let seq = history
+            .range((land.id, 0)..=(land.id, u64::MAX))
+            .next_back()
+            .map(|((_, seq), _)| seq + 1)
+            .unwrap_or(0);
+
+        history.insert((land.id, seq), PricePoint {
+            land_id: land.id,
+            kind,
+            price,
+            timestamp: env.time(),
+            size: land.size,
+            zoning: zoning_class(&land.metadata),
+        });
+    });
+}
+
+pub fn history(land_id: u64) -> Vec<PricePoint> {
+    PRICE_HISTORY.with(|history| {
+        history
+            .borrow()
+            .range((land_id, 0)..=(land_id, u64::MAX))
+            .map(|(_, point)| point)
+            .collect()
+    })
+}
+
+pub fn last_sale(land_id: u64) -> Option<PricePoint> {
+    history(land_id)
+        .into_iter()
+        .rev()
+        .find(|point| point.kind == PricePointKind::Sold)
+}
+
+pub fn market_stats(filter: &MarketFilter) -> MarketStats {
+    let zoning = filter.zoning.as_ref().map(|zoning| zoning.to_lowercase());
+    let coordinates = parcel_coordinates();
+
+    let prices: Vec<f64> = latest_sales()
+        .into_values()
+        .filter(|sale| zoning.is_none() || sale.zoning == zoning)
+        .filter(|sale| match &filter.area {
+            Some(area) => coordinates.get(&sale.land_id).is_some_and(|point| area.contains(*point)),
+            None => true,
+        })
+        .filter_map(|sale| price_per_unit(&sale))
+        .collect();
+
+    MarketStats {
+        sales: prices.len() as u64,
+        median_price_per_unit: median(prices),
+    }
+}
+
+/// Values a parcel from the most recent sales of its nearest comparable parcels.
+pub fn estimate(land_id: u64) -> Result<ValuationEstimate, String> {
+    let land = LANDS.with(|lands| lands.borrow().get(&land_id))
+        .ok_or("Land parcel not found")?;
+    let zoning = zoning_class(&land.metadata);
+    let coordinates = parcel_coordinates();
+
+    let sales: Vec<PricePoint> = latest_sales()
+        .into_values()
+        .filter(|sale| sale.land_id != land_id && price_per_unit(sale).is_some())
+        .collect();
+
+    // Prefer parcels with the same zoning, but fall back to the whole market if there are too few
+    let same_zoning: Vec<PricePoint> = sales
+        .iter()
+        .filter(|sale| zoning.is_some() && sale.zoning == zoning)
+        .cloned()
+        .collect();
+    let (mut candidates, same_zoning) = if same_zoning.len() >= MIN_SAME_ZONING_COMPARABLES {
+        (same_zoning, true)
+    } else {
+        (sales, false)
+    };
+
+    if candidates.is_empty() {
+        return Err("No comparable sales recorded yet".to_string());
+    }
+
+    // Nearest first when locations are known, otherwise most recent first
+    let origin = parse_coordinates(&land.coordinates);
+    candidates.sort_by(|a, b| {
+        let distance_a = origin.zip(coordinates.get(&a.land_id).copied()).map(|(o, p)| distance(o, p));
+        let distance_b = origin.zip(coordinates.get(&b.land_id).copied()).map(|(o, p)| distance(o, p));
+        match (distance_a, distance_b) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => b.timestamp.cmp(&a.timestamp),
+        }
+    });
+    candidates.truncate(COMPARABLES);
+
+    let median_price_per_unit = median(candidates.iter().filter_map(price_per_unit).collect())
+        .ok_or("No comparable sales recorded yet")?;
+
+    Ok(ValuationEstimate {
+        land_id,
+        estimated_price: (median_price_per_unit * land.size).round() as u64,
+        median_price_per_unit,
+        comparables: candidates.iter().map(|sale| sale.land_id).collect(),
+        same_zoning,
+    })
+}
+
+/// Extracts the zoning class from free-form metadata such as "Commercial zoning, high traffic area".
+pub fn zoning_class(metadata: &str) -> Option<String> {
+    metadata
+        .split(',')
+        .map(|part| part.trim().to_lowercase())
+        .find_map(|part| {
+            part.strip_suffix("zoning")
+                .map(|class| class.trim().to_string())
+                .filter(|class| !class.is_empty())
+        })
+}
+
+/// Parses "lat, lon" coordinates. Other coordinate formats have no location.
+pub fn parse_coordinates(coordinates: &str) -> Option<(f64, f64)> {
+    let (lat, lon) = coordinates.split_once(',')?;
+    let lat: f64 = lat.trim().parse().ok()?;
+    let lon: f64 = lon.trim().parse().ok()?;
+
+    if lat.is_finite() && lon.is_finite() {
+        Some((lat, lon))
+    } else {
+        None
+    }
+}
+
+impl BoundingBox {
+    fn contains(&self, (lat, lon): (f64, f64)) -> bool {
+        lat >= self.min_lat && lat <= self.max_lat && lon >= self.min_lon && lon <= self.max_lon
+    }
+}
+
+// The most recent sale of every parcel, so frequently traded parcels do not dominate
+fn latest_sales() -> BTreeMap<u64, PricePoint> {
+    PRICE_HISTORY.with(|history| {
+        let mut sales = BTreeMap::new();
+        for (_, point) in history.borrow().iter() {
+            if point.kind == PricePointKind::Sold {
+                sales.insert(point.land_id, point);
+            }
+        }
+        sales
+    })
+}
+
+fn parcel_coordinates() -> BTreeMap<u64, (f64, f64)> {
+    LANDS.with(|lands| {
+        lands
+            .borrow()
+            .iter()
+            .filter_map(|(id, land)| parse_coordinates(&land.coordinates).map(|point| (id, point)))
+            .collect()
+    })
+}
+
+fn price_per_unit(point: &PricePoint) -> Option<f64> {
+    if point.size > 0.0 {
+        Some(point.price as f64 / point.size)
+    } else {
+        None
+    }
+}
+
+pub(crate) fn median(mut values: Vec<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+
+    values.sort_by(|a, b| a.total_cmp(b));
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        Some((values[middle - 1] + values[middle]) / 2.0)
+    } else {
+        Some(values[middle])
+    }
+}
+
+// Planar distance is good enough for ranking nearby parcels
+fn distance((lat_a, lon_a): (f64, f64), (lat_b, lon_b): (f64, f64)) -> f64 {
+    ((lat_a - lat_b).powi(2) + (lon_a - lon_b).powi(2)).sqrt()
+}
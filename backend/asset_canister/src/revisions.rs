@@ -0,0 +1,151 @@
+//! Owner edits of parcel details.
+//!
+//! Cosmetic fields (description, metadata) change immediately; images are cosmetic as well and are
+//! managed through the `images` module. Material fields (coordinates, size) change what was
+//! verified, so editing them sends the parcel back to `Pending` and takes it off the market. Every
+//! edit keeps the values it replaced as a revision.
+
+use candid::{CandidType, Principal};
+use ic_stable_structures::Storable;
+use registry_common::env::Environment;
+use registry_common::validation::{self, LandFields};
+use serde::{Serialize, Deserialize as SerdeDeserialize};
+use std::borrow::Cow;
+
+use crate::audit::{self, AuditDetails, AuditEventKind};
+use crate::{config, lands, tax, LandParcel, LandStatus, LANDS, LAND_REVISIONS};
+
+#[derive(CandidType, Serialize, SerdeDeserialize)]
+pub struct LandDetailsUpdate {
+    pub description: Option<String>, // None leaves the field unchanged
+    pub metadata: Option<String>,
+    pub coordinates: Option<String>,
+    pub size: Option<f64>,
+}
+
+// The values an edit replaced, so earlier versions of a parcel can be reconstructed
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone)]
+pub struct LandRevision {
+    pub land_id: u64,
+    pub revision: u64, // Sequence number per parcel, starting at 1
+    pub editor: Principal,
+    pub timestamp: u64,
+    pub description: String,
+    pub metadata: String,
+    pub coordinates: String,
+    pub size: f64,
+    pub status: LandStatus, // Status before the edit
+    pub material: bool, // Whether the edit required re-verification
+}
+
+impl Storable for LandRevision {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+pub fn update_land_details(env: &impl Environment, land_id: u64, update: LandDetailsUpdate) -> Result<LandParcel, String> {
+    let principal = env.caller();
+
+    let mut land = LANDS.with(|lands| lands.borrow().get(&land_id)).ok_or("Land parcel not found")?;
+
+    if land.owner != principal {
+        return Err("Only the owner can update land details".to_string());
+    }
+
+    lands::ensure_not_frozen(&land)?;
+    tax::ensure_not_reclaiming(land_id)?;
+
+    let description = update.description.unwrap_or_else(|| land.description.clone());
+    let metadata = update.metadata.unwrap_or_else(|| land.metadata.clone());
+    let coordinates = update.coordinates.unwrap_or_else(|| land.coordinates.clone());
+    let size = update.size.unwrap_or(land.size);
+
+    let fields = LandFields {
+        coordinates: &coordinates,
+        size,
+        description: &description,
+        metadata: &metadata,
+    };
+    validation::validate_land(&fields, &config::validation_limits())?;
+
+    let material = coordinates != land.coordinates || size != land.size;
+    if !material && description == land.description && metadata == land.metadata {
+        return Err("No details were changed".to_string());
+    }
+
+    let now = env.time();
+    let revision = LandRevision {
+        land_id,
+        revision: last_revision(land_id) + 1,
+        editor: principal,
+        timestamp: now,
+        description: std::mem::replace(&mut land.description, description),
+        metadata: std::mem::replace(&mut land.metadata, metadata),
+        coordinates: std::mem::replace(&mut land.coordinates, coordinates),
+        size: std::mem::replace(&mut land.size, size),
+        status: land.status.clone(),
+        material,
+    };
+
+    let before = audit::summarize_land(&land);
+    if material {
+        land.status = LandStatus::Pending;
+        land.verified_by = None;
+        land.price = None;
+        land.listing_expires_at = None;
+    }
+    land.updated_at = now;
+
+    LANDS.with(|lands| {
+        lands.borrow_mut().insert(land_id, land.clone());
+    });
+    LAND_REVISIONS.with(|revisions| {
+        revisions.borrow_mut().insert((land_id, revision.revision), revision.clone());
+    });
+
+    audit::record(env, principal, AuditEventKind::LandDetailsUpdated, AuditDetails {
+        land_id: Some(land_id),
+        before: Some(format!("{} revision={} material={}", before, revision.revision, material)),
+        after: Some(audit::summarize_land(&land)),
+        ..Default::default()
+    });
+
+    Ok(land)
+}
+
+/// The parcel's revisions, oldest first.
+pub fn revisions_of(land_id: u64) -> Vec<LandRevision> {
+    LAND_REVISIONS.with(|revisions| {
+        revisions
+            .borrow()
+            .range((land_id, 0)..=(land_id, u64::MAX))
+            .map(|(_, revision)| revision)
+            .collect()
+    })
+}
+
+/// Whether the parcel is pending because the owner changed it after it had been reviewed. The
+/// registration fee only covers the first review, so such parcels get no refund when rejected.
+pub fn was_resubmitted(land_id: u64) -> bool {
+    revisions_of(land_id)
+        .iter()
+        .any(|revision| revision.material && !matches!(revision.status, LandStatus::Pending))
+}
+
+fn last_revision(land_id: u64) -> u64 {
+    LAND_REVISIONS.with(|revisions| {
+        revisions
+            .borrow()
+            .range((land_id, 0)..=(land_id, u64::MAX))
+            .last()
+            .map(|((_, revision), _)| revision)
+            .unwrap_or(0)
+    })
+}
@@ -0,0 +1,123 @@
+use candid::{CandidType, Principal};
+use registry_common::env::Environment;
+use serde::{Serialize, Deserialize as SerdeDeserialize};
+
+use crate::treasury::BASIS_POINTS;
+use crate::wallets::{self, WalletDetails, WalletTransactionKind};
+
+// Royalties above this share would leave sellers with little incentive to resell
+pub const MAX_ROYALTY_BPS: u16 = 2_500;
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug)]
+pub struct RoyaltyConfig {
+    pub recipient: Principal, // Original registrant or a designated world operator
+    pub basis_points: u16,
+}
+
+/// The commission the marketplace takes from the seller's share of a sale it brokered.
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug)]
+pub struct MarketplaceFee {
+    pub amount: u64, // in e8s
+    pub collector: Principal,
+}
+
+/// How the proceeds of a sale were split, recorded on the transfer it paid for.
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug)]
+pub struct SaleSettlement {
+    pub price: u64, // in e8s
+    pub royalty_recipient: Option<Principal>,
+    pub royalty_amount: u64,
+    pub seller_proceeds: u64, // Net of the royalty and any marketplace fee
+    pub marketplace_fee: Option<u64>, // Only set for marketplace sales that charged one
+}
+
+pub fn validate_royalty(royalty: &RoyaltyConfig) -> Result<(), String> {
+    if royalty.recipient == Principal::anonymous() {
+        return Err("Royalty recipient cannot be anonymous".to_string());
+    }
+
+    if royalty.basis_points > MAX_ROYALTY_BPS {
+        return Err(format!("Royalty cannot exceed {} basis points", MAX_ROYALTY_BPS));
+    }
+
+    Ok(())
+}
+
+/// Splits a sale price between the royalty recipient and the seller.
+pub fn split_proceeds(price: u64, seller: Principal, royalty: Option<&RoyaltyConfig>) -> SaleSettlement {
+    // A registrant selling their own parcel is a primary sale and pays no royalty
+    let royalty = royalty.filter(|royalty| royalty.recipient != seller && royalty.basis_points > 0);
+
+    match royalty {
+        Some(royalty) => {
+            let royalty_amount = (price as u128 * royalty.basis_points as u128 / BASIS_POINTS as u128) as u64;
+            SaleSettlement {
+                price,
+                royalty_recipient: Some(royalty.recipient),
+                royalty_amount,
+                seller_proceeds: price - royalty_amount,
+                marketplace_fee: None,
+            }
+        },
+        None => SaleSettlement {
+            price,
+            royalty_recipient: None,
+            royalty_amount: 0,
+            seller_proceeds: price,
+            marketplace_fee: None,
+        },
+    }
+}
+
+/// Splits a sale price as `split_proceeds` does and takes the marketplace fee out of the seller's
+/// share.
+pub fn split_with_fee(price: u64, seller: Principal, royalty: Option<&RoyaltyConfig>, fee: Option<&MarketplaceFee>) -> Result<SaleSettlement, String> {
+    let mut settlement = split_proceeds(price, seller, royalty);
+
+    if let Some(fee) = fee.filter(|fee| fee.amount > 0) {
+        if fee.amount > settlement.seller_proceeds {
+            return Err("The marketplace fee exceeds the seller's proceeds".to_string());
+        }
+
+        settlement.seller_proceeds -= fee.amount;
+        settlement.marketplace_fee = Some(fee.amount);
+    }
+
+    Ok(settlement)
+}
+
+/// Debits the buyer and pays the seller, the royalty recipient and the marketplace in one step.
+pub fn settle_sale(env: &impl Environment, buyer: Principal, seller: Principal, land_id: u64, price: u64, royalty: Option<&RoyaltyConfig>, fee: Option<&MarketplaceFee>) -> Result<SaleSettlement, String> {
+    let settlement = split_with_fee(price, seller, royalty, fee)?;
+
+    // Debit first, so an insufficient balance leaves every wallet untouched
+    wallets::debit(env, buyer, price, WalletTransactionKind::Purchase, WalletDetails {
+        counterparty: Some(seller),
+        land_id: Some(land_id),
+        ..Default::default()
+    })?;
+
+    wallets::credit(env, seller, settlement.seller_proceeds, WalletTransactionKind::Sale, WalletDetails {
+        counterparty: Some(buyer),
+        land_id: Some(land_id),
+        ..Default::default()
+    });
+
+    if let Some(recipient) = settlement.royalty_recipient {
+        wallets::credit(env, recipient, settlement.royalty_amount, WalletTransactionKind::RoyaltyIncome, WalletDetails {
+            counterparty: Some(buyer),
+            land_id: Some(land_id),
+            ..Default::default()
+        });
+    }
+
+    if let (Some(fee), Some(amount)) = (fee, settlement.marketplace_fee) {
+        wallets::credit(env, fee.collector, amount, WalletTransactionKind::MarketplaceFee, WalletDetails {
+            counterparty: Some(seller),
+            land_id: Some(land_id),
+            ..Default::default()
+        });
+    }
+
+    Ok(settlement)
+}
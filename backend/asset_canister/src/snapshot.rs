@@ -0,0 +1,406 @@
+use candid::{CandidType, Principal};
+use registry_common::env::Environment;
+use registry_common::snapshot::{sha256_hex, Restore, Snapshot, SnapshotManifest, SnapshotSection};
+use serde::{Serialize, Deserialize as SerdeDeserialize};
+use std::cell::RefCell;
+
+use crate::audit::AuditEvent;
+use crate::escrow::{self, EscrowHold};
+use crate::images::{self, ImageInfo};
+use crate::inheritance::{self, InheritancePlan};
+use crate::offers::Offer;
+use crate::pricing::PricePoint;
+use crate::revisions::LandRevision;
+use crate::subscriptions::{self, Subscription};
+use crate::tax::TaxAccount;
+use crate::treasury::TreasuryEntry;
+use crate::wallets::WalletTransaction;
+use crate::LandParcel;
+use crate::{AUDIT_LOG, CONFIG, ESCROW_HOLDS, IMAGES, IMAGE_CHUNKS, INHERITANCE_PLANS, LANDS, LAND_ID_COUNTER, LAND_REVISIONS, LAST_ACTIVITY, OFFERS, PRICE_HISTORY, SUBSCRIPTIONS, TAX_ACCOUNTS, TREASURY_LEDGER, VERIFIERS, WALLETS, WALLET_TRANSACTIONS};
+
+const CANISTER_NAME: &str = "land_registry";
+
+// Everything the registry persists. Keys that can be derived from the values are not stored twice.
+// Stores added after the first snapshot format are optional, so older snapshots still restore.
+#[derive(CandidType, Serialize, SerdeDeserialize)]
+struct RegistryState {
+    last_land_id: u64,
+    lands: Vec<LandParcel>,
+    verifiers: Vec<Principal>,
+    wallets: Vec<(Principal, u64)>,
+    wallet_transactions: Vec<WalletTransaction>,
+    treasury_ledger: Vec<TreasuryEntry>,
+    price_history: Vec<(u64, PricePoint)>, // (sequence, point)
+    audit_log: Vec<AuditEvent>,
+    subscriptions: Vec<Subscription>,
+    config: Vec<(String, String)>,
+    inheritance_plans: Option<Vec<InheritancePlan>>,
+    last_activity: Option<Vec<(Principal, u64)>>,
+    tax_accounts: Option<Vec<TaxAccount>>,
+    last_image_id: Option<u64>,
+    images: Option<Vec<ImageInfo>>,
+    // Only in older snapshots; image bytes are now streamed separately, see `image_chunk`
+    image_chunks: Option<Vec<(u64, u32, Vec<u8>)>>, // (image id, chunk index, bytes)
+    land_revisions: Option<Vec<LandRevision>>,
+    offers: Option<Vec<Offer>>,
+    escrow_holds: Option<Vec<EscrowHold>>,
+    last_hold_id: Option<u64>,
+    last_subscription_id: Option<u64>,
+}
+
+/// An image whose bytes are downloaded and uploaded separately from the snapshot buffer.
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug, PartialEq)]
+pub struct SnapshotImage {
+    pub image_id: u64,
+    pub chunk_count: u32,
+}
+
+thread_local! {
+    // Held on the heap only; an upgrade discards an unfinished download or restore
+    static SNAPSHOT: RefCell<Option<Snapshot>> = const { RefCell::new(None) };
+    static SNAPSHOT_IMAGES: RefCell<Vec<SnapshotImage>> = const { RefCell::new(Vec::new()) };
+    static RESTORE: RefCell<Option<Restore>> = const { RefCell::new(None) };
+}
+
+/// Captures the registry state in one message and keeps it ready for download.
+///
+/// Image bytes would not fit in one message alongside everything else, so the snapshot only
+/// carries their metadata. Finished images are immutable; their chunks are read straight from
+/// stable memory by `image_chunk` and uploaded again after the restore with `upload_image_chunk`.
+pub fn create(env: &impl Environment) -> Result<SnapshotManifest, String> {
+    let state = RegistryState {
+        last_land_id: LAND_ID_COUNTER.with(|counter| counter.borrow().get(&0).unwrap_or(0)),
+        lands: LANDS.with(|lands| lands.borrow().iter().map(|(_, land)| land).collect()),
+        verifiers: VERIFIERS.with(|verifiers| {
+            verifiers.borrow().iter().filter(|(_, active)| *active).map(|(verifier, _)| verifier).collect()
+        }),
+        wallets: WALLETS.with(|wallets| wallets.borrow().iter().collect()),
+        wallet_transactions: WALLET_TRANSACTIONS.with(|transactions| {
+            transactions.borrow().iter().map(|(_, transaction)| transaction).collect()
+        }),
+        treasury_ledger: TREASURY_LEDGER.with(|ledger| ledger.borrow().iter().map(|(_, entry)| entry).collect()),
+        price_history: PRICE_HISTORY.with(|history| {
+            history.borrow().iter().map(|((_, seq), point)| (seq, point)).collect()
+        }),
+        audit_log: AUDIT_LOG.with(|log| log.borrow().iter().map(|(_, event)| event).collect()),
+        subscriptions: SUBSCRIPTIONS.with(|subscriptions| {
+            subscriptions.borrow().iter().map(|(_, subscription)| subscription).collect()
+        }),
+        config: CONFIG.with(|config| config.borrow().iter().collect()),
+        inheritance_plans: Some(INHERITANCE_PLANS.with(|plans| plans.borrow().iter().map(|(_, plan)| plan).collect())),
+        last_activity: Some(LAST_ACTIVITY.with(|activity| activity.borrow().iter().collect())),
+        tax_accounts: Some(TAX_ACCOUNTS.with(|accounts| accounts.borrow().iter().map(|(_, account)| account).collect())),
+        last_image_id: LAND_ID_COUNTER.with(|counter| counter.borrow().get(&images::IMAGE_ID_KEY)),
+        images: Some(IMAGES.with(|images| images.borrow().iter().map(|(_, image)| image).collect())),
+        image_chunks: None,
+        land_revisions: Some(LAND_REVISIONS.with(|revisions| revisions.borrow().iter().map(|(_, revision)| revision).collect())),
+        offers: Some(OFFERS.with(|offers| offers.borrow().iter().map(|(_, offer)| offer).collect())),
+        escrow_holds: Some(ESCROW_HOLDS.with(|holds| holds.borrow().iter().map(|(_, hold)| hold).collect())),
+        last_hold_id: LAND_ID_COUNTER.with(|counter| counter.borrow().get(&escrow::HOLD_ID_KEY)),
+        last_subscription_id: LAND_ID_COUNTER.with(|counter| counter.borrow().get(&subscriptions::SUBSCRIPTION_ID_KEY)),
+    };
+
+    let images: Vec<SnapshotImage> = state
+        .images
+        .iter()
+        .flatten()
+        .map(|image| SnapshotImage { image_id: image.id, chunk_count: image.chunk_count })
+        .collect();
+    let image_chunk_count: u64 = images.iter().map(|image| image.chunk_count as u64).sum();
+
+    let sections = vec![
+        section("lands", state.lands.len()),
+        section("verifiers", state.verifiers.len()),
+        section("wallets", state.wallets.len()),
+        section("wallet_transactions", state.wallet_transactions.len()),
+        section("treasury_ledger", state.treasury_ledger.len()),
+        section("price_history", state.price_history.len()),
+        section("audit_log", state.audit_log.len()),
+        section("subscriptions", state.subscriptions.len()),
+        section("config", state.config.len()),
+        section("inheritance_plans", state.inheritance_plans.as_ref().map_or(0, Vec::len)),
+        section("last_activity", state.last_activity.as_ref().map_or(0, Vec::len)),
+        section("tax_accounts", state.tax_accounts.as_ref().map_or(0, Vec::len)),
+        section("images", state.images.as_ref().map_or(0, Vec::len)),
+        section("image_chunks", image_chunk_count as usize),
+        section("land_revisions", state.land_revisions.as_ref().map_or(0, Vec::len)),
+        section("offers", state.offers.as_ref().map_or(0, Vec::len)),
+        section("escrow_holds", state.escrow_holds.as_ref().map_or(0, Vec::len)),
+    ];
+
+    let bytes = candid::encode_one(&state).map_err(|e| format!("Failed to encode snapshot: {}", e))?;
+    let snapshot = Snapshot::new(CANISTER_NAME, env.time(), bytes, sections);
+    let manifest = snapshot.manifest().clone();
+
+    SNAPSHOT.with(|current| *current.borrow_mut() = Some(snapshot));
+    SNAPSHOT_IMAGES.with(|current| *current.borrow_mut() = images);
+    Ok(manifest)
+}
+
+pub fn chunk(snapshot_id: u64, index: u32) -> Result<Vec<u8>, String> {
+    SNAPSHOT.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .ok_or("No snapshot has been created")?
+            .chunk(snapshot_id, index)
+    })
+}
+
+/// The images whose chunks have to be downloaded alongside the snapshot.
+pub fn images(snapshot_id: u64) -> Result<Vec<SnapshotImage>, String> {
+    ensure_current(snapshot_id)?;
+    Ok(SNAPSHOT_IMAGES.with(|images| images.borrow().clone()))
+}
+
+pub fn image_chunk(snapshot_id: u64, image_id: u64, index: u32) -> Result<Vec<u8>, String> {
+    ensure_current(snapshot_id)?;
+
+    let image = SNAPSHOT_IMAGES
+        .with(|images| images.borrow().iter().find(|image| image.image_id == image_id).cloned())
+        .ok_or("Image is not part of the snapshot")?;
+    if index >= image.chunk_count {
+        return Err(format!("Chunk {} is out of range, the image has {} chunks", index, image.chunk_count));
+    }
+
+    IMAGE_CHUNKS
+        .with(|chunks| chunks.borrow().get(&(image_id, index)))
+        .ok_or_else(|| "Image has been removed since the snapshot was taken".to_string())
+}
+
+fn ensure_current(snapshot_id: u64) -> Result<(), String> {
+    let current = SNAPSHOT.with(|current| current.borrow().as_ref().map(|snapshot| snapshot.manifest().snapshot_id));
+
+    match current {
+        None => Err("No snapshot has been created".to_string()),
+        Some(current) if current != snapshot_id => Err("Snapshot has been replaced by a newer one".to_string()),
+        Some(_) => Ok(()),
+    }
+}
+
+/// Starts a restore. Only allowed into a registry that holds no state yet.
+pub fn begin_restore(manifest: SnapshotManifest) -> Result<(), String> {
+    if !is_empty() {
+        return Err("State can only be restored into an empty registry".to_string());
+    }
+
+    let restore = Restore::new(manifest, CANISTER_NAME)?;
+    RESTORE.with(|current| *current.borrow_mut() = Some(restore));
+    Ok(())
+}
+
+/// Returns the number of chunks still missing.
+pub fn upload_chunk(index: u32, chunk: Vec<u8>) -> Result<u32, String> {
+    RESTORE.with(|current| {
+        current
+            .borrow_mut()
+            .as_mut()
+            .ok_or("No restore is in progress")?
+            .push_chunk(index, chunk)
+    })
+}
+
+/// Verifies the uploaded snapshot and writes it into stable memory.
+pub fn finish_restore() -> Result<SnapshotManifest, String> {
+    let restore = RESTORE.with(|current| current.borrow_mut().take())
+        .ok_or("No restore is in progress")?;
+    let manifest = restore.manifest().clone();
+
+    // Checked again in case state was written while the chunks were uploading
+    if !is_empty() {
+        return Err("State can only be restored into an empty registry".to_string());
+    }
+
+    let bytes = restore.finish()?;
+    let state: RegistryState = candid::decode_one(&bytes).map_err(|e| format!("Failed to decode snapshot: {}", e))?;
+
+    LAND_ID_COUNTER.with(|counter| {
+        counter.borrow_mut().insert(0, state.last_land_id);
+    });
+    let id_counters = [
+        (images::IMAGE_ID_KEY, state.last_image_id),
+        (escrow::HOLD_ID_KEY, state.last_hold_id),
+        (subscriptions::SUBSCRIPTION_ID_KEY, state.last_subscription_id),
+    ];
+    for (key, last_id) in id_counters {
+        if let Some(last_id) = last_id {
+            LAND_ID_COUNTER.with(|counter| {
+                counter.borrow_mut().insert(key, last_id);
+            });
+        }
+    }
+    LANDS.with(|lands| {
+        let mut lands = lands.borrow_mut();
+        for land in state.lands {
+            lands.insert(land.id, land);
+        }
+    });
+    VERIFIERS.with(|verifiers| {
+        let mut verifiers = verifiers.borrow_mut();
+        for verifier in state.verifiers {
+            verifiers.insert(verifier, true);
+        }
+    });
+    WALLETS.with(|wallets| {
+        let mut wallets = wallets.borrow_mut();
+        for (owner, balance) in state.wallets {
+            wallets.insert(owner, balance);
+        }
+    });
+    WALLET_TRANSACTIONS.with(|transactions| {
+        let mut transactions = transactions.borrow_mut();
+        for transaction in state.wallet_transactions {
+            transactions.insert((transaction.owner, transaction.id), transaction);
+        }
+    });
+    TREASURY_LEDGER.with(|ledger| {
+        let mut ledger = ledger.borrow_mut();
+        for entry in state.treasury_ledger {
+            ledger.insert(entry.id, entry);
+        }
+    });
+    PRICE_HISTORY.with(|history| {
+        let mut history = history.borrow_mut();
+        for (seq, point) in state.price_history {
+            history.insert((point.land_id, seq), point);
+        }
+    });
+    AUDIT_LOG.with(|log| {
+        let mut log = log.borrow_mut();
+        for event in state.audit_log {
+            log.insert(event.id, event);
+        }
+    });
+    SUBSCRIPTIONS.with(|subscriptions| {
+        let mut subscriptions = subscriptions.borrow_mut();
+        for subscription in state.subscriptions {
+            subscriptions.insert(subscription.id, subscription);
+        }
+    });
+    CONFIG.with(|config| {
+        let mut config = config.borrow_mut();
+        for (key, value) in state.config {
+            config.insert(key, value);
+        }
+    });
+    INHERITANCE_PLANS.with(|plans| {
+        let mut plans = plans.borrow_mut();
+        for plan in state.inheritance_plans.unwrap_or_default() {
+            plans.insert((plan.owner, plan.land_id.unwrap_or(inheritance::PORTFOLIO)), plan);
+        }
+    });
+    LAST_ACTIVITY.with(|activity| {
+        let mut activity = activity.borrow_mut();
+        for (principal, timestamp) in state.last_activity.unwrap_or_default() {
+            activity.insert(principal, timestamp);
+        }
+    });
+    TAX_ACCOUNTS.with(|accounts| {
+        let mut accounts = accounts.borrow_mut();
+        for account in state.tax_accounts.unwrap_or_default() {
+            accounts.insert(account.land_id, account);
+        }
+    });
+
+    IMAGES.with(|images| {
+        let mut images = images.borrow_mut();
+        for image in state.images.unwrap_or_default() {
+            images.insert((image.land_id, image.id), image);
+        }
+    });
+    IMAGE_CHUNKS.with(|chunks| {
+        let mut chunks = chunks.borrow_mut();
+        for (image_id, index, chunk) in state.image_chunks.unwrap_or_default() {
+            chunks.insert((image_id, index), chunk);
+        }
+    });
+
+    LAND_REVISIONS.with(|revisions| {
+        let mut revisions = revisions.borrow_mut();
+        for revision in state.land_revisions.unwrap_or_default() {
+            revisions.insert((revision.land_id, revision.revision), revision);
+        }
+    });
+
+    OFFERS.with(|offers| {
+        let mut offers = offers.borrow_mut();
+        for offer in state.offers.unwrap_or_default() {
+            offers.insert(offer.id, offer);
+        }
+    });
+    ESCROW_HOLDS.with(|holds| {
+        let mut holds = holds.borrow_mut();
+        for hold in state.escrow_holds.unwrap_or_default() {
+            holds.insert(hold.id, hold);
+        }
+    });
+
+    Ok(manifest)
+}
+
+/// Appends a chunk of a restored image, in order. The image's metadata arrives with the snapshot,
+/// so its bytes are checked against the recorded size and hash once the last chunk is in.
+/// Returns the number of the image's chunks still missing.
+pub fn upload_image_chunk(image_id: u64, index: u32, chunk: Vec<u8>) -> Result<u32, String> {
+    if RESTORE.with(|current| current.borrow().is_some()) {
+        return Err("Images can only be uploaded once the restore has finished".to_string());
+    }
+
+    let image = IMAGES
+        .with(|images| images.borrow().iter().map(|(_, image)| image).find(|image| image.id == image_id))
+        .ok_or("Image not found")?;
+
+    let uploaded = images::chunk_count_of(image_id);
+    if uploaded >= image.chunk_count {
+        return Err("All of the image's chunks have already been uploaded".to_string());
+    }
+    if index != uploaded {
+        return Err(format!("Expected chunk {}", uploaded));
+    }
+    if chunk.is_empty() || chunk.len() > images::MAX_CHUNK_SIZE {
+        return Err(format!("Chunks must be between 1 and {} bytes", images::MAX_CHUNK_SIZE));
+    }
+
+    IMAGE_CHUNKS.with(|chunks| {
+        chunks.borrow_mut().insert((image_id, index), chunk);
+    });
+
+    let remaining = image.chunk_count - (index + 1);
+    if remaining == 0 {
+        let bytes = images::read_bytes(image_id);
+        if bytes.len() as u64 != image.size || sha256_hex(&bytes) != image.sha256 {
+            images::delete_chunks(image_id);
+            return Err("Image content does not match the snapshot".to_string());
+        }
+    }
+
+    Ok(remaining)
+}
+
+// Configuration may already have been set on the target canister; the snapshot's values win.
+fn is_empty() -> bool {
+    LAND_ID_COUNTER.with(|counter| counter.borrow().is_empty())
+        && LANDS.with(|lands| lands.borrow().is_empty())
+        && VERIFIERS.with(|verifiers| verifiers.borrow().is_empty())
+        && WALLETS.with(|wallets| wallets.borrow().is_empty())
+        && WALLET_TRANSACTIONS.with(|transactions| transactions.borrow().is_empty())
+        && TREASURY_LEDGER.with(|ledger| ledger.borrow().is_empty())
+        && PRICE_HISTORY.with(|history| history.borrow().is_empty())
+        && AUDIT_LOG.with(|log| log.borrow().is_empty())
+        && SUBSCRIPTIONS.with(|subscriptions| subscriptions.borrow().is_empty())
+        && INHERITANCE_PLANS.with(|plans| plans.borrow().is_empty())
+        && LAST_ACTIVITY.with(|activity| activity.borrow().is_empty())
+        && TAX_ACCOUNTS.with(|accounts| accounts.borrow().is_empty())
+        && IMAGES.with(|images| images.borrow().is_empty())
+        && IMAGE_CHUNKS.with(|chunks| chunks.borrow().is_empty())
+        && LAND_REVISIONS.with(|revisions| revisions.borrow().is_empty())
+        && OFFERS.with(|offers| offers.borrow().is_empty())
+        && ESCROW_HOLDS.with(|holds| holds.borrow().is_empty())
+}
+
+fn section(name: &str, entries: usize) -> SnapshotSection {
+    SnapshotSection {
+        name: name.to_string(),
+        entries: entries as u64,
+    }
+}
@@ -0,0 +1,247 @@
+use candid::{CandidType, Principal};
+use ic_cdk::api::call::RejectionCode;
+use ic_stable_structures::Storable;
+use registry_common::env::{CanisterEnv, Environment};
+use serde::{Serialize, Deserialize as SerdeDeserialize};
+use std::borrow::Cow;
+use std::time::Duration;
+
+use crate::audit::{AuditEvent, AuditEventKind};
+use crate::{AUDIT_LOG, LAND_ID_COUNTER, SUBSCRIPTIONS};
+
+// How often the outbox is drained
+const DELIVERY_INTERVAL: Duration = Duration::from_secs(10);
+// Upper bound on the events sent to a single subscriber per timer tick
+const MAX_EVENTS_PER_TICK: usize = 50;
+// Retry backoff after a failed delivery, doubled for every consecutive failure
+const RETRY_BASE_DELAY_NS: u64 = 30_000_000_000; // 30 seconds
+const RETRY_MAX_DELAY_NS: u64 = 3_600_000_000_000; // 1 hour
+const MAX_METHOD_NAME_LEN: usize = 128;
+// Key of the subscription id counter in LAND_ID_COUNTER, so ids are not reused after unsubscribing
+pub const SUBSCRIPTION_ID_KEY: u8 = 3;
+
+#[allow(clippy::enum_variant_names)] // Names are part of the public event contract
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug, PartialEq)]
+pub enum ParcelEventKind {
+    ParcelTransferred,
+    ParcelVerified,
+    ParcelListed,
+}
+
+impl ParcelEventKind {
+    fn from_audit(kind: &AuditEventKind) -> Option<Self> {
+        match kind {
+            AuditEventKind::LandPurchased | AuditEventKind::OwnershipTransferred | AuditEventKind::InheritanceClaimed => {
+                Some(ParcelEventKind::ParcelTransferred)
+            },
+            AuditEventKind::LandVerified => Some(ParcelEventKind::ParcelVerified),
+            AuditEventKind::LandListedForSale => Some(ParcelEventKind::ParcelListed),
+            _ => None,
+        }
+    }
+}
+
+/// Payload delivered to subscribers. The subscriber method must accept a single `ParcelEvent`.
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone)]
+pub struct ParcelEvent {
+    pub event_id: u64, // Id of the underlying audit event, strictly increasing
+    pub kind: ParcelEventKind,
+    pub land_id: u64,
+    pub actor: Principal,
+    pub counterparty: Option<Principal>, // New owner for transfers, owner for verifications
+    pub amount: Option<u64>, // Sale or listing price in e8s
+    pub timestamp: u64,
+}
+
+impl ParcelEvent {
+    fn from_audit(event: &AuditEvent) -> Option<Self> {
+        let kind = ParcelEventKind::from_audit(&event.kind)?;
+        let land_id = event.land_id?;
+
+        // Purchases and inheritance claims are recorded with the new owner as caller
+        let counterparty = match event.kind {
+            AuditEventKind::LandPurchased | AuditEventKind::InheritanceClaimed => Some(event.caller),
+            _ => event.subject,
+        };
+
+        Some(ParcelEvent {
+            event_id: event.id,
+            kind,
+            land_id,
+            actor: event.caller,
+            counterparty,
+            amount: event.amount,
+            timestamp: event.timestamp,
+        })
+    }
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone)]
+pub struct Subscription {
+    pub id: u64,
+    pub canister_id: Principal,
+    pub method: String,
+    pub event_kinds: Vec<ParcelEventKind>,
+    pub created_by: Principal,
+    pub created_at: u64,
+    pub cursor: u64, // Id of the last audit event delivered or skipped
+    pub delivered: u64,
+    pub consecutive_failures: u32,
+    pub next_attempt_at: u64,
+    pub last_error: Option<String>,
+}
+
+impl Storable for Subscription {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize)]
+pub struct SubscriptionInput {
+    pub canister_id: Principal,
+    pub method: String,
+    pub event_kinds: Vec<ParcelEventKind>,
+    pub start_after: Option<u64>, // Replay from this audit event id, defaults to the latest event
+}
+
+pub fn subscribe(env: &impl Environment, principal: Principal, input: SubscriptionInput) -> Result<Subscription, String> {
+    if input.method.is_empty() || input.method.len() > MAX_METHOD_NAME_LEN {
+        return Err("Invalid subscriber method name".to_string());
+    }
+
+    if input.event_kinds.is_empty() {
+        return Err("At least one event kind is required".to_string());
+    }
+
+    let latest_event = AUDIT_LOG.with(|log| {
+        log.borrow().last_key_value().map(|(id, _)| id).unwrap_or(0)
+    });
+    let cursor = input.start_after.unwrap_or(latest_event).min(latest_event);
+
+    SUBSCRIPTIONS.with(|subscriptions| {
+        let mut subscriptions = subscriptions.borrow_mut();
+        let id = next_subscription_id(subscriptions.last_key_value().map(|(id, _)| id));
+        let subscription = Subscription {
+            id,
+            canister_id: input.canister_id,
+            method: input.method,
+            event_kinds: input.event_kinds,
+            created_by: principal,
+            created_at: env.time(),
+            cursor,
+            delivered: 0,
+            consecutive_failures: 0,
+            next_attempt_at: 0,
+            last_error: None,
+        };
+
+        subscriptions.insert(id, subscription.clone());
+        Ok(subscription)
+    })
+}
+
+// Subscriptions created before the counter existed are taken into account
+fn next_subscription_id(last_stored: Option<u64>) -> u64 {
+    LAND_ID_COUNTER.with(|counter| {
+        let mut counter = counter.borrow_mut();
+        let next_id = counter.get(&SUBSCRIPTION_ID_KEY).unwrap_or(0).max(last_stored.unwrap_or(0)) + 1;
+        counter.insert(SUBSCRIPTION_ID_KEY, next_id);
+        next_id
+    })
+}
+
+pub fn unsubscribe(subscription_id: u64) -> Result<Subscription, String> {
+    SUBSCRIPTIONS.with(|subscriptions| {
+        subscriptions
+            .borrow_mut()
+            .remove(&subscription_id)
+            .ok_or_else(|| "Subscription not found".to_string())
+    })
+}
+
+pub fn list() -> Vec<Subscription> {
+    SUBSCRIPTIONS.with(|subscriptions| {
+        subscriptions
+            .borrow()
+            .iter()
+            .map(|(_, subscription)| subscription)
+            .collect()
+    })
+}
+
+pub fn start_delivery_timer() {
+    ic_cdk_timers::set_timer_interval(DELIVERY_INTERVAL, deliver_pending);
+}
+
+/// Drains the outbox for every subscriber that is due. Deliveries are one-way calls, so a slow
+/// or stopped subscriber can never hold up the registry or block an upgrade.
+fn deliver_pending() {
+    let now = CanisterEnv.time();
+    let due: Vec<Subscription> = SUBSCRIPTIONS.with(|subscriptions| {
+        subscriptions
+            .borrow()
+            .iter()
+            .filter(|(_, subscription)| subscription.next_attempt_at <= now)
+            .map(|(_, subscription)| subscription)
+            .collect()
+    });
+
+    for mut subscription in due {
+        deliver_to(&mut subscription, now, |canister_id, method, parcel_event| {
+            ic_cdk::notify(canister_id, method, (parcel_event,))
+        });
+
+        SUBSCRIPTIONS.with(|subscriptions| {
+            subscriptions.borrow_mut().insert(subscription.id, subscription);
+        });
+    }
+}
+
+/// Sends the subscriber the events after its cursor through `send`, advancing the cursor past
+/// every event sent or skipped. A failed send stops the batch and schedules a retry.
+pub fn deliver_to(
+    subscription: &mut Subscription,
+    now: u64,
+    mut send: impl FnMut(Principal, &str, ParcelEvent) -> Result<(), RejectionCode>,
+) {
+    let pending: Vec<AuditEvent> = AUDIT_LOG.with(|log| {
+        log.borrow()
+            .range(subscription.cursor + 1..)
+            .map(|(_, event)| event)
+            .take(MAX_EVENTS_PER_TICK)
+            .collect()
+    });
+
+    for event in pending {
+        let parcel_event = ParcelEvent::from_audit(&event)
+            .filter(|parcel_event| subscription.event_kinds.contains(&parcel_event.kind));
+
+        if let Some(parcel_event) = parcel_event {
+            if let Err(code) = send(subscription.canister_id, &subscription.method, parcel_event) {
+                subscription.consecutive_failures += 1;
+                subscription.next_attempt_at = now + retry_delay(subscription.consecutive_failures);
+                subscription.last_error = Some(format!("Delivery of event {} failed: {:?}", event.id, code));
+                return;
+            }
+
+            subscription.delivered += 1;
+        }
+
+        subscription.cursor = event.id;
+    }
+
+    subscription.consecutive_failures = 0;
+    subscription.next_attempt_at = 0;
+}
+
+pub fn retry_delay(failures: u32) -> u64 {
+    let factor = 1u64.checked_shl(failures.saturating_sub(1)).unwrap_or(u64::MAX);
+    RETRY_BASE_DELAY_NS.saturating_mul(factor).min(RETRY_MAX_DELAY_NS)
+}
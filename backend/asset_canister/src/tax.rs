@@ -0,0 +1,364 @@
+//! Periodic holding tax on parcels.
+//!
+//! A timer assesses every owned parcel once per tax period and collects what it can from the
+//! owner's wallet. Anything it cannot collect is carried as arrears on the parcel, so a new owner
+//! takes over the debt. Parcels whose arrears stay unpaid escalate from `InArrears` to
+//! `Delinquent` and finally `Reclaimable`, at which point an admin can put them up for a
+//! reclamation sale. Arrears are paid out of the sale proceeds.
+
+use candid::{CandidType, Principal};
+use ic_stable_structures::Storable;
+use registry_common::env::{CanisterEnv, Environment};
+use serde::{Serialize, Deserialize as SerdeDeserialize};
+use std::borrow::Cow;
+use std::time::Duration;
+
+use crate::audit::{self, AuditDetails, AuditEventKind};
+use crate::{is_admin, lands, pricing, treasury, wallets};
+use crate::{LandParcel, LandStatus, CONFIG, LANDS, TAX_ACCOUNTS};
+
+const TAX_POLICY_KEY: &str = "tax_policy";
+// How often parcels are checked for a due assessment
+const ASSESSMENT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const NANOS_PER_DAY: u64 = 86_400_000_000_000;
+const MAX_ZONING_RATES: usize = 32;
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug, PartialEq)]
+pub struct ZoningRate {
+    pub zoning: String, // Zoning class as found in parcel metadata, e.g. "commercial"
+    pub rate: u64, // e8s per unit of size and period
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug, Default)]
+pub struct TaxPolicy {
+    pub period_days: u32, // Zero disables assessment
+    pub default_rate: u64, // e8s per unit of size and period, for parcels without a zoning rate
+    pub zoning_rates: Vec<ZoningRate>,
+    pub delinquency_days: u32, // Arrears older than this make a parcel delinquent
+    pub reclamation_days: u32, // Further days of delinquency before a reclamation sale is allowed
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug, PartialEq)]
+pub enum TaxStanding {
+    Current,
+    InArrears,
+    Delinquent,
+    Reclaimable,
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone)]
+pub struct TaxAccount {
+    pub land_id: u64,
+    pub last_assessed_at: u64,
+    pub arrears: u64, // Unpaid tax in e8s
+    pub arrears_since: Option<u64>, // When the oldest unpaid tax fell due
+    pub standing: TaxStanding,
+    pub total_assessed: u64,
+    pub total_paid: u64,
+    pub reclamation_listed: bool, // Listed for sale by an admin to recover the arrears
+}
+
+impl Storable for TaxAccount {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+impl TaxPolicy {
+    pub fn check(&self) -> Result<(), String> {
+        if self.zoning_rates.len() > MAX_ZONING_RATES {
+            return Err(format!("At most {} zoning rates can be set", MAX_ZONING_RATES));
+        }
+
+        for (index, rate) in self.zoning_rates.iter().enumerate() {
+            if rate.zoning.trim().is_empty() || rate.zoning.trim() != rate.zoning.to_lowercase() {
+                return Err("Zoning classes must be non-empty, trimmed and lowercase".to_string());
+            }
+
+            if self.zoning_rates[..index].iter().any(|other| other.zoning == rate.zoning) {
+                return Err(format!("Zoning class {} is listed twice", rate.zoning));
+            }
+        }
+
+        if self.period_days > 0 && self.delinquency_days == 0 {
+            return Err("Delinquency period must be at least one day".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Tax due on a parcel for one period.
+    pub fn amount_due(&self, land: &LandParcel) -> u64 {
+        let zoning = pricing::zoning_class(&land.metadata);
+        let rate = self
+            .zoning_rates
+            .iter()
+            .find(|rate| Some(&rate.zoning) == zoning.as_ref())
+            .map(|rate| rate.rate)
+            .unwrap_or(self.default_rate);
+
+        (land.size * rate as f64).round() as u64
+    }
+
+    fn standing(&self, account: &TaxAccount, now: u64) -> TaxStanding {
+        let Some(since) = account.arrears_since.filter(|_| account.arrears > 0) else {
+            return TaxStanding::Current;
+        };
+
+        let overdue_days = now.saturating_sub(since) / NANOS_PER_DAY;
+        if overdue_days >= self.delinquency_days as u64 + self.reclamation_days as u64 {
+            TaxStanding::Reclaimable
+        } else if overdue_days >= self.delinquency_days as u64 {
+            TaxStanding::Delinquent
+        } else {
+            TaxStanding::InArrears
+        }
+    }
+}
+
+pub fn tax_policy() -> TaxPolicy {
+    CONFIG.with(|config| {
+        config
+            .borrow()
+            .get(&TAX_POLICY_KEY.to_string())
+            .and_then(|value| serde_json::from_str(&value).ok())
+            .unwrap_or_default()
+    })
+}
+
+pub fn set_tax_policy(policy: &TaxPolicy) -> Result<(), String> {
+    policy.check()?;
+
+    let value = serde_json::to_string(policy).map_err(|e| format!("Failed to encode tax policy: {}", e))?;
+    CONFIG.with(|config| {
+        config.borrow_mut().insert(TAX_POLICY_KEY.to_string(), value);
+    });
+
+    Ok(())
+}
+
+pub fn account(land_id: u64) -> Option<TaxAccount> {
+    TAX_ACCOUNTS.with(|accounts| accounts.borrow().get(&land_id))
+}
+
+pub fn accounts_in_standing(standing: &TaxStanding) -> Vec<TaxAccount> {
+    TAX_ACCOUNTS.with(|accounts| {
+        accounts
+            .borrow()
+            .iter()
+            .filter(|(_, account)| &account.standing == standing)
+            .map(|(_, account)| account)
+            .collect()
+    })
+}
+
+pub fn start_assessment_timer() {
+    ic_cdk_timers::set_timer_interval(ASSESSMENT_INTERVAL, || assess_due(&CanisterEnv));
+}
+
+/// Assesses every parcel whose tax period has ended and updates the standing of parcels in arrears.
+pub fn assess_due(env: &impl Environment) {
+    let policy = tax_policy();
+    if policy.period_days == 0 {
+        return;
+    }
+
+    let now = env.time();
+    let period = policy.period_days as u64 * NANOS_PER_DAY;
+    let lands: Vec<LandParcel> = LANDS.with(|lands| {
+        lands
+            .borrow()
+            .iter()
+            .filter(|(_, land)| matches!(land.status, LandStatus::Verified | LandStatus::ForSale))
+            .map(|(_, land)| land)
+            .collect()
+    });
+
+    for land in lands {
+        // Tax accrues from the first assessment run that sees the parcel
+        let Some(mut account) = account(land.id) else {
+            TAX_ACCOUNTS.with(|accounts| accounts.borrow_mut().insert(land.id, new_account(land.id, now)));
+            continue;
+        };
+
+        // Catches up on periods missed while assessment was paused
+        let periods = now.saturating_sub(account.last_assessed_at) / period;
+        if periods > 0 {
+            let tax = policy.amount_due(&land).saturating_mul(periods);
+            // The first of the missed periods fell due when it ended, however late this run is
+            let first_due_at = account.last_assessed_at + period;
+            account.last_assessed_at += periods * period;
+            account.total_assessed = account.total_assessed.saturating_add(tax);
+            account.arrears = account.arrears.saturating_add(tax);
+            if account.arrears > 0 && account.arrears_since.is_none() {
+                account.arrears_since = Some(first_due_at);
+            }
+
+            let payable = account.arrears.min(wallets::balance(&land.owner));
+            collect(env, &mut account, land.owner, payable);
+            if account.arrears == 0 {
+                end_reclamation(env, &mut account);
+            }
+        }
+
+        update_standing(env, env.canister_id(), &policy, &mut account, now);
+        TAX_ACCOUNTS.with(|accounts| accounts.borrow_mut().insert(land.id, account));
+    }
+}
+
+/// Pays off a parcel's arrears in full from the caller's wallet. Ends a running reclamation sale,
+/// also one whose arrears the assessment has already collected.
+pub fn pay_arrears(env: &impl Environment, land_id: u64) -> Result<TaxAccount, String> {
+    let principal = env.caller();
+
+    let land = LANDS.with(|lands| lands.borrow().get(&land_id)).ok_or("Land parcel not found")?;
+    if land.owner != principal {
+        return Err("Only the owner can pay a parcel's tax arrears".to_string());
+    }
+
+    let mut account = account(land_id)
+        .filter(|account| account.arrears > 0 || account.reclamation_listed)
+        .ok_or("No tax arrears are outstanding")?;
+    treasury::check_can_pay(principal, account.arrears)?;
+    let arrears = account.arrears;
+    collect(env, &mut account, principal, arrears);
+    end_reclamation(env, &mut account);
+
+    update_standing(env, principal, &tax_policy(), &mut account, env.time());
+    TAX_ACCOUNTS.with(|accounts| accounts.borrow_mut().insert(land_id, account.clone()));
+    Ok(account)
+}
+
+/// Lists a reclaimable parcel for sale on the owner's behalf. The arrears are paid from the proceeds.
+pub fn start_reclamation_sale(env: &impl Environment, land_id: u64, price: u64) -> Result<LandParcel, String> {
+    let principal = env.caller();
+
+    if !is_admin(env, &principal) {
+        return Err("Only admins can start reclamation sales".to_string());
+    }
+
+    let mut account = account(land_id).ok_or("Land parcel has never been assessed")?;
+    if account.standing != TaxStanding::Reclaimable {
+        return Err("Only parcels in reclaimable standing can be sold for their arrears".to_string());
+    }
+
+    if price < account.arrears {
+        return Err(format!("Price must cover the arrears of {} e8s", account.arrears));
+    }
+
+    LANDS.with(|lands| {
+        let mut lands = lands.borrow_mut();
+        let mut land = lands.get(&land_id).ok_or("Land parcel not found")?;
+        lands::ensure_not_frozen(&land)?;
+
+        let before = audit::summarize_land(&land);
+        land.status = LandStatus::ForSale;
+        land.price = Some(price);
+        land.listing_expires_at = None; // Runs until sold or the arrears are paid
+        land.updated_at = env.time();
+        lands.insert(land_id, land.clone());
+        pricing::record_listing(env, &land, price);
+
+        account.reclamation_listed = true;
+        TAX_ACCOUNTS.with(|accounts| accounts.borrow_mut().insert(land_id, account.clone()));
+
+        audit::record(env, principal, AuditEventKind::ReclamationSaleStarted, AuditDetails {
+            land_id: Some(land_id),
+            subject: Some(land.owner),
+            amount: Some(price),
+            before: Some(before),
+            after: Some(format!("{} arrears={}", audit::summarize_land(&land), account.arrears)),
+        });
+        Ok(land)
+    })
+}
+
+/// Collects outstanding arrears from the seller's freshly paid proceeds after a sale.
+pub fn settle_on_sale(env: &impl Environment, land_id: u64, seller: Principal) {
+    let Some(mut account) = account(land_id) else {
+        return;
+    };
+
+    let payable = account.arrears.min(wallets::balance(&seller));
+    collect(env, &mut account, seller, payable);
+    account.reclamation_listed = false;
+
+    update_standing(env, env.caller(), &tax_policy(), &mut account, env.time());
+    TAX_ACCOUNTS.with(|accounts| accounts.borrow_mut().insert(land_id, account));
+}
+
+/// Owners cannot move a parcel out from under a running reclamation sale.
+pub fn ensure_not_reclaiming(land_id: u64) -> Result<(), String> {
+    if account(land_id).is_some_and(|account| account.reclamation_listed) {
+        return Err("Land parcel is listed for a reclamation sale".to_string());
+    }
+
+    Ok(())
+}
+
+fn new_account(land_id: u64, now: u64) -> TaxAccount {
+    TaxAccount {
+        land_id,
+        last_assessed_at: now,
+        arrears: 0,
+        arrears_since: None,
+        standing: TaxStanding::Current,
+        total_assessed: 0,
+        total_paid: 0,
+        reclamation_listed: false,
+    }
+}
+
+// Takes a paid-off parcel off the reclamation sale
+fn end_reclamation(env: &impl Environment, account: &mut TaxAccount) {
+    if !account.reclamation_listed {
+        return;
+    }
+
+    account.reclamation_listed = false;
+    LANDS.with(|lands| {
+        let mut lands = lands.borrow_mut();
+        if let Some(mut land) = lands.get(&account.land_id) {
+            lands::end_listing(&mut land);
+            land.updated_at = env.time();
+            lands.insert(account.land_id, land);
+        }
+    });
+}
+
+fn collect(env: &impl Environment, account: &mut TaxAccount, payer: Principal, amount: u64) {
+    if amount == 0 {
+        return;
+    }
+
+    // The amount never exceeds the payer's balance, so the debit cannot fail
+    if treasury::collect_land_tax(env, payer, account.land_id, amount).is_ok() {
+        account.arrears -= amount;
+        account.total_paid = account.total_paid.saturating_add(amount);
+        if account.arrears == 0 {
+            account.arrears_since = None;
+        }
+    }
+}
+
+fn update_standing(env: &impl Environment, actor: Principal, policy: &TaxPolicy, account: &mut TaxAccount, now: u64) {
+    let standing = policy.standing(account, now);
+    if standing == account.standing {
+        return;
+    }
+
+    audit::record(env, actor, AuditEventKind::TaxStandingChanged, AuditDetails {
+        land_id: Some(account.land_id),
+        amount: Some(account.arrears),
+        before: Some(format!("{:?}", account.standing)),
+        after: Some(format!("{:?}", standing)),
+        ..Default::default()
+    });
+    account.standing = standing;
+}
@@ -0,0 +1,1045 @@
+// Native tests of the registry flows. Every test runs on its own thread and therefore starts
+// with empty stable structures.
+
+use candid::Principal;
+use ic_cdk::api::call::RejectionCode;
+use registry_common::env::{Environment, TestEnv};
+use registry_common::snapshot::sha256_hex;
+use registry_common::title::{self, TitleCertificate};
+
+use crate::admin;
+use crate::audit::{self, AuditEvent, AuditEventKind, AuditFilter};
+use crate::escrow::{self, HoldPurpose};
+use crate::images::{self, HttpRequest, ImageRole, ImageUploadInput};
+use crate::inheritance::{self, InheritancePlanInput};
+use crate::lands;
+use crate::marketplace;
+use crate::offers::{self, OfferStatus};
+use crate::pricing::{self, BoundingBox, MarketFilter};
+use crate::revisions::{self, LandDetailsUpdate};
+use crate::sales::{MarketplaceFee, RoyaltyConfig};
+use crate::subscriptions::{self, ParcelEventKind, Subscription, SubscriptionInput};
+use crate::tax::{self, TaxPolicy, TaxStanding, ZoningRate};
+use crate::titles;
+use crate::treasury::{self, FeePolicy};
+use crate::wallets::{self, WalletTransactionKind};
+use crate::{LandInput, LandParcel, LandStatus, TransferKind, AUDIT_LOG};
+
+const ICP: u64 = 100_000_000;
+const STARTING_BALANCE: u64 = 500 * ICP;
+
+fn principal(id: u8) -> Principal {
+    Principal::from_slice(&[id; 29])
+}
+
+fn admin() -> Principal {
+    principal(1)
+}
+
+fn verifier() -> Principal {
+    principal(2)
+}
+
+fn alice() -> Principal {
+    principal(3)
+}
+
+fn bob() -> Principal {
+    principal(4)
+}
+
+fn carol() -> Principal {
+    principal(5)
+}
+
+fn setup() -> TestEnv {
+    let env = TestEnv::new(1_700_000_000_000_000_000);
+    env.add_controller(admin());
+    admin::add_verifier(env.act_as(admin()), verifier()).unwrap();
+
+    for user in [alice(), bob(), carol()] {
+        wallets::initialize_user_wallet(env.act_as(user)).unwrap();
+    }
+
+    env
+}
+
+fn land_input() -> LandInput {
+    LandInput {
+        coordinates: "40.7128, -74.0060".to_string(),
+        size: 1000.0,
+        description: "Waterfront parcel".to_string(),
+        metadata: "Residential zoning".to_string(),
+        price: None,
+        royalty: None,
+    }
+}
+
+fn verified_land(env: &TestEnv, owner: Principal, input: LandInput) -> LandParcel {
+    let land = lands::register_land(env.act_as(owner), input).unwrap();
+    lands::verify_land(env.act_as(verifier()), land.id).unwrap()
+}
+
+#[test]
+fn registered_land_is_pending_until_verified() {
+    let env = setup();
+
+    let land = lands::register_land(env.act_as(alice()), land_input()).unwrap();
+    assert!(matches!(land.status, LandStatus::Pending));
+    assert_eq!(land.owner, alice());
+    assert_eq!(land.created_at, env.time());
+
+    env.advance(1_000);
+    let verified = lands::verify_land(env.act_as(verifier()), land.id).unwrap();
+    assert!(matches!(verified.status, LandStatus::Verified));
+    assert_eq!(verified.verified_by, Some(verifier()));
+    assert_eq!(verified.updated_at, land.created_at + 1_000);
+}
+
+#[test]
+fn only_verifiers_can_verify() {
+    let env = setup();
+    let land = lands::register_land(env.act_as(alice()), land_input()).unwrap();
+
+    assert!(lands::verify_land(env.act_as(bob()), land.id).is_err());
+    assert!(lands::verify_land(env.act_as(alice()), land.id).is_err());
+
+    lands::verify_land(env.act_as(verifier()), land.id).unwrap();
+    assert!(lands::verify_land(env.act_as(verifier()), land.id).is_err(), "already verified");
+}
+
+#[test]
+fn anonymous_callers_cannot_register() {
+    let env = setup();
+    assert!(lands::register_land(env.act_as(Principal::anonymous()), land_input()).is_err());
+}
+
+#[test]
+fn rejection_refunds_the_configured_share_of_the_fee() {
+    let env = setup();
+    admin::set_fee_policy(env.act_as(admin()), FeePolicy {
+        registration_fee: 10 * ICP,
+        rejection_refund_bps: 5_000,
+    }).unwrap();
+
+    let land = lands::register_land(env.act_as(alice()), land_input()).unwrap();
+    assert_eq!(wallets::balance(&alice()), STARTING_BALANCE - 10 * ICP);
+    assert_eq!(treasury::balance(), 10 * ICP);
+
+    lands::reject_land_verification(env.act_as(verifier()), land.id).unwrap();
+    assert_eq!(wallets::balance(&alice()), STARTING_BALANCE - 5 * ICP);
+    assert_eq!(treasury::balance(), 5 * ICP);
+
+    // The share of a huge fee does not overflow, and is capped at what the treasury holds
+    assert_eq!(treasury::refund_registration_fee(&env, alice(), land.id, u64::MAX), 5 * ICP);
+}
+
+#[test]
+fn buying_moves_funds_and_ownership() {
+    let env = setup();
+    let land = verified_land(&env, alice(), land_input());
+
+    assert!(lands::set_land_for_sale(env.act_as(bob()), land.id, 100 * ICP, None).is_err(), "not the owner");
+    lands::set_land_for_sale(env.act_as(alice()), land.id, 100 * ICP, None).unwrap();
+
+    assert!(lands::buy_land(env.act_as(alice()), land.id).is_err(), "cannot buy own land");
+    let bought = lands::buy_land(env.act_as(bob()), land.id).unwrap();
+
+    assert_eq!(bought.owner, bob());
+    assert!(matches!(bought.status, LandStatus::Verified));
+    assert_eq!(bought.price, None);
+    assert_eq!(bought.history.len(), 2);
+    assert_eq!(wallets::balance(&alice()), STARTING_BALANCE + 100 * ICP);
+    assert_eq!(wallets::balance(&bob()), STARTING_BALANCE - 100 * ICP);
+
+    let purchases = wallets::transactions_page(bob(), None, 10).transactions;
+    assert_eq!(purchases.last().unwrap().kind, WalletTransactionKind::Purchase);
+    assert!(lands::buy_land(env.act_as(carol()), land.id).is_err(), "no longer for sale");
+}
+
+#[test]
+fn failed_purchase_leaves_everything_untouched() {
+    let env = setup();
+    let land = verified_land(&env, alice(), land_input());
+    lands::set_land_for_sale(env.act_as(alice()), land.id, STARTING_BALANCE + 1, None).unwrap();
+
+    assert!(lands::buy_land(env.act_as(bob()), land.id).is_err());
+
+    let land = crate::LANDS.with(|lands| lands.borrow().get(&land.id)).unwrap();
+    assert_eq!(land.owner, alice());
+    assert!(matches!(land.status, LandStatus::ForSale));
+    assert_eq!(wallets::balance(&alice()), STARTING_BALANCE);
+    assert_eq!(wallets::balance(&bob()), STARTING_BALANCE);
+}
+
+#[test]
+fn secondary_sales_pay_the_royalty() {
+    let env = setup();
+    let land = verified_land(&env, alice(), LandInput {
+        royalty: Some(RoyaltyConfig { recipient: alice(), basis_points: 1_000 }),
+        ..land_input()
+    });
+
+    // Primary sale by the royalty recipient pays no royalty
+    lands::set_land_for_sale(env.act_as(alice()), land.id, 100 * ICP, None).unwrap();
+    let bought = lands::buy_land(env.act_as(bob()), land.id).unwrap();
+    assert_eq!(bought.history.last().unwrap().sale.as_ref().unwrap().royalty_amount, 0);
+
+    lands::set_land_for_sale(env.act_as(bob()), land.id, 200 * ICP, None).unwrap();
+    let bought = lands::buy_land(env.act_as(carol()), land.id).unwrap();
+    let settlement = bought.history.last().unwrap().sale.clone().unwrap();
+
+    assert_eq!(settlement.royalty_amount, 20 * ICP);
+    assert_eq!(settlement.seller_proceeds, 180 * ICP);
+    assert_eq!(wallets::balance(&alice()), STARTING_BALANCE + 100 * ICP + 20 * ICP);
+    assert_eq!(wallets::balance(&bob()), STARTING_BALANCE - 100 * ICP + 180 * ICP);
+    assert_eq!(wallets::balance(&carol()), STARTING_BALANCE - 200 * ICP);
+}
+
+#[test]
+fn pricing_helpers_parse_free_form_inputs() {
+    assert_eq!(pricing::median(vec![]), None);
+    assert_eq!(pricing::median(vec![3.0, 1.0, 2.0]), Some(2.0));
+    assert_eq!(pricing::median(vec![4.0, 1.0, 3.0, 2.0]), Some(2.5), "even length averages the middle pair");
+
+    assert_eq!(pricing::zoning_class("Commercial zoning, high traffic area").as_deref(), Some("commercial"));
+    assert_eq!(pricing::zoning_class("Corner lot,  Mixed Use zoning").as_deref(), Some("mixed use"));
+    assert_eq!(pricing::zoning_class("zoning"), None);
+    assert_eq!(pricing::zoning_class("Ocean view"), None);
+
+    assert_eq!(pricing::parse_coordinates("40.7128, -74.0060"), Some((40.7128, -74.006)));
+    assert_eq!(pricing::parse_coordinates("VR sector 7"), None);
+    assert_eq!(pricing::parse_coordinates("40.7, east"), None);
+    assert_eq!(pricing::parse_coordinates("NaN, 1.0"), None);
+    assert_eq!(pricing::parse_coordinates("inf, 1.0"), None);
+}
+
+fn sold_land(env: &TestEnv, input: LandInput, price: u64) -> LandParcel {
+    let land = verified_land(env, alice(), input);
+    lands::set_land_for_sale(env.act_as(alice()), land.id, price, None).unwrap();
+    lands::buy_land(env.act_as(bob()), land.id).unwrap()
+}
+
+#[test]
+fn valuations_use_the_nearest_comparable_sales() {
+    let env = setup();
+    let subject = verified_land(&env, carol(), land_input());
+    assert!(pricing::estimate(subject.id).is_err(), "no sales yet");
+    assert_eq!(pricing::market_stats(&MarketFilter::default()).median_price_per_unit, None);
+
+    for price in [10 * ICP, 20 * ICP, 30 * ICP] {
+        sold_land(&env, land_input(), price);
+    }
+    let commercial = || LandInput {
+        coordinates: "51.5072, -0.1276".to_string(),
+        metadata: "Commercial zoning, high traffic area".to_string(),
+        ..land_input()
+    };
+    sold_land(&env, commercial(), 100 * ICP);
+
+    // Three residential sales are enough to value a residential parcel on its own
+    let estimate = pricing::estimate(subject.id).unwrap();
+    assert!(estimate.same_zoning);
+    assert_eq!(estimate.comparables.len(), 3);
+    assert_eq!(estimate.estimated_price, 20 * ICP);
+
+    // A lone commercial sale is not, so the whole market is used
+    let shop = verified_land(&env, carol(), commercial());
+    let estimate = pricing::estimate(shop.id).unwrap();
+    assert!(!estimate.same_zoning);
+    assert_eq!(estimate.comparables.len(), 4);
+    assert_eq!(estimate.estimated_price, 25 * ICP);
+    assert!(pricing::estimate(999).is_err());
+
+    let all = pricing::market_stats(&MarketFilter::default());
+    assert_eq!(all.sales, 4);
+    assert_eq!(all.median_price_per_unit, Some(25.0 * ICP as f64 / 1_000.0));
+
+    let residential = pricing::market_stats(&MarketFilter { zoning: Some("Residential".to_string()), area: None });
+    assert_eq!(residential.sales, 3);
+    assert_eq!(residential.median_price_per_unit, Some(20.0 * ICP as f64 / 1_000.0));
+
+    let new_york = BoundingBox { min_lat: 40.0, max_lat: 41.0, min_lon: -75.0, max_lon: -73.0 };
+    assert_eq!(pricing::market_stats(&MarketFilter { zoning: None, area: Some(new_york) }).sales, 3);
+    let industrial = pricing::market_stats(&MarketFilter { zoning: Some("industrial".to_string()), area: None });
+    assert_eq!(industrial.sales, 0);
+    assert_eq!(industrial.median_price_per_unit, None);
+}
+
+#[test]
+fn owners_can_transfer_parcels() {
+    let env = setup();
+    let land = verified_land(&env, alice(), land_input());
+
+    assert!(lands::transfer_ownership(env.act_as(bob()), land.id, bob()).is_err());
+
+    env.advance(5);
+    let transferred = lands::transfer_ownership(env.act_as(alice()), land.id, bob()).unwrap();
+    assert_eq!(transferred.owner, bob());
+
+    let transfer = transferred.history.last().unwrap();
+    assert_eq!(transfer.from, alice());
+    assert_eq!(transfer.to, bob());
+    assert_eq!(transfer.timestamp, env.time());
+    assert!(transfer.sale.is_none());
+}
+
+// Written directly, so a test controls exactly what is in the log
+fn log_event(caller: Principal, kind: AuditEventKind, land_id: Option<u64>, subject: Option<Principal>) -> u64 {
+    AUDIT_LOG.with(|log| {
+        let mut log = log.borrow_mut();
+        let id = log.last_key_value().map(|(id, _)| id + 1).unwrap_or(1);
+        log.insert(id, AuditEvent {
+            id,
+            timestamp: id,
+            caller,
+            kind,
+            land_id,
+            subject,
+            amount: None,
+            before: None,
+            after: None,
+        });
+        id
+    })
+}
+
+#[test]
+fn audit_events_are_filtered_and_paged_oldest_first() {
+    log_event(alice(), AuditEventKind::WalletInitialized, None, None);
+    log_event(alice(), AuditEventKind::LandRegistered, Some(1), None);
+    log_event(bob(), AuditEventKind::LandRegistered, Some(2), None);
+    log_event(bob(), AuditEventKind::LandVerified, Some(1), Some(alice()));
+    log_event(bob(), AuditEventKind::LandVerified, Some(2), None);
+
+    let registered = AuditFilter { kind: Some(AuditEventKind::LandRegistered), ..Default::default() };
+    let page = audit::events_page(&registered, None, 10);
+    assert_eq!(page.events.iter().map(|event| event.caller).collect::<Vec<_>>(), vec![alice(), bob()]);
+    assert_eq!(page.next_cursor, None);
+
+    // The principal filter matches the caller or the subject
+    let by_alice = audit::events_page(&AuditFilter { principal: Some(alice()), ..Default::default() }, None, 100).events;
+    assert_eq!(by_alice.iter().map(|event| event.id).collect::<Vec<_>>(), vec![1, 2, 4]);
+
+    let on_land = audit::events_page(&AuditFilter { land_id: Some(1), ..Default::default() }, None, 100).events;
+    let kinds: Vec<AuditEventKind> = on_land.into_iter().map(|event| event.kind).collect();
+    assert_eq!(kinds, vec![AuditEventKind::LandRegistered, AuditEventKind::LandVerified]);
+
+    // Walking the pages visits every event once, and the last page has no cursor
+    let everything = audit::events_page(&AuditFilter::default(), None, 100).events;
+    assert_eq!(everything.len() as u64, audit::event_count());
+    let mut walked = Vec::new();
+    let mut cursor = None;
+    loop {
+        let page = audit::events_page(&AuditFilter::default(), cursor, 2);
+        assert!(page.events.len() <= 2);
+        walked.extend(page.events.iter().map(|event| event.id));
+        match page.next_cursor {
+            Some(next) => {
+                assert_eq!(Some(next), page.events.last().map(|event| event.id));
+                cursor = Some(next);
+            },
+            None => break,
+        }
+    }
+    assert_eq!(walked, everything.iter().map(|event| event.id).collect::<Vec<_>>());
+
+    let end = audit::events_page(&AuditFilter::default(), Some(5), 10);
+    assert!(end.events.is_empty());
+    assert_eq!(end.next_cursor, None);
+    assert_eq!(audit::events_page(&AuditFilter::default(), None, 0).events.len(), 1, "limit is at least one");
+}
+
+fn subscription(event_kinds: Vec<ParcelEventKind>) -> Subscription {
+    Subscription {
+        id: 1,
+        canister_id: principal(9),
+        method: "on_parcel_event".to_string(),
+        event_kinds,
+        created_by: principal(1),
+        created_at: 0,
+        cursor: 0,
+        delivered: 0,
+        consecutive_failures: 0,
+        next_attempt_at: 0,
+        last_error: None,
+    }
+}
+
+#[test]
+fn subscribers_receive_matching_events_and_back_off_on_failure() {
+    let now = 1_000;
+    let mut subscription = subscription(vec![ParcelEventKind::ParcelVerified, ParcelEventKind::ParcelTransferred]);
+
+    log_event(principal(2), AuditEventKind::LandVerified, Some(1), Some(alice()));
+    log_event(alice(), AuditEventKind::LandListedForSale, Some(1), None);
+    let latest = log_event(bob(), AuditEventKind::LandPurchased, Some(1), Some(alice()));
+
+    // The listing is skipped but still moves the cursor
+    let mut sent = Vec::new();
+    subscriptions::deliver_to(&mut subscription, now, |_, _, event| {
+        sent.push((event.kind, event.counterparty));
+        Ok(())
+    });
+    assert_eq!(sent, vec![
+        (ParcelEventKind::ParcelVerified, Some(alice())),
+        (ParcelEventKind::ParcelTransferred, Some(bob())),
+    ]);
+    assert_eq!(subscription.delivered, 2);
+    assert_eq!(subscription.cursor, latest);
+
+    let transfer = log_event(bob(), AuditEventKind::OwnershipTransferred, Some(1), Some(carol()));
+    let reject = |_: Principal, _: &str, _| Err(RejectionCode::CanisterReject);
+    subscriptions::deliver_to(&mut subscription, now, reject);
+    subscriptions::deliver_to(&mut subscription, now, reject);
+    assert_eq!(subscription.cursor, latest, "the failed event is retried");
+    assert_eq!(subscription.consecutive_failures, 2);
+    assert_eq!(subscription.next_attempt_at, now + subscriptions::retry_delay(2));
+    assert!(subscription.last_error.is_some());
+
+    subscriptions::deliver_to(&mut subscription, now, |_, _, _| Ok(()));
+    assert_eq!(subscription.delivered, 3);
+    assert_eq!(subscription.cursor, transfer);
+    assert_eq!(subscription.consecutive_failures, 0);
+    assert_eq!(subscription.next_attempt_at, 0);
+}
+
+#[test]
+fn delivery_retries_back_off_exponentially_up_to_an_hour() {
+    let second = 1_000_000_000;
+    assert_eq!(subscriptions::retry_delay(1), 30 * second);
+    assert_eq!(subscriptions::retry_delay(2), 60 * second);
+    assert_eq!(subscriptions::retry_delay(3), 120 * second);
+    assert_eq!(subscriptions::retry_delay(7), 1_920 * second);
+    assert_eq!(subscriptions::retry_delay(8), 3_600 * second, "capped");
+    assert_eq!(subscriptions::retry_delay(200), 3_600 * second, "no overflow");
+}
+
+#[test]
+fn admin_operations_require_a_controller() {
+    let env = setup();
+
+    assert!(admin::set_fee_policy(env.act_as(alice()), FeePolicy::default()).is_err());
+    assert!(admin::withdraw_from_treasury(env.act_as(alice()), alice(), 1).is_err());
+    assert!(admin::create_snapshot(env.act_as(alice())).is_err());
+    assert!(admin::create_snapshot(env.act_as(admin())).is_ok());
+}
+
+#[test]
+fn wallet_transfers_move_funds() {
+    let env = setup();
+
+    let remaining = wallets::transfer_funds(env.act_as(alice()), bob(), 10 * ICP, Some("rent".to_string())).unwrap();
+    assert_eq!(remaining, STARTING_BALANCE - 10 * ICP);
+    assert_eq!(wallets::balance(&bob()), STARTING_BALANCE + 10 * ICP);
+
+    assert!(wallets::transfer_funds(env.act_as(alice()), alice(), 1, None).is_err());
+    assert!(wallets::transfer_funds(env.act_as(alice()), bob(), STARTING_BALANCE, None).is_err());
+
+    let credited = wallets::credit(&env, carol(), u64::MAX, WalletTransactionKind::TransferIn, Default::default());
+    assert_eq!(credited, u64::MAX, "balances saturate rather than wrap");
+}
+
+#[test]
+fn frozen_parcels_block_owner_actions() {
+    let env = setup();
+    let land = verified_land(&env, alice(), land_input());
+    lands::set_land_for_sale(env.act_as(alice()), land.id, 100 * ICP, None).unwrap();
+
+    assert!(lands::freeze_land(env.act_as(bob()), land.id, "Dispute".to_string()).is_err());
+    assert!(lands::freeze_land(env.act_as(verifier()), land.id, "  ".to_string()).is_err());
+    let frozen = lands::freeze_land(env.act_as(verifier()), land.id, "Ownership dispute".to_string()).unwrap();
+    assert_eq!(frozen.freeze.unwrap().authority, verifier());
+
+    assert!(lands::buy_land(env.act_as(bob()), land.id).is_err());
+    assert!(lands::transfer_ownership(env.act_as(alice()), land.id, bob()).is_err());
+    assert_eq!(wallets::balance(&bob()), STARTING_BALANCE);
+
+    assert!(lands::unfreeze_land(env.act_as(alice()), land.id).is_err(), "owners cannot lift a hold");
+    // Nor can they appoint themselves verifier to get around it
+    assert!(admin::add_verifier(env.act_as(alice()), alice()).is_err());
+    assert!(lands::unfreeze_land(env.act_as(alice()), land.id).is_err());
+    assert!(admin::remove_verifier(env.act_as(alice()), verifier()).is_err());
+    lands::unfreeze_land(env.act_as(admin()), land.id).unwrap();
+    assert_eq!(lands::buy_land(env.act_as(bob()), land.id).unwrap().owner, bob());
+}
+
+const DAY: u64 = 86_400_000_000_000;
+
+#[test]
+fn beneficiaries_inherit_after_inactivity_and_grace() {
+    let env = setup();
+    let land = verified_land(&env, alice(), land_input());
+    inheritance::set_plan(env.act_as(alice()), InheritancePlanInput {
+        land_id: None,
+        beneficiaries: vec![bob()],
+        inactivity_days: 30,
+    }).unwrap();
+
+    env.advance(31 * DAY);
+    assert!(inheritance::claim(env.act_as(bob()), land.id).is_err(), "no notice yet");
+    inheritance::issue_notices(&env);
+    assert!(inheritance::claim(env.act_as(bob()), land.id).is_err(), "grace period running");
+    assert!(inheritance::claim(env.act_as(carol()), land.id).is_err(), "not a beneficiary");
+
+    env.advance(inheritance::GRACE_PERIOD_NS);
+    let claimed = inheritance::claim(env.act_as(bob()), land.id).unwrap();
+    assert_eq!(claimed.owner, bob());
+    assert_eq!(claimed.history.last().unwrap().transfer_kind, Some(TransferKind::Inheritance));
+}
+
+#[test]
+fn owner_activity_withdraws_the_notice() {
+    let env = setup();
+    let land = verified_land(&env, alice(), land_input());
+    inheritance::set_plan(env.act_as(alice()), InheritancePlanInput {
+        land_id: Some(land.id),
+        beneficiaries: vec![bob()],
+        inactivity_days: 30,
+    }).unwrap();
+
+    env.advance(31 * DAY);
+    inheritance::issue_notices(&env);
+    inheritance::check_in(env.act_as(alice())).unwrap();
+
+    env.advance(inheritance::GRACE_PERIOD_NS);
+    assert!(inheritance::claim(env.act_as(bob()), land.id).is_err());
+    inheritance::issue_notices(&env);
+    assert_eq!(inheritance::plans_of(alice())[0].notice_issued_at, None);
+}
+
+fn tax_policy() -> TaxPolicy {
+    TaxPolicy {
+        period_days: 30,
+        default_rate: ICP / 100,
+        zoning_rates: vec![ZoningRate { zoning: "residential".to_string(), rate: ICP / 50 }],
+        delinquency_days: 10,
+        reclamation_days: 20,
+    }
+}
+
+// Empties a wallet so the next assessment cannot be paid
+fn drain(env: &TestEnv, user: Principal) {
+    let balance = wallets::balance(&user);
+    wallets::transfer_funds(env.act_as(user), carol(), balance, None).unwrap();
+}
+
+#[test]
+fn unpaid_tax_escalates_to_a_reclamation_sale() {
+    let env = setup();
+    admin::set_tax_policy(env.act_as(admin()), tax_policy()).unwrap();
+    let land = verified_land(&env, alice(), land_input());
+
+    tax::assess_due(&env);
+    env.advance(30 * DAY);
+    tax::assess_due(&env);
+    // 1000 units at the residential rate
+    assert_eq!(wallets::balance(&alice()), STARTING_BALANCE - 20 * ICP);
+    assert_eq!(treasury::balance(), 20 * ICP);
+
+    drain(&env, alice());
+    env.advance(30 * DAY);
+    tax::assess_due(&env);
+    let account = tax::account(land.id).unwrap();
+    assert_eq!(account.arrears, 20 * ICP);
+    assert_eq!(account.standing, TaxStanding::InArrears);
+
+    env.advance(10 * DAY);
+    tax::assess_due(&env);
+    assert_eq!(tax::account(land.id).unwrap().standing, TaxStanding::Delinquent);
+    assert!(tax::start_reclamation_sale(env.act_as(admin()), land.id, 100 * ICP).is_err(), "not reclaimable yet");
+
+    // Another period falls due on the way
+    env.advance(20 * DAY);
+    tax::assess_due(&env);
+    let account = tax::account(land.id).unwrap();
+    assert_eq!(account.standing, TaxStanding::Reclaimable);
+    assert_eq!(account.arrears, 40 * ICP);
+
+    assert!(tax::start_reclamation_sale(env.act_as(alice()), land.id, 100 * ICP).is_err());
+    tax::start_reclamation_sale(env.act_as(admin()), land.id, 100 * ICP).unwrap();
+    assert!(lands::transfer_ownership(env.act_as(alice()), land.id, bob()).is_err());
+
+    lands::buy_land(env.act_as(bob()), land.id).unwrap();
+    let account = tax::account(land.id).unwrap();
+    assert_eq!(account.arrears, 0);
+    assert_eq!(account.standing, TaxStanding::Current);
+    assert_eq!(wallets::balance(&alice()), 60 * ICP);
+}
+
+#[test]
+fn owners_can_pay_their_arrears() {
+    let env = setup();
+    admin::set_tax_policy(env.act_as(admin()), tax_policy()).unwrap();
+    let land = verified_land(&env, alice(), LandInput {
+        metadata: "Unzoned".to_string(),
+        ..land_input()
+    });
+
+    tax::assess_due(&env);
+    drain(&env, alice());
+    env.advance(30 * DAY);
+    tax::assess_due(&env);
+    assert_eq!(tax::account(land.id).unwrap().arrears, 10 * ICP, "default rate");
+
+    assert!(tax::pay_arrears(env.act_as(alice()), land.id).is_err(), "wallet is empty");
+    wallets::transfer_funds(env.act_as(bob()), alice(), 10 * ICP, None).unwrap();
+    assert!(tax::pay_arrears(env.act_as(bob()), land.id).is_err(), "not the owner");
+
+    let account = tax::pay_arrears(env.act_as(alice()), land.id).unwrap();
+    assert_eq!(account.arrears, 0);
+    assert_eq!(account.total_paid, 10 * ICP);
+    assert_eq!(account.standing, TaxStanding::Current);
+}
+
+#[test]
+fn collected_arrears_end_a_running_reclamation_sale() {
+    let env = setup();
+    admin::set_tax_policy(env.act_as(admin()), tax_policy()).unwrap();
+    let land = verified_land(&env, alice(), land_input());
+
+    tax::assess_due(&env);
+    drain(&env, alice());
+    // Assessment was paused for two periods, so the first one is already reclaimable
+    env.advance(60 * DAY);
+    tax::assess_due(&env);
+    let account = tax::account(land.id).unwrap();
+    assert_eq!(account.arrears, 40 * ICP);
+    assert_eq!(account.arrears_since, Some(land.created_at + 30 * DAY));
+    assert_eq!(account.standing, TaxStanding::Reclaimable);
+    tax::start_reclamation_sale(env.act_as(admin()), land.id, 100 * ICP).unwrap();
+
+    // The owner's wallet is topped up and the next run collects everything
+    wallets::transfer_funds(env.act_as(bob()), alice(), 100 * ICP, None).unwrap();
+    env.advance(30 * DAY);
+    tax::assess_due(&env);
+    let account = tax::account(land.id).unwrap();
+    assert_eq!(account.arrears, 0);
+    assert_eq!(account.standing, TaxStanding::Current);
+    assert!(!account.reclamation_listed);
+
+    let land = crate::LANDS.with(|lands| lands.borrow().get(&land.id)).unwrap();
+    assert!(matches!(land.status, LandStatus::Verified));
+    assert_eq!(land.price, None);
+    lands::transfer_ownership(env.act_as(alice()), land.id, bob()).unwrap();
+}
+
+#[test]
+fn title_claims_describe_the_current_owner() {
+    let env = setup();
+    let land = verified_land(&env, alice(), land_input());
+
+    assert!(titles::prepare(env.act_as(bob()), land.id).is_err());
+
+    let claims = titles::prepare(env.act_as(alice()), land.id).unwrap();
+    assert_eq!(claims.registry, env.canister_id());
+    assert_eq!(claims.owner, alice());
+    assert_eq!(claims.status, "Verified");
+    assert_eq!(claims.geometry_hash, title::geometry_hash(&land.coordinates, land.size));
+    assert_eq!(claims.issued_at, env.time());
+
+    // Nothing has been signed yet, so there is no key to check against
+    let certificate = TitleCertificate {
+        claims,
+        key_name: "test_key_1".to_string(),
+        public_key: vec![2; 33],
+        signature: vec![0; 64],
+    };
+    assert!(titles::public_key().is_none());
+    assert!(titles::verify(&env, &certificate).is_err());
+}
+
+#[test]
+fn title_certificates_are_reused_while_the_claims_hold() {
+    let env = setup();
+    let land = verified_land(&env, alice(), land_input());
+
+    let claims = titles::prepare(env.act_as(alice()), land.id).unwrap();
+    assert!(titles::reusable(&claims).is_none());
+    let certificate = TitleCertificate {
+        claims,
+        key_name: titles::signing_key().key_name,
+        public_key: vec![2; 33],
+        signature: vec![0; 64],
+    };
+    titles::remember(&certificate);
+
+    env.advance(DAY);
+    let reused = titles::reusable(&titles::prepare(env.act_as(alice()), land.id).unwrap()).unwrap();
+    assert_eq!(reused.signature, certificate.signature);
+    assert_eq!(reused.claims.issued_at, certificate.claims.issued_at);
+
+    // A new owner or a new signing key needs a fresh signature
+    admin::set_title_signing_key(env.act_as(admin()), "test_key_1".to_string()).unwrap();
+    assert!(titles::reusable(&titles::prepare(env.act_as(alice()), land.id).unwrap()).is_none());
+    admin::set_title_signing_key(env.act_as(admin()), certificate.key_name.clone()).unwrap();
+    assert!(titles::reusable(&titles::prepare(env.act_as(alice()), land.id).unwrap()).is_some());
+
+    lands::transfer_ownership(env.act_as(alice()), land.id, bob()).unwrap();
+    assert!(titles::reusable(&titles::prepare(env.act_as(bob()), land.id).unwrap()).is_none());
+}
+
+fn png(len: usize) -> Vec<u8> {
+    let mut bytes = b"\x89PNG\r\n\x1a\n".to_vec();
+    bytes.resize(len, 7);
+    bytes
+}
+
+fn get(url: &str, headers: Vec<(String, String)>) -> images::HttpResponse {
+    images::http_request(&HttpRequest {
+        method: "GET".to_string(),
+        url: url.to_string(),
+        headers,
+        body: Vec::new(),
+    })
+}
+
+#[test]
+fn uploaded_previews_are_served_with_caching_headers() {
+    let env = setup();
+    let land = verified_land(&env, alice(), land_input());
+    let bytes = png(3_000);
+
+    let input = |sha256: String| ImageUploadInput {
+        land_id: land.id,
+        role: ImageRole::Preview,
+        mime_type: "image/png".to_string(),
+        size: bytes.len() as u64,
+        sha256,
+    };
+
+    assert!(images::begin_upload(env.act_as(bob()), input(sha256_hex(&bytes))).is_err());
+
+    // Content that does not match the declared hash is discarded
+    let upload = images::begin_upload(env.act_as(alice()), input(sha256_hex(b"something else"))).unwrap();
+    images::upload_chunk(env.act_as(alice()), upload, 0, bytes.clone()).unwrap();
+    assert!(images::finish_upload(env.act_as(alice()), upload).is_err());
+
+    let upload = images::begin_upload(env.act_as(alice()), input(sha256_hex(&bytes))).unwrap();
+    assert!(images::upload_chunk(env.act_as(alice()), upload, 0, b"GIF89a".to_vec()).is_err());
+    images::upload_chunk(env.act_as(alice()), upload, 0, bytes[..2_000].to_vec()).unwrap();
+    assert!(images::upload_chunk(env.act_as(alice()), upload, 2, bytes[2_000..].to_vec()).is_err());
+    assert_eq!(images::upload_chunk(env.act_as(alice()), upload, 1, bytes[2_000..].to_vec()), Ok(3_000));
+    let image = images::finish_upload(env.act_as(alice()), upload).unwrap();
+
+    let path = format!("/images/{}/{}", land.id, image.id);
+    assert_eq!(image.url, format!("https://{}.raw.icp0.io{}", env.canister_id(), path));
+    assert_eq!(crate::LANDS.with(|lands| lands.borrow().get(&land.id)).unwrap().preview_image_url, Some(image.url.clone()));
+
+    let response = get(&format!("{}?v=1", path), Vec::new());
+    assert_eq!(response.status_code, 200);
+    assert_eq!(response.body, bytes);
+    let header = |name: &str| {
+        response.headers.iter().find(|(key, _)| key == name).map(|(_, value)| value.clone()).unwrap()
+    };
+    assert_eq!(header("Content-Type"), "image/png");
+    assert!(header("Cache-Control").contains("immutable"));
+
+    let cached = get(&path, vec![("If-None-Match".to_string(), header("ETag"))]);
+    assert_eq!(cached.status_code, 304);
+    assert!(cached.body.is_empty());
+
+    images::remove_image(env.act_as(alice()), land.id, image.id).unwrap();
+    assert_eq!(crate::LANDS.with(|lands| lands.borrow().get(&land.id)).unwrap().preview_image_url, None);
+    assert_eq!(get(&path, Vec::new()).status_code, 404);
+}
+
+#[test]
+fn snapshots_stream_images_separately_from_the_state() {
+    let env = setup();
+    let land = verified_land(&env, alice(), land_input());
+    let bytes = png(3_000);
+
+    let upload = images::begin_upload(env.act_as(alice()), ImageUploadInput {
+        land_id: land.id,
+        role: ImageRole::Gallery,
+        mime_type: "image/png".to_string(),
+        size: bytes.len() as u64,
+        sha256: sha256_hex(&bytes),
+    }).unwrap();
+    images::upload_chunk(env.act_as(alice()), upload, 0, bytes[..2_000].to_vec()).unwrap();
+    images::upload_chunk(env.act_as(alice()), upload, 1, bytes[2_000..].to_vec()).unwrap();
+    let image = images::finish_upload(env.act_as(alice()), upload).unwrap();
+
+    let manifest = admin::create_snapshot(env.act_as(admin())).unwrap();
+    let state: Vec<u8> = (0..manifest.chunk_count)
+        .flat_map(|index| admin::get_snapshot_chunk(env.act_as(admin()), manifest.snapshot_id, index).unwrap())
+        .collect();
+    assert!(!state.windows(bytes.len()).any(|window| window == bytes), "image bytes are not part of the state buffer");
+
+    assert!(admin::get_snapshot_images(env.act_as(alice()), manifest.snapshot_id).is_err());
+    let listed = admin::get_snapshot_images(env.act_as(admin()), manifest.snapshot_id).unwrap();
+    assert_eq!(listed.len(), 1);
+    assert_eq!((listed[0].image_id, listed[0].chunk_count), (image.id, 2));
+    let chunks: Vec<Vec<u8>> = (0..2)
+        .map(|index| admin::get_snapshot_image_chunk(env.act_as(admin()), manifest.snapshot_id, image.id, index).unwrap())
+        .collect();
+    assert!(admin::get_snapshot_image_chunk(env.act_as(admin()), manifest.snapshot_id, image.id, 2).is_err());
+    assert!(admin::get_snapshot_image_chunk(env.act_as(admin()), manifest.snapshot_id + 1, image.id, 0).is_err());
+
+    // Stable structures are per thread, so a new thread starts with an empty registry
+    let path = format!("/images/{}/{}", land.id, image.id);
+    std::thread::spawn(move || {
+        let env = TestEnv::new(1_700_000_000_000_000_000);
+        env.add_controller(admin());
+
+        admin::begin_restore(env.act_as(admin()), manifest.clone()).unwrap();
+        for (index, chunk) in state.chunks(manifest.chunk_size as usize).enumerate() {
+            admin::upload_restore_chunk(env.act_as(admin()), index as u32, chunk.to_vec()).unwrap();
+        }
+        assert!(admin::upload_restore_image_chunk(env.act_as(admin()), image.id, 0, chunks[0].clone()).is_err(), "restore still running");
+        admin::finish_restore(env.act_as(admin())).unwrap();
+        assert_eq!(get(&path, Vec::new()).status_code, 503, "not served until its bytes are back");
+
+        assert!(admin::upload_restore_image_chunk(env.act_as(alice()), image.id, 0, chunks[0].clone()).is_err());
+        assert!(admin::upload_restore_image_chunk(env.act_as(admin()), image.id, 1, chunks[1].clone()).is_err());
+        assert_eq!(admin::upload_restore_image_chunk(env.act_as(admin()), image.id, 0, chunks[0].clone()), Ok(1));
+
+        // Bytes that do not match the recorded hash are dropped again
+        assert!(admin::upload_restore_image_chunk(env.act_as(admin()), image.id, 1, chunks[0].clone()).is_err());
+        admin::upload_restore_image_chunk(env.act_as(admin()), image.id, 0, chunks[0].clone()).unwrap();
+        assert_eq!(admin::upload_restore_image_chunk(env.act_as(admin()), image.id, 1, chunks[1].clone()), Ok(0));
+        assert!(admin::upload_restore_image_chunk(env.act_as(admin()), image.id, 2, chunks[1].clone()).is_err());
+
+        let response = get(&path, Vec::new());
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.body, bytes);
+    }).join().unwrap();
+}
+
+fn details_update() -> LandDetailsUpdate {
+    LandDetailsUpdate {
+        description: None,
+        metadata: None,
+        coordinates: None,
+        size: None,
+    }
+}
+
+#[test]
+fn material_edits_require_reverification() {
+    let env = setup();
+    admin::set_fee_policy(env.act_as(admin()), FeePolicy {
+        registration_fee: 10 * ICP,
+        rejection_refund_bps: 5_000,
+    }).unwrap();
+    let land = verified_land(&env, alice(), land_input());
+    lands::set_land_for_sale(env.act_as(alice()), land.id, 100 * ICP, None).unwrap();
+
+    let cosmetic = LandDetailsUpdate { description: Some("Renovated".to_string()), ..details_update() };
+    assert!(revisions::update_land_details(env.act_as(bob()), land.id, details_update()).is_err());
+    assert!(revisions::update_land_details(env.act_as(alice()), land.id, details_update()).is_err());
+    let edited = revisions::update_land_details(env.act_as(alice()), land.id, cosmetic).unwrap();
+    assert_eq!(edited.description, "Renovated");
+    assert!(matches!(edited.status, LandStatus::ForSale));
+    assert_eq!(edited.price, Some(100 * ICP));
+
+    let material = LandDetailsUpdate { size: Some(1_200.0), ..details_update() };
+    let edited = revisions::update_land_details(env.act_as(alice()), land.id, material).unwrap();
+    assert!(matches!(edited.status, LandStatus::Pending));
+    assert_eq!(edited.size, 1_200.0);
+    assert_eq!(edited.price, None);
+    assert!(edited.verified_by.is_none());
+
+    let history = revisions::revisions_of(land.id);
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].description, "Waterfront parcel");
+    assert!(!history[0].material);
+    assert_eq!(history[1].size, 1000.0);
+    assert!(history[1].material);
+
+    // The registration fee only covered the first review
+    let balance = wallets::balance(&alice());
+    lands::reject_land_verification(env.act_as(verifier()), land.id).unwrap();
+    assert_eq!(wallets::balance(&alice()), balance);
+}
+
+#[test]
+fn owners_can_reprice_cancel_and_let_listings_expire() {
+    let env = setup();
+    let land = verified_land(&env, alice(), land_input());
+
+    assert!(lands::cancel_land_sale(env.act_as(alice()), land.id).is_err(), "not listed");
+    assert!(lands::set_land_for_sale(env.act_as(alice()), land.id, 100 * ICP, Some(env.time())).is_err());
+    assert!(lands::set_land_for_sale(env.act_as(alice()), land.id, 0, None).is_err(), "free listing");
+
+    lands::set_land_for_sale(env.act_as(alice()), land.id, 100 * ICP, None).unwrap();
+    assert!(lands::update_land_price(env.act_as(bob()), land.id, 80 * ICP).is_err());
+    assert!(lands::update_land_price(env.act_as(alice()), land.id, 0).is_err());
+    let repriced = lands::update_land_price(env.act_as(alice()), land.id, 80 * ICP).unwrap();
+    assert_eq!(repriced.price, Some(80 * ICP));
+
+    let cancelled = lands::cancel_land_sale(env.act_as(alice()), land.id).unwrap();
+    assert!(matches!(cancelled.status, LandStatus::Verified));
+    assert_eq!(cancelled.price, None);
+    assert!(lands::buy_land(env.act_as(bob()), land.id).is_err());
+
+    lands::set_land_for_sale(env.act_as(alice()), land.id, 100 * ICP, Some(env.time() + DAY)).unwrap();
+    lands::expire_listings(&env);
+    assert_eq!(lands::update_land_price(env.act_as(alice()), land.id, 90 * ICP).unwrap().listing_expires_at, Some(env.time() + DAY));
+
+    env.advance(DAY);
+    // Buyers cannot get in between the expiry and the next sweep
+    assert!(lands::buy_land(env.act_as(bob()), land.id).is_err());
+    lands::expire_listings(&env);
+    let expired = crate::LANDS.with(|lands| lands.borrow().get(&land.id)).unwrap();
+    assert!(matches!(expired.status, LandStatus::Verified));
+    assert_eq!(expired.listing_expires_at, None);
+    assert_eq!(wallets::balance(&bob()), STARTING_BALANCE);
+}
+
+#[test]
+fn accepted_counter_offers_settle_like_a_purchase() {
+    let env = setup();
+    let land = verified_land(&env, alice(), land_input());
+    let expires_at = env.time() + DAY;
+
+    assert!(offers::place_offer(env.act_as(alice()), land.id, 100 * ICP, expires_at).is_err(), "own parcel");
+    let offer = offers::place_offer(env.act_as(bob()), land.id, 100 * ICP, expires_at).unwrap();
+    assert_eq!(wallets::balance(&bob()), STARTING_BALANCE - 100 * ICP);
+    assert_eq!(escrow::held_by(&bob()), 100 * ICP);
+
+    assert!(offers::counter_offer(env.act_as(bob()), offer.id, 120 * ICP).is_err(), "only the owner counters");
+    assert!(offers::counter_offer(env.act_as(alice()), offer.id, 90 * ICP).is_err(), "counters go up");
+    offers::counter_offer(env.act_as(alice()), offer.id, 120 * ICP).unwrap();
+
+    let rival = offers::place_offer(env.act_as(carol()), land.id, 110 * ICP, expires_at).unwrap();
+    offers::reject_offer(env.act_as(alice()), rival.id).unwrap();
+    assert_eq!(wallets::balance(&carol()), STARTING_BALANCE);
+
+    let bought = offers::accept_counter_offer(env.act_as(bob()), offer.id).unwrap();
+    assert_eq!(bought.owner, bob());
+    assert_eq!(bought.history.last().unwrap().transfer_kind, Some(TransferKind::Sale));
+    assert_eq!(wallets::balance(&alice()), STARTING_BALANCE + 120 * ICP);
+    assert_eq!(wallets::balance(&bob()), STARTING_BALANCE - 120 * ICP);
+    assert_eq!(escrow::held_by(&bob()), 0);
+
+    let offer = offers::get_offer(offer.id).unwrap();
+    assert_eq!(offer.status, OfferStatus::Accepted);
+    assert_eq!(offer.price_paid, Some(120 * ICP));
+}
+
+#[test]
+fn expired_offers_release_the_held_funds() {
+    let env = setup();
+    let land = verified_land(&env, alice(), land_input());
+
+    let offer = offers::place_offer(env.act_as(bob()), land.id, 50 * ICP, env.time() + DAY).unwrap();
+    offers::expire_offers(&env);
+    assert_eq!(escrow::held_by(&bob()), 50 * ICP);
+
+    env.advance(DAY);
+    assert!(offers::accept_offer(env.act_as(alice()), offer.id).is_err());
+    offers::expire_offers(&env);
+    assert_eq!(offers::get_offer(offer.id).unwrap().status, OfferStatus::Expired);
+    assert_eq!(wallets::balance(&bob()), STARTING_BALANCE);
+    assert_eq!(escrow::held_by(&bob()), 0);
+}
+
+#[test]
+fn open_offers_are_cancelled_when_the_parcel_changes_hands() {
+    let env = setup();
+    let land = verified_land(&env, alice(), land_input());
+    let expires_at = env.time() + DAY;
+
+    let offer = offers::place_offer(env.act_as(bob()), land.id, 50 * ICP, expires_at).unwrap();
+    let own = offers::place_offer(env.act_as(carol()), land.id, 60 * ICP, expires_at).unwrap();
+    lands::set_land_for_sale(env.act_as(alice()), land.id, 100 * ICP, None).unwrap();
+    lands::buy_land(env.act_as(carol()), land.id).unwrap();
+
+    assert_eq!(offers::get_offer(offer.id).unwrap().status, OfferStatus::Cancelled);
+    assert_eq!(offers::get_offer(own.id).unwrap().status, OfferStatus::Cancelled);
+    assert_eq!(wallets::balance(&bob()), STARTING_BALANCE);
+    assert_eq!(wallets::balance(&carol()), STARTING_BALANCE - 100 * ICP);
+    assert_eq!(escrow::held_by(&bob()) + escrow::held_by(&carol()), 0);
+
+    let offer = offers::place_offer(env.act_as(bob()), land.id, 50 * ICP, expires_at).unwrap();
+    lands::transfer_ownership(env.act_as(carol()), land.id, alice()).unwrap();
+    assert_eq!(offers::get_offer(offer.id).unwrap().status, OfferStatus::Cancelled);
+    assert_eq!(escrow::held_by(&bob()), 0);
+}
+
+#[test]
+fn released_hold_and_subscription_ids_are_never_reused() {
+    let env = setup();
+    let land = verified_land(&env, alice(), land_input());
+
+    let released = escrow::hold(&env, bob(), ICP, land.id, HoldPurpose::Offer(1)).unwrap();
+    escrow::release(&env, released).unwrap();
+    let next = escrow::hold(&env, carol(), ICP, land.id, HoldPurpose::Offer(2)).unwrap();
+    assert_eq!(next, released + 1);
+
+    // A late second release of the old id must not free the new hold
+    assert!(escrow::release(&env, released).is_err());
+    assert_eq!(escrow::held_by(&carol()), ICP);
+
+    let input = || SubscriptionInput {
+        canister_id: principal(9),
+        method: "on_parcel_event".to_string(),
+        event_kinds: vec![ParcelEventKind::ParcelTransferred],
+        start_after: None,
+    };
+    let removed = subscriptions::subscribe(&env, admin(), input()).unwrap();
+    subscriptions::unsubscribe(removed.id).unwrap();
+    assert_eq!(subscriptions::subscribe(&env, admin(), input()).unwrap().id, removed.id + 1);
+}
+
+#[test]
+fn marketplace_purchases_are_paid_from_a_hold() {
+    let env = setup();
+    let market = principal(9);
+    let land = verified_land(&env, alice(), land_input());
+    admin::set_marketplace_canister(env.act_as(admin()), market).unwrap();
+
+    assert!(marketplace::check_listing(land.id, carol()).is_err());
+    marketplace::check_listing(land.id, alice()).unwrap();
+
+    assert!(marketplace::hold_payment(env.act_as(market), alice(), 40 * ICP, land.id, 1).is_err(), "own parcel");
+    let payment = marketplace::hold_payment(env.act_as(market), bob(), 40 * ICP, land.id, 1).unwrap();
+    assert_eq!(wallets::balance(&bob()), STARTING_BALANCE - 40 * ICP);
+
+    let fee = |amount| Some(MarketplaceFee { amount, collector: principal(10) });
+    assert!(marketplace::settle(env.act_as(alice()), payment, alice(), 40 * ICP, None).is_err(), "only the marketplace");
+    assert!(marketplace::settle(env.act_as(market), payment, carol(), 40 * ICP, None).is_err(), "not the owner");
+    assert!(marketplace::settle(env.act_as(market), payment, alice(), 40 * ICP, fee(41 * ICP)).is_err(), "fee too high");
+    assert_eq!(escrow::held_by(&bob()), 40 * ICP);
+
+    let sold = marketplace::settle(env.act_as(market), payment, alice(), 40 * ICP, fee(2 * ICP)).unwrap();
+    assert_eq!(sold.owner, bob());
+    let transfer = sold.history.last().unwrap();
+    assert_eq!(transfer.transfer_kind, Some(TransferKind::Sale));
+    assert_eq!(transfer.sale.as_ref().unwrap().marketplace_fee, Some(2 * ICP));
+    assert_eq!(wallets::balance(&alice()), STARTING_BALANCE + 38 * ICP);
+    assert_eq!(wallets::balance(&principal(10)), 2 * ICP);
+    assert_eq!(escrow::held_by(&bob()), 0);
+
+    lands::set_land_for_sale(env.act_as(bob()), land.id, 10 * ICP, None).unwrap();
+    assert!(marketplace::check_listing(land.id, bob()).is_err(), "listed in the registry");
+}
+
+#[test]
+fn marketplace_auction_bids_settle_from_escrow() {
+    let env = setup();
+    let market = principal(9);
+    let land = verified_land(&env, alice(), land_input());
+
+    assert!(marketplace::hold_bid(env.act_as(market), bob(), 10 * ICP, land.id, 1).is_err(), "not configured");
+    assert!(admin::set_marketplace_canister(env.act_as(alice()), market).is_err());
+    admin::set_marketplace_canister(env.act_as(admin()), market).unwrap();
+    assert!(marketplace::hold_bid(env.act_as(bob()), bob(), 10 * ICP, land.id, 1).is_err(), "only the marketplace");
+
+    let outbid = marketplace::hold_bid(env.act_as(market), bob(), 10 * ICP, land.id, 1).unwrap();
+    let winning = marketplace::hold_bid(env.act_as(market), carol(), 30 * ICP, land.id, 1).unwrap();
+    marketplace::release_hold(env.act_as(market), outbid).unwrap();
+    assert_eq!(wallets::balance(&bob()), STARTING_BALANCE);
+
+    assert!(marketplace::settle(env.act_as(market), winning, bob(), 30 * ICP, None).is_err(), "not the seller");
+    assert!(marketplace::settle(env.act_as(market), winning, alice(), 31 * ICP, None).is_err(), "above the bid");
+    assert_eq!(escrow::held_by(&carol()), 30 * ICP);
+
+    let sold = marketplace::settle(env.act_as(market), winning, alice(), 25 * ICP, None).unwrap();
+    assert_eq!(sold.owner, carol());
+    assert_eq!(wallets::balance(&carol()), STARTING_BALANCE - 25 * ICP);
+    assert_eq!(wallets::balance(&alice()), STARTING_BALANCE + 25 * ICP);
+    assert_eq!(escrow::held_by(&carol()), 0);
+}
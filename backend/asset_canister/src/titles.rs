@@ -0,0 +1,222 @@
+//! Signed title certificates that owners can hand to third parties as proof of ownership.
+//!
+//! Certificates are signed with the canister's threshold ECDSA key. The encoding and the offline
+//! check live in `registry_common::title` so that clients can verify without calling the registry.
+//!
+//! Every signature is paid for in cycles, so a parcel's last certificate is handed out again for as
+//! long as its claims still hold, and only one signature per parcel can be in flight at a time.
+
+use ic_cdk::api::management_canister::ecdsa::{
+    ecdsa_public_key, sign_with_ecdsa, EcdsaCurve, EcdsaKeyId, EcdsaPublicKeyArgument, SignWithEcdsaArgument,
+};
+use registry_common::env::Environment;
+use registry_common::title::{self, TitleCertificate, TitleClaims};
+use serde::{Serialize, Deserialize as SerdeDeserialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::audit::{self, AuditDetails, AuditEventKind};
+use crate::{CONFIG, LANDS};
+
+const SIGNING_KEY_KEY: &str = "title_signing_key";
+// Production key on mainnet; local replicas and PocketIC use "dfx_test_key" or "test_key_1"
+const DEFAULT_KEY_NAME: &str = "key_1";
+const MAX_KEY_NAME_LEN: usize = 64;
+
+thread_local! {
+    // Held on the heap only; after an upgrade each parcel costs at most one more signature
+    static ISSUED: RefCell<BTreeMap<u64, TitleCertificate>> = const { RefCell::new(BTreeMap::new()) };
+    static SIGNING: RefCell<BTreeSet<u64>> = const { RefCell::new(BTreeSet::new()) };
+}
+
+// Marks a parcel as being signed for. Dropped on every exit, including a trap after an await.
+struct SigningClaim(u64);
+
+impl SigningClaim {
+    fn acquire(land_id: u64) -> Option<Self> {
+        // Lazy on purpose: an eagerly built claim would be dropped and release the holder's entry
+        SIGNING.with(|signing| signing.borrow_mut().insert(land_id)).then(|| SigningClaim(land_id))
+    }
+}
+
+impl Drop for SigningClaim {
+    fn drop(&mut self) {
+        SIGNING.with(|signing| signing.borrow_mut().remove(&self.0));
+    }
+}
+
+fn derivation_path() -> Vec<Vec<u8>> {
+    vec![b"title-certificates".to_vec()]
+}
+
+#[derive(Serialize, SerdeDeserialize, Clone, Debug)]
+pub struct SigningKey {
+    pub key_name: String,
+    pub public_key: Option<Vec<u8>>, // Cached on first use, cleared when the key changes
+}
+
+impl Default for SigningKey {
+    fn default() -> Self {
+        SigningKey {
+            key_name: DEFAULT_KEY_NAME.to_string(),
+            public_key: None,
+        }
+    }
+}
+
+impl SigningKey {
+    fn key_id(&self) -> EcdsaKeyId {
+        EcdsaKeyId {
+            curve: EcdsaCurve::Secp256k1,
+            name: self.key_name.clone(),
+        }
+    }
+}
+
+pub fn signing_key() -> SigningKey {
+    CONFIG.with(|config| {
+        config
+            .borrow()
+            .get(&SIGNING_KEY_KEY.to_string())
+            .and_then(|value| serde_json::from_str(&value).ok())
+            .unwrap_or_default()
+    })
+}
+
+fn store_signing_key(key: &SigningKey) -> Result<(), String> {
+    let value = serde_json::to_string(key).map_err(|e| format!("Failed to encode signing key: {}", e))?;
+    CONFIG.with(|config| {
+        config.borrow_mut().insert(SIGNING_KEY_KEY.to_string(), value);
+    });
+
+    Ok(())
+}
+
+pub fn set_key_name(key_name: String) -> Result<SigningKey, String> {
+    if key_name.is_empty() || key_name.len() > MAX_KEY_NAME_LEN {
+        return Err(format!("Key name must be between 1 and {} characters", MAX_KEY_NAME_LEN));
+    }
+
+    let mut key = signing_key();
+    if key.key_name != key_name {
+        key = SigningKey { key_name, public_key: None };
+        store_signing_key(&key)?;
+    }
+
+    Ok(key)
+}
+
+/// The key certificates are currently signed with. `None` until the first certificate is issued.
+pub fn public_key() -> Option<Vec<u8>> {
+    signing_key().public_key
+}
+
+/// The claims a certificate for the parcel would make right now. Only the owner may ask.
+pub fn prepare(env: &impl Environment, land_id: u64) -> Result<TitleClaims, String> {
+    let land = LANDS.with(|lands| lands.borrow().get(&land_id)).ok_or("Land parcel not found")?;
+
+    if land.owner != env.caller() {
+        return Err("Only the owner can request a title certificate".to_string());
+    }
+
+    Ok(TitleClaims {
+        registry: env.canister_id(),
+        land_id,
+        owner: land.owner,
+        geometry_hash: title::geometry_hash(&land.coordinates, land.size),
+        status: format!("{:?}", land.status),
+        issued_at: env.time(),
+    })
+}
+
+/// The parcel's last certificate, if it makes the same claims and was signed with the current key.
+pub fn reusable(claims: &TitleClaims) -> Option<TitleCertificate> {
+    let certificate = ISSUED.with(|issued| issued.borrow().get(&claims.land_id).cloned())?;
+
+    let unchanged = certificate.claims.registry == claims.registry
+        && certificate.claims.owner == claims.owner
+        && certificate.claims.status == claims.status
+        && certificate.claims.geometry_hash == claims.geometry_hash
+        && certificate.key_name == signing_key().key_name;
+    unchanged.then_some(certificate)
+}
+
+pub fn remember(certificate: &TitleCertificate) {
+    ISSUED.with(|issued| {
+        issued.borrow_mut().insert(certificate.claims.land_id, certificate.clone());
+    });
+}
+
+pub async fn issue(env: &impl Environment, land_id: u64) -> Result<TitleCertificate, String> {
+    let claims = prepare(env, land_id)?;
+    if let Some(certificate) = reusable(&claims) {
+        return Ok(certificate);
+    }
+
+    let _claim = SigningClaim::acquire(land_id).ok_or("A certificate for this parcel is already being signed")?;
+    let key = signing_key();
+
+    let public_key = match key.public_key.clone() {
+        Some(public_key) => public_key,
+        None => fetch_public_key(&key).await?,
+    };
+
+    let (response,) = sign_with_ecdsa(SignWithEcdsaArgument {
+        message_hash: claims.message_hash().to_vec(),
+        derivation_path: derivation_path(),
+        key_id: key.key_id(),
+    })
+    .await
+    .map_err(|(code, message)| format!("Signing failed: {:?} {}", code, message))?;
+
+    // Signing takes several rounds; do not vouch for a parcel that changed hands in the meantime
+    let current = prepare(env, land_id)?;
+    if current.owner != claims.owner || current.status != claims.status || current.geometry_hash != claims.geometry_hash {
+        return Err("The parcel changed while the certificate was being signed".to_string());
+    }
+
+    let certificate = TitleCertificate {
+        claims,
+        key_name: key.key_name,
+        public_key,
+        signature: response.signature,
+    };
+    remember(&certificate);
+
+    audit::record(env, certificate.claims.owner, AuditEventKind::TitleCertificateIssued, AuditDetails {
+        land_id: Some(land_id),
+        after: Some(format!("issued_at={}", certificate.claims.issued_at)),
+        ..Default::default()
+    });
+
+    Ok(certificate)
+}
+
+/// Checks a certificate against this registry's current key.
+pub fn verify(env: &impl Environment, certificate: &TitleCertificate) -> Result<(), String> {
+    if certificate.claims.registry != env.canister_id() {
+        return Err("Certificate was issued by a different registry".to_string());
+    }
+
+    let public_key = public_key().ok_or("No title certificates have been issued yet")?;
+    title::verify_certificate(certificate, &public_key)
+}
+
+async fn fetch_public_key(key: &SigningKey) -> Result<Vec<u8>, String> {
+    let (response,) = ecdsa_public_key(EcdsaPublicKeyArgument {
+        canister_id: None,
+        derivation_path: derivation_path(),
+        key_id: key.key_id(),
+    })
+    .await
+    .map_err(|(code, message)| format!("Fetching the signing key failed: {:?} {}", code, message))?;
+
+    // Only cache if the admin did not switch keys while the call was in flight
+    let mut current = signing_key();
+    if current.key_name == key.key_name {
+        current.public_key = Some(response.public_key.clone());
+        store_signing_key(&current)?;
+    }
+
+    Ok(response.public_key)
+}
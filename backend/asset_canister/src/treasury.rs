@@ -0,0 +1,196 @@
+use candid::{CandidType, Principal};
+use ic_stable_structures::Storable;
+use registry_common::env::Environment;
+use serde::{Serialize, Deserialize as SerdeDeserialize};
+use std::borrow::Cow;
+
+use crate::wallets::{self, WalletDetails, WalletTransactionKind};
+use crate::{CONFIG, TREASURY_LEDGER};
+
+const FEE_POLICY_KEY: &str = "fee_policy";
+const MAX_PAGE_SIZE: u64 = 100;
+pub const BASIS_POINTS: u64 = 10_000;
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug, Default)]
+pub struct FeePolicy {
+    pub registration_fee: u64, // in e8s, zero disables the fee
+    pub rejection_refund_bps: u16, // Share of the fee refunded when a registration is rejected
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug, PartialEq)]
+pub enum TreasuryEntryKind {
+    RegistrationFee,
+    RejectionRefund,
+    Withdrawal,
+    LandTax,
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone)]
+pub struct TreasuryEntry {
+    pub id: u64,
+    pub timestamp: u64,
+    pub kind: TreasuryEntryKind,
+    pub amount: u64, // in e8s
+    pub counterparty: Principal,
+    pub land_id: Option<u64>,
+    pub balance_after: u64,
+}
+
+impl Storable for TreasuryEntry {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize)]
+pub struct TreasuryLedgerPage {
+    pub entries: Vec<TreasuryEntry>,
+    pub next_cursor: Option<u64>,
+}
+
+pub fn fee_policy() -> FeePolicy {
+    CONFIG.with(|config| {
+        config
+            .borrow()
+            .get(&FEE_POLICY_KEY.to_string())
+            .and_then(|value| serde_json::from_str(&value).ok())
+            .unwrap_or_default()
+    })
+}
+
+pub fn set_fee_policy(policy: &FeePolicy) -> Result<(), String> {
+    if policy.rejection_refund_bps as u64 > BASIS_POINTS {
+        return Err(format!("Refund share cannot exceed {} basis points", BASIS_POINTS));
+    }
+
+    let value = serde_json::to_string(policy).map_err(|e| format!("Failed to encode fee policy: {}", e))?;
+    CONFIG.with(|config| {
+        config.borrow_mut().insert(FEE_POLICY_KEY.to_string(), value);
+    });
+
+    Ok(())
+}
+
+pub fn balance() -> u64 {
+    TREASURY_LEDGER.with(|ledger| {
+        ledger.borrow().last_key_value().map(|(_, entry)| entry.balance_after).unwrap_or(0)
+    })
+}
+
+pub fn check_can_pay(payer: Principal, fee: u64) -> Result<(), String> {
+    let payer_balance = wallets::balance(&payer);
+    if payer_balance < fee {
+        return Err(format!("Insufficient funds for the registration fee. You have {} e8s but need {} e8s", payer_balance, fee));
+    }
+
+    Ok(())
+}
+
+/// Moves the registration fee from the registrant's wallet into the treasury.
+pub fn charge_registration_fee(env: &impl Environment, payer: Principal, land_id: u64, fee: u64) -> Result<(), String> {
+    if fee == 0 {
+        return Ok(());
+    }
+
+    check_can_pay(payer, fee)?;
+    wallets::debit(env, payer, fee, WalletTransactionKind::RegistrationFee, WalletDetails {
+        land_id: Some(land_id),
+        ..Default::default()
+    })?;
+
+    append(env, TreasuryEntryKind::RegistrationFee, fee, payer, Some(land_id), balance() + fee);
+    Ok(())
+}
+
+/// Refunds the policy share of a registration fee to the registrant. Returns the refunded amount.
+pub fn refund_registration_fee(env: &impl Environment, owner: Principal, land_id: u64, fee_paid: u64) -> u64 {
+    let refund = (fee_paid as u128 * fee_policy().rejection_refund_bps as u128 / BASIS_POINTS as u128) as u64;
+    // The treasury may have been drained by withdrawals since the fee was paid
+    let refund = refund.min(balance());
+    if refund == 0 {
+        return 0;
+    }
+
+    wallets::credit(env, owner, refund, WalletTransactionKind::FeeRefund, WalletDetails {
+        land_id: Some(land_id),
+        ..Default::default()
+    });
+
+    append(env, TreasuryEntryKind::RejectionRefund, refund, owner, Some(land_id), balance() - refund);
+    refund
+}
+
+/// Moves collected land tax from the owner's wallet into the treasury.
+pub fn collect_land_tax(env: &impl Environment, payer: Principal, land_id: u64, amount: u64) -> Result<(), String> {
+    wallets::debit(env, payer, amount, WalletTransactionKind::LandTax, WalletDetails {
+        land_id: Some(land_id),
+        ..Default::default()
+    })?;
+
+    append(env, TreasuryEntryKind::LandTax, amount, payer, Some(land_id), balance() + amount);
+    Ok(())
+}
+
+/// Pays treasury funds out to a user wallet. Returns the remaining treasury balance.
+pub fn withdraw(env: &impl Environment, to: Principal, amount: u64) -> Result<u64, String> {
+    if amount == 0 {
+        return Err("Withdrawal amount must be greater than zero".to_string());
+    }
+
+    let treasury_balance = balance();
+    if treasury_balance < amount {
+        return Err(format!("Insufficient treasury funds. Treasury holds {} e8s", treasury_balance));
+    }
+
+    wallets::credit(env, to, amount, WalletTransactionKind::TreasuryPayout, WalletDetails::default());
+
+    let remaining = treasury_balance - amount;
+    append(env, TreasuryEntryKind::Withdrawal, amount, to, None, remaining);
+    Ok(remaining)
+}
+
+pub fn ledger_page(cursor: Option<u64>, limit: u64) -> TreasuryLedgerPage {
+    let limit = limit.clamp(1, MAX_PAGE_SIZE) as usize;
+    let start = cursor.map(|cursor| cursor + 1).unwrap_or(0);
+
+    TREASURY_LEDGER.with(|ledger| {
+        let ledger = ledger.borrow();
+        let mut entries: Vec<TreasuryEntry> = ledger
+            .range(start..)
+            .map(|(_, entry)| entry)
+            .take(limit + 1)
+            .collect();
+
+        let next_cursor = if entries.len() > limit {
+            entries.truncate(limit);
+            entries.last().map(|entry| entry.id)
+        } else {
+            None
+        };
+
+        TreasuryLedgerPage { entries, next_cursor }
+    })
+}
+
+fn append(env: &impl Environment, kind: TreasuryEntryKind, amount: u64, counterparty: Principal, land_id: Option<u64>, balance_after: u64) {
+    TREASURY_LEDGER.with(|ledger| {
+        let mut ledger = ledger.borrow_mut();
+        let id = ledger.last_key_value().map(|(id, _)| id + 1).unwrap_or(1);
+
+        ledger.insert(id, TreasuryEntry {
+            id,
+            timestamp: env.time(),
+            kind,
+            amount,
+            counterparty,
+            land_id,
+            balance_after,
+        });
+    });
+}
@@ -0,0 +1,224 @@
+use candid::{CandidType, Principal};
+use ic_stable_structures::Storable;
+use registry_common::env::Environment;
+use serde::{Serialize, Deserialize as SerdeDeserialize};
+use std::borrow::Cow;
+
+use crate::audit::{self, AuditDetails, AuditEventKind};
+use crate::{WALLETS, WALLET_TRANSACTIONS};
+
+const MAX_PAGE_SIZE: u64 = 100;
+pub const MAX_MEMO_LEN: usize = 256;
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug, PartialEq)]
+pub enum WalletTransactionKind {
+    Deposit,
+    Purchase,
+    Sale,
+    RoyaltyIncome,
+    RegistrationFee,
+    FeeRefund,
+    TreasuryPayout,
+    TransferIn,
+    TransferOut,
+    LandTax,
+    EscrowHold,
+    EscrowRelease,
+    MarketplaceFee, // Commission credited to the marketplace's fee collector
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone)]
+pub struct WalletTransaction {
+    pub id: u64, // Sequence number within the owner's wallet
+    pub owner: Principal,
+    pub timestamp: u64,
+    pub kind: WalletTransactionKind,
+    pub amount: u64, // in e8s, always positive; the kind gives the direction
+    pub balance_after: u64,
+    pub counterparty: Option<Principal>,
+    pub land_id: Option<u64>,
+    pub memo: Option<String>,
+}
+
+impl Storable for WalletTransaction {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize)]
+pub struct WalletTransactionPage {
+    pub transactions: Vec<WalletTransaction>,
+    pub next_cursor: Option<u64>,
+}
+
+/// Optional details explaining a balance change.
+#[derive(Default)]
+pub struct WalletDetails {
+    pub counterparty: Option<Principal>,
+    pub land_id: Option<u64>,
+    pub memo: Option<String>,
+}
+
+pub fn balance(owner: &Principal) -> u64 {
+    WALLETS.with(|wallets| wallets.borrow().get(owner).unwrap_or(0))
+}
+
+/// Adds funds to a wallet. Returns the new balance, which saturates rather than wraps.
+pub fn credit(env: &impl Environment, owner: Principal, amount: u64, kind: WalletTransactionKind, details: WalletDetails) -> u64 {
+    let new_balance = balance(&owner).saturating_add(amount);
+    WALLETS.with(|wallets| {
+        wallets.borrow_mut().insert(owner, new_balance);
+    });
+
+    append(env, owner, amount, new_balance, kind, details);
+    new_balance
+}
+
+/// Takes funds out of a wallet, leaving it untouched if the balance is too low.
+/// Returns the new balance.
+pub fn debit(env: &impl Environment, owner: Principal, amount: u64, kind: WalletTransactionKind, details: WalletDetails) -> Result<u64, String> {
+    let current_balance = balance(&owner);
+    if current_balance < amount {
+        return Err(format!("Insufficient funds. You have {} e8s but need {} e8s", current_balance, amount));
+    }
+
+    let new_balance = current_balance - amount;
+    WALLETS.with(|wallets| {
+        wallets.borrow_mut().insert(owner, new_balance);
+    });
+
+    append(env, owner, amount, new_balance, kind, details);
+    Ok(new_balance)
+}
+
+/// Moves funds between two wallets. Returns the sender's new balance.
+pub fn transfer(env: &impl Environment, from: Principal, to: Principal, amount: u64, memo: Option<String>) -> Result<u64, String> {
+    if amount == 0 {
+        return Err("Transfer amount must be greater than zero".to_string());
+    }
+
+    if to == Principal::anonymous() {
+        return Err("Cannot transfer funds to the anonymous principal".to_string());
+    }
+
+    if to == from {
+        return Err("Cannot transfer funds to yourself".to_string());
+    }
+
+    if memo.as_ref().is_some_and(|memo| memo.chars().count() > MAX_MEMO_LEN) {
+        return Err(format!("Memo must not be longer than {} characters", MAX_MEMO_LEN));
+    }
+
+    let remaining = debit(env, from, amount, WalletTransactionKind::TransferOut, WalletDetails {
+        counterparty: Some(to),
+        memo: memo.clone(),
+        ..Default::default()
+    })?;
+    credit(env, to, amount, WalletTransactionKind::TransferIn, WalletDetails {
+        counterparty: Some(from),
+        memo,
+        ..Default::default()
+    });
+    Ok(remaining)
+}
+
+pub fn transactions_page(owner: Principal, cursor: Option<u64>, limit: u64) -> WalletTransactionPage {
+    let limit = limit.clamp(1, MAX_PAGE_SIZE) as usize;
+    let start = cursor.map(|cursor| cursor + 1).unwrap_or(0);
+
+    WALLET_TRANSACTIONS.with(|transactions| {
+        let mut page: Vec<WalletTransaction> = transactions
+            .borrow()
+            .range((owner, start)..=(owner, u64::MAX))
+            .map(|(_, transaction)| transaction)
+            .take(limit + 1)
+            .collect();
+
+        let next_cursor = if page.len() > limit {
+            page.truncate(limit);
+            page.last().map(|transaction| transaction.id)
+        } else {
+            None
+        };
+
+        WalletTransactionPage { transactions: page, next_cursor }
+    })
+}
+
+/// Gives a new user their starting balance. Existing wallets are left untouched.
+pub fn initialize_user_wallet(env: &impl Environment) -> Result<u64, String> {
+    let user = env.caller();
+    
+    if user == Principal::anonymous() {
+        return Err("Anonymous users cannot have wallets".to_string());
+    }
+    
+    // Give new users 50,000,000,000 e8s (500 ICP) as starting balance
+    let starting_balance = 50_000_000_000u64; // 500 ICP in e8s
+    
+    // Only initialize if wallet doesn't exist
+    if WALLETS.with(|wallets| wallets.borrow().contains_key(&user)) {
+        return Ok(balance(&user));
+    }
+
+    credit(env, user, starting_balance, WalletTransactionKind::Deposit, WalletDetails {
+        memo: Some("Starting balance".to_string()),
+        ..Default::default()
+    });
+
+    audit::record(env, user, AuditEventKind::WalletInitialized, AuditDetails {
+        amount: Some(starting_balance),
+        after: Some(format!("balance={}", starting_balance)),
+        ..Default::default()
+    });
+    Ok(starting_balance)
+}
+
+pub fn transfer_funds(env: &impl Environment, to: Principal, amount: u64, memo: Option<String>) -> Result<u64, String> {
+    let from = env.caller();
+    
+    if from == Principal::anonymous() {
+        return Err("Anonymous users cannot have wallets".to_string());
+    }
+
+    let remaining = transfer(env, from, to, amount, memo)?;
+
+    audit::record(env, from, AuditEventKind::FundsTransferred, AuditDetails {
+        subject: Some(to),
+        amount: Some(amount),
+        after: Some(format!("balance={}", remaining)),
+        ..Default::default()
+    });
+
+    Ok(remaining)
+}
+
+fn append(env: &impl Environment, owner: Principal, amount: u64, balance_after: u64, kind: WalletTransactionKind, details: WalletDetails) {
+    WALLET_TRANSACTIONS.with(|transactions| {
+        let mut transactions = transactions.borrow_mut();
+        let id = transactions
+            .range((owner, 0)..=(owner, u64::MAX))
+            .next_back()
+            .map(|((_, id), _)| id + 1)
+            .unwrap_or(1);
+
+        transactions.insert((owner, id), WalletTransaction {
+            id,
+            owner,
+            timestamp: env.time(),
+            kind,
+            amount,
+            balance_after,
+            counterparty: details.counterparty,
+            land_id: details.land_id,
+            memo: details.memo,
+        });
+    });
+}
@@ -0,0 +1,46 @@
+//! Admin operations: snapshots.
+
+use registry_common::env::Environment;
+use registry_common::snapshot::SnapshotManifest;
+
+use crate::snapshot;
+
+pub fn create_snapshot(env: &impl Environment) -> Result<SnapshotManifest, String> {
+    if !env.is_controller(&env.caller()) {
+        return Err("Only admins can create snapshots".to_string());
+    }
+
+    snapshot::create(env)
+}
+
+pub fn get_snapshot_chunk(env: &impl Environment, snapshot_id: u64, index: u32) -> Result<Vec<u8>, String> {
+    if !env.is_controller(&env.caller()) {
+        return Err("Only admins can download snapshots".to_string());
+    }
+
+    snapshot::chunk(snapshot_id, index)
+}
+
+pub fn begin_restore(env: &impl Environment, manifest: SnapshotManifest) -> Result<(), String> {
+    if !env.is_controller(&env.caller()) {
+        return Err("Only admins can restore snapshots".to_string());
+    }
+
+    snapshot::begin_restore(manifest)
+}
+
+pub fn upload_restore_chunk(env: &impl Environment, index: u32, chunk: Vec<u8>) -> Result<u32, String> {
+    if !env.is_controller(&env.caller()) {
+        return Err("Only admins can restore snapshots".to_string());
+    }
+
+    snapshot::upload_chunk(index, chunk)
+}
+
+pub fn finish_restore(env: &impl Environment) -> Result<SnapshotManifest, String> {
+    if !env.is_controller(&env.caller()) {
+        return Err("Only admins can restore snapshots".to_string());
+    }
+
+    snapshot::finish_restore()
+}
@@ -1,12 +1,19 @@
 use candid::{CandidType, Principal};
-use ic_cdk::api::time;
-use ic_cdk::{caller, query, update};
+use ic_cdk::{query, update};
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
 use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap, Storable};
+use registry_common::env::CanisterEnv;
+use registry_common::snapshot::SnapshotManifest;
 use serde::{Serialize, Deserialize as SerdeDeserialize};
 use std::cell::RefCell;
 use std::borrow::Cow;
 
+mod admin;
+mod snapshot;
+#[cfg(test)]
+mod tests;
+mod users;
+
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 type IdStore = StableBTreeMap<Principal, UserProfile, Memory>;
 
@@ -53,55 +60,12 @@ thread_local! {
 
 #[update]
 fn register_user(username: Option<String>, email: Option<String>) -> Result<UserProfile, String> {
-    let principal = caller();
-    
-    if principal == Principal::anonymous() {
-        return Err("Anonymous users cannot register".to_string());
-    }
-
-    USERS.with(|users| {
-        let mut users = users.borrow_mut();
-        
-        if users.contains_key(&principal) {
-            return Err("User already registered".to_string());
-        }
-
-        let current_time = time();
-        let user_profile = UserProfile {
-            user_principal: principal,
-            username,
-            email,
-            role: UserRole::Owner, // Default role for new users
-            created_at: current_time,
-            last_login: current_time,
-            is_active: true,
-        };
-
-        users.insert(principal, user_profile.clone());
-        Ok(user_profile)
-    })
+    users::register_user(&CanisterEnv, username, email)
 }
 
 #[update]
 fn login() -> Result<UserProfile, String> {
-    let principal = caller();
-    
-    if principal == Principal::anonymous() {
-        return Err("Anonymous users cannot login".to_string());
-    }
-
-    USERS.with(|users| {
-        let mut users = users.borrow_mut();
-        
-        match users.get(&principal) {
-            Some(mut user) => {
-                user.last_login = time();
-                users.insert(principal, user.clone());
-                Ok(user)
-            },
-            None => Err("User not registered".to_string()),
-        }
-    })
+    users::login(&CanisterEnv)
 }
 
 #[query]
@@ -113,42 +77,12 @@ fn get_user_profile(principal: Principal) -> Option<UserProfile> {
 
 #[query]
 fn get_current_user() -> Option<UserProfile> {
-    let principal = caller();
-    
-    if principal == Principal::anonymous() {
-        return None;
-    }
-
-    USERS.with(|users| {
-        users.borrow().get(&principal)
-    })
+    users::get_current_user(&CanisterEnv)
 }
 
 #[update]
 fn update_user_profile(username: Option<String>, email: Option<String>) -> Result<UserProfile, String> {
-    let principal = caller();
-    
-    if principal == Principal::anonymous() {
-        return Err("Anonymous users cannot update profile".to_string());
-    }
-
-    USERS.with(|users| {
-        let mut users = users.borrow_mut();
-        
-        match users.get(&principal) {
-            Some(mut user) => {
-                if let Some(new_username) = username {
-                    user.username = Some(new_username);
-                }
-                if let Some(new_email) = email {
-                    user.email = Some(new_email);
-                }
-                users.insert(principal, user.clone());
-                Ok(user)
-            },
-            None => Err("User not found".to_string()),
-        }
-    })
+    users::update_user_profile(&CanisterEnv, username, email)
 }
 
 #[query]
@@ -165,5 +99,30 @@ fn get_total_users() -> u64 {
     })
 }
 
+#[update]
+fn create_snapshot() -> Result<SnapshotManifest, String> {
+    admin::create_snapshot(&CanisterEnv)
+}
+
+#[query]
+fn get_snapshot_chunk(snapshot_id: u64, index: u32) -> Result<Vec<u8>, String> {
+    admin::get_snapshot_chunk(&CanisterEnv, snapshot_id, index)
+}
+
+#[update]
+fn begin_restore(manifest: SnapshotManifest) -> Result<(), String> {
+    admin::begin_restore(&CanisterEnv, manifest)
+}
+
+#[update]
+fn upload_restore_chunk(index: u32, chunk: Vec<u8>) -> Result<u32, String> {
+    admin::upload_restore_chunk(&CanisterEnv, index, chunk)
+}
+
+#[update]
+fn finish_restore() -> Result<SnapshotManifest, String> {
+    admin::finish_restore(&CanisterEnv)
+}
+
 // Export Candid interface
 ic_cdk::export_candid!();
@@ -0,0 +1,100 @@
+use candid::CandidType;
+use registry_common::env::Environment;
+use registry_common::snapshot::{Restore, Snapshot, SnapshotManifest, SnapshotSection};
+use serde::{Serialize, Deserialize as SerdeDeserialize};
+use std::cell::RefCell;
+
+use crate::UserProfile;
+use crate::USERS;
+
+const CANISTER_NAME: &str = "auth";
+
+#[derive(CandidType, Serialize, SerdeDeserialize)]
+struct AuthState {
+    users: Vec<UserProfile>,
+}
+
+thread_local! {
+    // Held on the heap only; an upgrade discards an unfinished download or restore
+    static SNAPSHOT: RefCell<Option<Snapshot>> = const { RefCell::new(None) };
+    static RESTORE: RefCell<Option<Restore>> = const { RefCell::new(None) };
+}
+
+/// Captures every user profile in one message and keeps them ready for download.
+pub fn create(env: &impl Environment) -> Result<SnapshotManifest, String> {
+    let state = AuthState {
+        users: USERS.with(|users| users.borrow().iter().map(|(_, user)| user).collect()),
+    };
+
+    let sections = vec![SnapshotSection {
+        name: "users".to_string(),
+        entries: state.users.len() as u64,
+    }];
+
+    let bytes = candid::encode_one(&state).map_err(|e| format!("Failed to encode snapshot: {}", e))?;
+    let snapshot = Snapshot::new(CANISTER_NAME, env.time(), bytes, sections);
+    let manifest = snapshot.manifest().clone();
+
+    SNAPSHOT.with(|current| *current.borrow_mut() = Some(snapshot));
+    Ok(manifest)
+}
+
+pub fn chunk(snapshot_id: u64, index: u32) -> Result<Vec<u8>, String> {
+    SNAPSHOT.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .ok_or("No snapshot has been created")?
+            .chunk(snapshot_id, index)
+    })
+}
+
+/// Starts a restore. Only allowed before any user has registered.
+pub fn begin_restore(manifest: SnapshotManifest) -> Result<(), String> {
+    if !is_empty() {
+        return Err("Users can only be restored into an empty auth canister".to_string());
+    }
+
+    let restore = Restore::new(manifest, CANISTER_NAME)?;
+    RESTORE.with(|current| *current.borrow_mut() = Some(restore));
+    Ok(())
+}
+
+/// Returns the number of chunks still missing.
+pub fn upload_chunk(index: u32, chunk: Vec<u8>) -> Result<u32, String> {
+    RESTORE.with(|current| {
+        current
+            .borrow_mut()
+            .as_mut()
+            .ok_or("No restore is in progress")?
+            .push_chunk(index, chunk)
+    })
+}
+
+/// Verifies the uploaded snapshot and writes it into stable memory.
+pub fn finish_restore() -> Result<SnapshotManifest, String> {
+    let restore = RESTORE.with(|current| current.borrow_mut().take())
+        .ok_or("No restore is in progress")?;
+    let manifest = restore.manifest().clone();
+
+    // Checked again in case users registered while the chunks were uploading
+    if !is_empty() {
+        return Err("Users can only be restored into an empty auth canister".to_string());
+    }
+
+    let bytes = restore.finish()?;
+    let state: AuthState = candid::decode_one(&bytes).map_err(|e| format!("Failed to decode snapshot: {}", e))?;
+
+    USERS.with(|users| {
+        let mut users = users.borrow_mut();
+        for user in state.users {
+            users.insert(user.user_principal, user);
+        }
+    });
+
+    Ok(manifest)
+}
+
+fn is_empty() -> bool {
+    USERS.with(|users| users.borrow().is_empty())
+}
@@ -0,0 +1,62 @@
+// Native tests of registration and profiles. Every test runs on its own thread and therefore
+// starts with an empty user store.
+
+use candid::Principal;
+use registry_common::env::{Environment, TestEnv};
+
+use crate::admin;
+use crate::users;
+
+fn principal(id: u8) -> Principal {
+    Principal::from_slice(&[id; 29])
+}
+
+#[test]
+fn users_register_once_and_log_in() {
+    let env = TestEnv::new(1_000);
+    let alice = principal(1);
+
+    let profile = users::register_user(env.act_as(alice), Some("alice".to_string()), None).unwrap();
+    assert_eq!(profile.created_at, 1_000);
+    assert!(users::register_user(env.act_as(alice), None, None).is_err());
+
+    env.advance(500);
+    let profile = users::login(env.act_as(alice)).unwrap();
+    assert_eq!(profile.last_login, env.time());
+    assert_eq!(profile.created_at, 1_000);
+
+    assert!(users::login(env.act_as(principal(2))).is_err(), "not registered");
+}
+
+#[test]
+fn anonymous_callers_are_turned_away() {
+    let env = TestEnv::new(0);
+    let anonymous = Principal::anonymous();
+
+    assert!(users::register_user(env.act_as(anonymous), None, None).is_err());
+    assert!(users::login(env.act_as(anonymous)).is_err());
+    assert!(users::get_current_user(env.act_as(anonymous)).is_none());
+}
+
+#[test]
+fn profiles_only_change_the_given_fields() {
+    let env = TestEnv::new(0);
+    let alice = principal(1);
+    users::register_user(env.act_as(alice), Some("alice".to_string()), Some("alice@example.com".to_string())).unwrap();
+
+    let profile = users::update_user_profile(env.act_as(alice), None, Some("new@example.com".to_string())).unwrap();
+    assert_eq!(profile.username.as_deref(), Some("alice"));
+    assert_eq!(profile.email.as_deref(), Some("new@example.com"));
+    assert_eq!(users::get_current_user(env.act_as(alice)).unwrap().email, profile.email);
+}
+
+#[test]
+fn snapshots_are_admin_only() {
+    let env = TestEnv::new(0);
+    env.add_controller(principal(9));
+
+    assert!(admin::create_snapshot(env.act_as(principal(1))).is_err());
+    let manifest = admin::create_snapshot(env.act_as(principal(9))).unwrap();
+    assert_eq!(manifest.canister, "auth");
+    assert!(admin::get_snapshot_chunk(env.act_as(principal(9)), manifest.snapshot_id, 0).is_ok());
+}
@@ -0,0 +1,98 @@
+//! User registration and profiles.
+//!
+//! Every function takes the calling environment explicitly, so the flows can be exercised
+//! natively in tests. The endpoints in `lib.rs` only forward to these.
+
+use candid::Principal;
+use registry_common::env::Environment;
+
+use crate::{UserProfile, UserRole, USERS};
+
+pub fn register_user(env: &impl Environment, username: Option<String>, email: Option<String>) -> Result<UserProfile, String> {
+    let principal = env.caller();
+    
+    if principal == Principal::anonymous() {
+        return Err("Anonymous users cannot register".to_string());
+    }
+
+    USERS.with(|users| {
+        let mut users = users.borrow_mut();
+        
+        if users.contains_key(&principal) {
+            return Err("User already registered".to_string());
+        }
+
+        let current_time = env.time();
+        let user_profile = UserProfile {
+            user_principal: principal,
+            username,
+            email,
+            role: UserRole::Owner, // Default role for new users
+            created_at: current_time,
+            last_login: current_time,
+            is_active: true,
+        };
+
+        users.insert(principal, user_profile.clone());
+        Ok(user_profile)
+    })
+}
+
+pub fn login(env: &impl Environment) -> Result<UserProfile, String> {
+    let principal = env.caller();
+    
+    if principal == Principal::anonymous() {
+        return Err("Anonymous users cannot login".to_string());
+    }
+
+    USERS.with(|users| {
+        let mut users = users.borrow_mut();
+        
+        match users.get(&principal) {
+            Some(mut user) => {
+                user.last_login = env.time();
+                users.insert(principal, user.clone());
+                Ok(user)
+            },
+            None => Err("User not registered".to_string()),
+        }
+    })
+}
+
+pub fn get_current_user(env: &impl Environment) -> Option<UserProfile> {
+    let principal = env.caller();
+    
+    if principal == Principal::anonymous() {
+        return None;
+    }
+
+    USERS.with(|users| {
+        users.borrow().get(&principal)
+    })
+}
+
+pub fn update_user_profile(env: &impl Environment, username: Option<String>, email: Option<String>) -> Result<UserProfile, String> {
+    let principal = env.caller();
+    
+    if principal == Principal::anonymous() {
+        return Err("Anonymous users cannot update profile".to_string());
+    }
+
+    USERS.with(|users| {
+        let mut users = users.borrow_mut();
+        
+        match users.get(&principal) {
+            Some(mut user) => {
+                if let Some(new_username) = username {
+                    user.username = Some(new_username);
+                }
+                if let Some(new_email) = email {
+                    user.email = Some(new_email);
+                }
+                users.insert(principal, user.clone());
+                Ok(user)
+            },
+            None => Err("User not found".to_string()),
+        }
+    })
+}
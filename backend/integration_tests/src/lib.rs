@@ -0,0 +1,116 @@
+//! Harness for running the auth, registry and marketplace canisters together in PocketIC.
+//!
+//! The canisters have to be built for wasm first and a PocketIC server binary must be available:
+//!
+//! ```text
+//! cargo build --target wasm32-unknown-unknown --release \
+//!     -p auth_canister -p land_registry_canister -p marketplace_canister
+//! POCKET_IC_BIN=/path/to/pocket-ic cargo test -p integration_tests -- --ignored
+//! ```
+//!
+//! The wasm locations can be overridden with `AUTH_WASM`, `LAND_REGISTRY_WASM` and
+//! `MARKETPLACE_WASM`. The scenarios are `#[ignore]`d, so a plain `cargo test --workspace` does
+//! not need them; run with `--ignored`, a missing server or module fails the scenario.
+
+use std::path::PathBuf;
+
+use candid::utils::ArgumentEncoder;
+use candid::{CandidType, Principal};
+use pocket_ic::{CallError, PocketIc, PocketIcBuilder};
+use serde::Deserialize;
+
+pub mod types;
+
+const INITIAL_CYCLES: u128 = 2_000_000_000_000;
+
+/// A canister under test, with the module it was installed from so it can be upgraded later.
+pub struct Canister {
+    pub id: Principal,
+    wasm: Vec<u8>,
+}
+
+/// The three canisters installed side by side, with the marketplace and the registry pointed at
+/// each other.
+pub struct Deployment {
+    pub pic: PocketIc,
+    pub controller: Principal,
+    pub auth: Canister,
+    pub registry: Canister,
+    pub marketplace: Canister,
+}
+
+impl Deployment {
+    /// Installs every canister. Panics when the PocketIC server or a wasm module is not
+    /// available.
+    pub fn install() -> Self {
+        assert!(std::env::var_os("POCKET_IC_BIN").is_some(), "POCKET_IC_BIN must point at a PocketIC server binary");
+
+        let auth_wasm = load_wasm("AUTH_WASM", "auth_canister");
+        let registry_wasm = load_wasm("LAND_REGISTRY_WASM", "land_registry_canister");
+        let marketplace_wasm = load_wasm("MARKETPLACE_WASM", "marketplace_canister");
+
+        // The II subnet hosts the threshold ECDSA test keys used for title certificates
+        let pic = PocketIcBuilder::new().with_ii_subnet().with_application_subnet().build();
+        let controller = principal(1);
+        let install = |wasm: Vec<u8>| {
+            let id = pic.create_canister_with_settings(Some(controller), None);
+            pic.add_cycles(id, INITIAL_CYCLES);
+            pic.install_canister(id, wasm.clone(), candid::encode_args(()).unwrap(), Some(controller));
+            Canister { id, wasm }
+        };
+
+        let auth = install(auth_wasm);
+        let registry = install(registry_wasm);
+        let marketplace = install(marketplace_wasm);
+
+        let deployment = Deployment { pic, controller, auth, registry, marketplace };
+        let wired: Result<String, String> = deployment
+            .update(&deployment.marketplace, controller, "set_asset_canister_id", (deployment.registry.id.to_text(),))
+            .expect("set_asset_canister_id should not trap");
+        wired.expect("marketplace should accept the registry canister id");
+        let wired: Result<Principal, String> = deployment
+            .update(&deployment.registry, controller, "set_marketplace_canister", (deployment.marketplace.id,))
+            .expect("set_marketplace_canister should not trap");
+        wired.expect("registry should accept the marketplace canister id");
+
+        deployment
+    }
+
+    pub fn update<Args, Out>(&self, canister: &Canister, sender: Principal, method: &str, args: Args) -> Result<Out, CallError>
+    where
+        Args: ArgumentEncoder,
+        Out: CandidType + for<'de> Deserialize<'de>,
+    {
+        pocket_ic::update_candid_as::<Args, (Out,)>(&self.pic, canister.id, sender, method, args).map(|(out,)| out)
+    }
+
+    pub fn query<Args, Out>(&self, canister: &Canister, sender: Principal, method: &str, args: Args) -> Result<Out, CallError>
+    where
+        Args: ArgumentEncoder,
+        Out: CandidType + for<'de> Deserialize<'de>,
+    {
+        pocket_ic::query_candid_as::<Args, (Out,)>(&self.pic, canister.id, sender, method, args).map(|(out,)| out)
+    }
+
+    /// Reinstalls the same module over the canister, running its upgrade hooks.
+    pub fn upgrade(&self, canister: &Canister) {
+        self.pic
+            .upgrade_canister(canister.id, canister.wasm.clone(), candid::encode_args(()).unwrap(), Some(self.controller))
+            .expect("upgrade should succeed");
+    }
+}
+
+/// Deterministic test identities; `principal(1)` is the controller of every canister.
+pub fn principal(id: u8) -> Principal {
+    Principal::from_slice(&[id; 29])
+}
+
+fn load_wasm(variable: &str, crate_name: &str) -> Vec<u8> {
+    let path = std::env::var_os(variable).map(PathBuf::from).unwrap_or_else(|| {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("../../target/wasm32-unknown-unknown/release")
+            .join(format!("{}.wasm", crate_name))
+    });
+
+    std::fs::read(&path).unwrap_or_else(|_| panic!("{} not found; build the canisters for wasm or set {}", path.display(), variable))
+}
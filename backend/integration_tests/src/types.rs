@@ -0,0 +1,148 @@
+//! Client-side views of the canister types. The canisters are cdylibs and cannot be linked here,
+//! so only the fields the scenarios look at are declared; candid ignores the rest when decoding.
+
+use candid::{CandidType, Principal};
+use serde::Deserialize;
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum LandStatus {
+    Pending,
+    Verified,
+    Rejected,
+    ForSale,
+    Sold,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct LandParcel {
+    pub id: u64,
+    pub owner: Principal,
+    pub status: LandStatus,
+    pub price: Option<u64>,
+}
+
+// `royalty` is optional on the canister side and left out here
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct LandInput {
+    pub coordinates: String,
+    pub size: f64,
+    pub description: String,
+    pub metadata: String,
+    pub price: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ListingInput {
+    pub asset_id: u64,
+    pub price: u64,
+    pub title: String,
+    pub description: String,
+    pub category: String,
+    pub tags: Vec<String>,
+    pub starts_at: Option<u64>,
+    pub expires_at: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct Listing {
+    pub id: u64,
+    pub asset_id: u64,
+    pub seller: Principal,
+    pub price: u64,
+    pub is_active: bool,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum TransactionStatus {
+    Pending,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct Transaction {
+    pub id: u64,
+    pub listing_id: u64,
+    pub seller: Principal,
+    pub buyer: Principal,
+    pub price: u64,
+    pub status: TransactionStatus,
+    pub breakdown: Option<SaleBreakdown>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct SaleBreakdown {
+    pub gross: u64,
+    pub fee: u64,
+    pub royalty: u64,
+    pub net_to_seller: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CategoryFee {
+    pub category: String,
+    pub basis_points: u16,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct FeeConfig {
+    pub basis_points: u16,
+    pub minimum_fee: u64,
+    pub category_fees: Vec<CategoryFee>,
+    pub fee_collector: Option<Principal>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum AuctionFormat {
+    English { min_increment: u64, extension: u64 },
+    Dutch { price_drop: u64, drop_interval: u64 },
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum AuctionStatus {
+    Open,
+    Settling,
+    Sold,
+    Unsold,
+    Cancelled,
+    Failed,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct AuctionInput {
+    pub asset_id: u64,
+    pub format: AuctionFormat,
+    pub start_price: u64,
+    pub reserve_price: u64,
+    pub starts_at: Option<u64>,
+    pub ends_at: u64,
+    pub title: String,
+    pub description: String,
+    pub category: String,
+    pub tags: Vec<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct Auction {
+    pub id: u64,
+    pub status: AuctionStatus,
+    pub sale_price: Option<u64>,
+    pub settlement_error: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct MarketplaceStats {
+    pub total_listings: u64,
+    pub active_listings: u64,
+    pub total_transactions: u64,
+    pub total_volume: u64,
+    pub total_fees: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct UserProfile {
+    pub user_principal: Principal,
+    pub username: Option<String>,
+    pub email: Option<String>,
+}
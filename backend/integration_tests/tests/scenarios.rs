@@ -0,0 +1,413 @@
+// End-to-end scenarios across the installed canisters. Each test gets a fresh PocketIC instance.
+
+use candid::Principal;
+use integration_tests::types::{Auction, AuctionFormat, AuctionInput, AuctionStatus, CategoryFee, FeeConfig, LandInput, LandParcel, LandStatus, Listing, ListingInput, MarketplaceStats, SaleBreakdown, Transaction, TransactionStatus, UserProfile};
+use integration_tests::{principal, Deployment};
+use registry_common::title::{self, TitleCertificate};
+
+const ICP: u64 = 100_000_000;
+const STARTING_BALANCE: u64 = 500 * ICP;
+
+fn verifier() -> Principal {
+    principal(2)
+}
+
+fn alice() -> Principal {
+    principal(3)
+}
+
+fn bob() -> Principal {
+    principal(4)
+}
+
+fn land_input() -> LandInput {
+    LandInput {
+        coordinates: "40.7128, -74.0060".to_string(),
+        size: 1000.0,
+        description: "Waterfront parcel".to_string(),
+        metadata: "Residential zoning".to_string(),
+        price: None,
+    }
+}
+
+fn listing_input(asset_id: u64) -> ListingInput {
+    ListingInput {
+        asset_id,
+        price: 100 * ICP,
+        title: "Waterfront parcel".to_string(),
+        description: "Ready to build".to_string(),
+        category: "residential".to_string(),
+        tags: vec!["waterfront".to_string()],
+        starts_at: None,
+        expires_at: None,
+    }
+}
+
+fn setup() -> Deployment {
+    let deployment = Deployment::install();
+
+    let added: Result<String, String> = deployment
+        .update(&deployment.registry, deployment.controller, "add_verifier", (verifier(),))
+        .unwrap();
+    added.unwrap();
+
+    for user in [alice(), bob()] {
+        let wallet: Result<u64, String> = deployment
+            .update(&deployment.registry, user, "initialize_user_wallet", ())
+            .unwrap();
+        assert_eq!(wallet.unwrap(), STARTING_BALANCE);
+    }
+
+    deployment
+}
+
+fn verified_land(deployment: &Deployment, owner: Principal) -> LandParcel {
+    let land: Result<LandParcel, String> = deployment
+        .update(&deployment.registry, owner, "register_land", (land_input(),))
+        .unwrap();
+    let land = land.unwrap();
+
+    let verified: Result<LandParcel, String> = deployment
+        .update(&deployment.registry, verifier(), "verify_land", (land.id,))
+        .unwrap();
+    verified.unwrap()
+}
+
+fn create_listing(deployment: &Deployment, seller: Principal, asset_id: u64) -> Listing {
+    let listing: Result<Listing, String> = deployment
+        .update(&deployment.marketplace, seller, "create_listing", (listing_input(asset_id),))
+        .unwrap();
+    listing.unwrap()
+}
+
+fn land(deployment: &Deployment, land_id: u64) -> LandParcel {
+    let land: Option<LandParcel> = deployment
+        .query(&deployment.registry, alice(), "get_land_details", (land_id,))
+        .unwrap();
+    land.expect("land should exist")
+}
+
+fn balance(deployment: &Deployment, user: Principal) -> u64 {
+    deployment.query(&deployment.registry, user, "get_wallet_balance", (user,)).unwrap()
+}
+
+#[test]
+#[ignore = "needs PocketIC and the wasm builds, run with --ignored"]
+fn registry_purchase_moves_funds_and_ownership() {
+    let deployment = setup();
+    let parcel = verified_land(&deployment, alice());
+
+    let listed: Result<LandParcel, String> = deployment
+        .update(&deployment.registry, alice(), "set_land_for_sale", (parcel.id, 100 * ICP))
+        .unwrap();
+    assert_eq!(listed.unwrap().status, LandStatus::ForSale);
+
+    let bought: Result<LandParcel, String> = deployment
+        .update(&deployment.registry, bob(), "buy_land", (parcel.id,))
+        .unwrap();
+    let bought = bought.unwrap();
+
+    assert_eq!(bought.owner, bob());
+    assert_eq!(bought.status, LandStatus::Verified);
+    assert_eq!(land(&deployment, parcel.id).owner, bob());
+    assert_eq!(balance(&deployment, alice()), STARTING_BALANCE + 100 * ICP);
+    assert_eq!(balance(&deployment, bob()), STARTING_BALANCE - 100 * ICP);
+}
+
+#[test]
+#[ignore = "needs PocketIC and the wasm builds, run with --ignored"]
+fn marketplace_purchase_moves_the_parcel() {
+    let deployment = setup();
+    let parcel = verified_land(&deployment, alice());
+    let listing = create_listing(&deployment, alice(), parcel.id);
+
+    let purchase: Result<Transaction, String> = deployment
+        .update(&deployment.marketplace, bob(), "buy_asset", (listing.id,))
+        .unwrap();
+    let purchase = purchase.unwrap();
+    assert_eq!(purchase.status, TransactionStatus::Completed);
+    assert_eq!(purchase.buyer, bob());
+
+    assert_eq!(land(&deployment, parcel.id).owner, bob());
+    assert_eq!(balance(&deployment, bob()), STARTING_BALANCE - listing.price);
+    assert_eq!(balance(&deployment, alice()), STARTING_BALANCE + listing.price);
+
+    let stats: MarketplaceStats = deployment
+        .query(&deployment.marketplace, bob(), "get_marketplace_stats", ())
+        .unwrap();
+    assert_eq!(stats.active_listings, 0);
+    assert_eq!(stats.total_volume, listing.price);
+}
+
+#[test]
+#[ignore = "needs PocketIC and the wasm builds, run with --ignored"]
+fn marketplace_fees_are_paid_to_the_collector() {
+    let deployment = setup();
+    let collector = principal(6);
+    let fees = FeeConfig {
+        basis_points: 250,
+        minimum_fee: ICP,
+        category_fees: vec![CategoryFee { category: "residential".to_string(), basis_points: 500 }],
+        fee_collector: Some(collector),
+    };
+    let configured: Result<FeeConfig, String> = deployment
+        .update(&deployment.marketplace, deployment.controller, "set_fee_config", (fees,))
+        .unwrap();
+    configured.unwrap();
+
+    let parcel = verified_land(&deployment, alice());
+    let listing = create_listing(&deployment, alice(), parcel.id);
+    let purchase: Result<Transaction, String> = deployment
+        .update(&deployment.marketplace, bob(), "buy_asset", (listing.id,))
+        .unwrap();
+
+    // Residential listings pay the 5% category rate
+    let breakdown = SaleBreakdown { gross: 100 * ICP, fee: 5 * ICP, royalty: 0, net_to_seller: 95 * ICP };
+    assert_eq!(purchase.unwrap().breakdown, Some(breakdown));
+    assert_eq!(balance(&deployment, bob()), STARTING_BALANCE - 100 * ICP);
+    assert_eq!(balance(&deployment, alice()), STARTING_BALANCE + 95 * ICP);
+    assert_eq!(balance(&deployment, collector), 5 * ICP);
+
+    let stats: MarketplaceStats = deployment
+        .query(&deployment.marketplace, bob(), "get_marketplace_stats", ())
+        .unwrap();
+    assert_eq!(stats.total_fees, 5 * ICP);
+}
+
+#[test]
+#[ignore = "needs PocketIC and the wasm builds, run with --ignored"]
+fn failed_transfer_reactivates_the_listing() {
+    let deployment = setup();
+    let parcel = verified_land(&deployment, alice());
+    let listing = create_listing(&deployment, alice(), parcel.id);
+
+    // Alice gives the parcel away after listing it, so the registry rejects the marketplace's transfer
+    let given: Result<LandParcel, String> = deployment
+        .update(&deployment.registry, alice(), "transfer_ownership", (parcel.id, principal(5)))
+        .unwrap();
+    given.unwrap();
+
+    let purchase: Result<Transaction, String> = deployment
+        .update(&deployment.marketplace, bob(), "buy_asset", (listing.id,))
+        .unwrap();
+    assert!(purchase.is_err());
+
+    let listing: Option<Listing> = deployment
+        .query(&deployment.marketplace, bob(), "get_listing", (listing.id,))
+        .unwrap();
+    assert!(listing.unwrap().is_active, "listing should be active again");
+
+    let purchases: Vec<Transaction> = deployment
+        .query(&deployment.marketplace, bob(), "get_user_purchases", (bob(),))
+        .unwrap();
+    assert_eq!(purchases.len(), 1);
+    assert_eq!(purchases[0].status, TransactionStatus::Failed);
+
+    let stats: MarketplaceStats = deployment
+        .query(&deployment.marketplace, bob(), "get_marketplace_stats", ())
+        .unwrap();
+    assert_eq!(stats.active_listings, 1);
+    assert_eq!(stats.total_volume, 0);
+
+    // The parcel stayed put and Bob's held payment came back
+    assert_eq!(land(&deployment, parcel.id).owner, principal(5));
+    assert_eq!(balance(&deployment, bob()), STARTING_BALANCE);
+    let held: u64 = deployment.query(&deployment.registry, bob(), "get_escrow_balance", (bob(),)).unwrap();
+    assert_eq!(held, 0);
+}
+
+#[test]
+#[ignore = "needs PocketIC and the wasm builds, run with --ignored"]
+fn only_owners_can_list_verified_parcels() {
+    let deployment = setup();
+    let parcel = verified_land(&deployment, alice());
+
+    let listing: Result<Listing, String> = deployment
+        .update(&deployment.marketplace, bob(), "create_listing", (listing_input(parcel.id),))
+        .unwrap();
+    assert!(listing.is_err(), "bob does not own the parcel");
+
+    let pending: Result<LandParcel, String> = deployment
+        .update(&deployment.registry, alice(), "register_land", (land_input(),))
+        .unwrap();
+    let listing: Result<Listing, String> = deployment
+        .update(&deployment.marketplace, alice(), "create_listing", (listing_input(pending.unwrap().id),))
+        .unwrap();
+    assert!(listing.is_err(), "the parcel is not verified yet");
+
+    create_listing(&deployment, alice(), parcel.id);
+}
+
+#[test]
+#[ignore = "needs PocketIC and the wasm builds, run with --ignored"]
+fn scheduled_listings_go_live_and_expire() {
+    let deployment = setup();
+    let parcel = verified_land(&deployment, alice());
+    let now = deployment.pic.get_time().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos() as u64;
+    let hour = 3_600_000_000_000;
+
+    let input = ListingInput { starts_at: Some(now + hour), expires_at: Some(now + 2 * hour), ..listing_input(parcel.id) };
+    let listing: Result<Listing, String> = deployment
+        .update(&deployment.marketplace, alice(), "create_listing", (input,))
+        .unwrap();
+    let listing = listing.unwrap();
+
+    let visible: Vec<Listing> = deployment.query(&deployment.marketplace, bob(), "get_marketplace_listings", ()).unwrap();
+    assert!(visible.is_empty(), "not live yet");
+
+    deployment.pic.advance_time(std::time::Duration::from_secs(3_600));
+    deployment.pic.tick();
+    let visible: Vec<Listing> = deployment.query(&deployment.marketplace, bob(), "search_listings", ("waterfront".to_string(),)).unwrap();
+    assert_eq!(visible.len(), 1);
+
+    // The settlement timer takes the listing down once it expires
+    deployment.pic.advance_time(std::time::Duration::from_secs(3_600));
+    for _ in 0..3 {
+        deployment.pic.tick();
+    }
+    let expired: Option<Listing> = deployment
+        .query(&deployment.marketplace, bob(), "get_listing", (listing.id,))
+        .unwrap();
+    assert!(!expired.unwrap().is_active);
+}
+
+#[test]
+#[ignore = "needs PocketIC and the wasm builds, run with --ignored"]
+fn own_listings_cannot_be_bought() {
+    let deployment = setup();
+    let parcel = verified_land(&deployment, alice());
+    let listing = create_listing(&deployment, alice(), parcel.id);
+
+    let purchase: Result<Transaction, String> = deployment
+        .update(&deployment.marketplace, alice(), "buy_asset", (listing.id,))
+        .unwrap();
+    assert!(purchase.is_err());
+
+    let purchases: Vec<Transaction> = deployment
+        .query(&deployment.marketplace, alice(), "get_user_purchases", (alice(),))
+        .unwrap();
+    assert!(purchases.is_empty(), "no transaction is recorded for a rejected purchase");
+}
+
+#[test]
+#[ignore = "needs PocketIC and the wasm builds, run with --ignored"]
+fn upgrades_preserve_state() {
+    let deployment = setup();
+
+    let profile: Result<UserProfile, String> = deployment
+        .update(&deployment.auth, alice(), "register_user", (Some("alice".to_string()), None::<String>))
+        .unwrap();
+    profile.unwrap();
+    let parcel = verified_land(&deployment, alice());
+    let listing = create_listing(&deployment, alice(), parcel.id);
+
+    deployment.upgrade(&deployment.auth);
+    deployment.upgrade(&deployment.registry);
+    deployment.upgrade(&deployment.marketplace);
+
+    let profile: Option<UserProfile> = deployment
+        .query(&deployment.auth, alice(), "get_user_profile", (alice(),))
+        .unwrap();
+    assert_eq!(profile.unwrap().username.as_deref(), Some("alice"));
+
+    assert_eq!(land(&deployment, parcel.id).status, LandStatus::Verified);
+    assert_eq!(balance(&deployment, alice()), STARTING_BALANCE);
+
+    let restored: Option<Listing> = deployment
+        .query(&deployment.marketplace, alice(), "get_listing", (listing.id,))
+        .unwrap();
+    assert_eq!(restored.unwrap().asset_id, parcel.id);
+
+    let configured: Option<String> = deployment
+        .query(&deployment.marketplace, alice(), "get_asset_canister_id", ())
+        .unwrap();
+    assert_eq!(configured, Some(deployment.registry.id.to_text()));
+
+    // Id counters survive as well, so new records do not overwrite old ones
+    let next = verified_land(&deployment, bob());
+    assert_eq!(next.id, parcel.id + 1);
+    let next_listing = create_listing(&deployment, bob(), next.id);
+    assert_eq!(next_listing.id, listing.id + 1);
+}
+
+#[test]
+#[ignore = "needs PocketIC and the wasm builds, run with --ignored"]
+fn title_certificates_verify_on_and_off_chain() {
+    let deployment = setup();
+    let parcel = verified_land(&deployment, alice());
+
+    let key: Result<String, String> = deployment
+        .update(&deployment.registry, deployment.controller, "set_title_signing_key", ("dfx_test_key".to_string(),))
+        .unwrap();
+    key.unwrap();
+
+    let refused: Result<TitleCertificate, String> = deployment
+        .update(&deployment.registry, bob(), "issue_title_certificate", (parcel.id,))
+        .unwrap();
+    assert!(refused.is_err(), "only the owner can request a certificate");
+
+    let certificate: Result<TitleCertificate, String> = deployment
+        .update(&deployment.registry, alice(), "issue_title_certificate", (parcel.id,))
+        .unwrap();
+    let certificate = certificate.unwrap();
+    assert_eq!(certificate.claims.owner, alice());
+    assert_eq!(certificate.claims.registry, deployment.registry.id);
+
+    let verified: Result<(), String> = deployment
+        .query(&deployment.registry, bob(), "verify_title_certificate", (certificate.clone(),))
+        .unwrap();
+    assert_eq!(verified, Ok(()));
+
+    let public_key: Option<Vec<u8>> = deployment
+        .query(&deployment.registry, bob(), "get_title_public_key", ())
+        .unwrap();
+    let public_key = public_key.unwrap();
+    assert_eq!(title::verify_certificate(&certificate, &public_key), Ok(()));
+
+    let mut forged = certificate;
+    forged.claims.owner = bob();
+    assert!(title::verify_certificate(&forged, &public_key).is_err());
+    let rejected: Result<(), String> = deployment
+        .query(&deployment.registry, bob(), "verify_title_certificate", (forged,))
+        .unwrap();
+    assert!(rejected.is_err());
+}
+
+#[test]
+#[ignore = "needs PocketIC and the wasm builds, run with --ignored"]
+fn dutch_auction_bid_buys_the_parcel_from_escrow() {
+    let deployment = setup();
+    let parcel = verified_land(&deployment, alice());
+    let now = deployment.pic.get_time().duration_since(std::time::UNIX_EPOCH).unwrap().as_nanos() as u64;
+
+    let input = AuctionInput {
+        asset_id: parcel.id,
+        format: AuctionFormat::Dutch { price_drop: 10 * ICP, drop_interval: 3_600_000_000_000 },
+        start_price: 100 * ICP,
+        reserve_price: 50 * ICP,
+        starts_at: None,
+        ends_at: now + 86_400_000_000_000,
+        title: "Waterfront parcel".to_string(),
+        description: "Ready to build".to_string(),
+        category: "residential".to_string(),
+        tags: vec![],
+    };
+    let auction: Result<Auction, String> = deployment
+        .update(&deployment.marketplace, alice(), "create_auction", (input,))
+        .unwrap();
+    let auction = auction.unwrap();
+
+    // Bob offers more than the asking price and pays only the current step
+    let bought: Result<Auction, String> = deployment
+        .update(&deployment.marketplace, bob(), "place_bid", (auction.id, 120 * ICP))
+        .unwrap();
+    let bought = bought.unwrap();
+    assert_eq!(bought.status, AuctionStatus::Sold);
+    assert_eq!(bought.sale_price, Some(100 * ICP));
+
+    assert_eq!(land(&deployment, parcel.id).owner, bob());
+    assert_eq!(balance(&deployment, bob()), STARTING_BALANCE - 100 * ICP);
+    assert_eq!(balance(&deployment, alice()), STARTING_BALANCE + 100 * ICP);
+    let held: u64 = deployment.query(&deployment.registry, bob(), "get_escrow_balance", (bob(),)).unwrap();
+    assert_eq!(held, 0);
+}
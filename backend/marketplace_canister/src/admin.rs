@@ -0,0 +1,95 @@
+//! Admin operations: canister wiring, configuration and snapshots.
+
+use candid::Principal;
+use registry_common::env::Environment;
+use registry_common::snapshot::SnapshotManifest;
+use registry_common::validation::ValidationLimits;
+
+use crate::fees::{self, FeeConfig};
+use crate::{snapshot, CONFIG};
+
+pub fn set_asset_canister_id(env: &impl Environment, canister_id: String) -> Result<String, String> {
+    let principal = env.caller();
+    
+    // In a production environment, you might want to restrict this to admin users
+    // For now, we'll allow any authenticated user to set it
+    if principal == Principal::anonymous() {
+        return Err("Anonymous users cannot set canister ID".to_string());
+    }
+
+    CONFIG.with(|config| {
+        let mut config = config.borrow_mut();
+        config.insert("asset_canister_id".to_string(), canister_id.clone());
+    });
+
+    Ok(canister_id)
+}
+
+pub fn set_validation_limits(env: &impl Environment, limits: ValidationLimits) -> Result<ValidationLimits, String> {
+    let principal = env.caller();
+    
+    if !env.is_controller(&principal) {
+        return Err("Only admins can change validation limits".to_string());
+    }
+
+    limits.check()?;
+    let value = serde_json::to_string(&limits).map_err(|e| format!("Failed to encode limits: {}", e))?;
+
+    CONFIG.with(|config| {
+        let mut config = config.borrow_mut();
+        config.insert("validation_limits".to_string(), value);
+    });
+
+    Ok(limits)
+}
+
+pub fn set_fee_config(env: &impl Environment, fees: FeeConfig) -> Result<FeeConfig, String> {
+    if !env.is_controller(&env.caller()) {
+        return Err("Only admins can change marketplace fees".to_string());
+    }
+
+    fees.check()?;
+    fees::store_fee_config(&fees)?;
+
+    Ok(fees)
+}
+
+pub fn create_snapshot(env: &impl Environment) -> Result<SnapshotManifest, String> {
+    if !env.is_controller(&env.caller()) {
+        return Err("Only admins can create snapshots".to_string());
+    }
+
+    snapshot::create(env)
+}
+
+pub fn get_snapshot_chunk(env: &impl Environment, snapshot_id: u64, index: u32) -> Result<Vec<u8>, String> {
+    if !env.is_controller(&env.caller()) {
+        return Err("Only admins can download snapshots".to_string());
+    }
+
+    snapshot::chunk(snapshot_id, index)
+}
+
+pub fn begin_restore(env: &impl Environment, manifest: SnapshotManifest) -> Result<(), String> {
+    if !env.is_controller(&env.caller()) {
+        return Err("Only admins can restore snapshots".to_string());
+    }
+
+    snapshot::begin_restore(manifest)
+}
+
+pub fn upload_restore_chunk(env: &impl Environment, index: u32, chunk: Vec<u8>) -> Result<u32, String> {
+    if !env.is_controller(&env.caller()) {
+        return Err("Only admins can restore snapshots".to_string());
+    }
+
+    snapshot::upload_chunk(index, chunk)
+}
+
+pub fn finish_restore(env: &impl Environment) -> Result<SnapshotManifest, String> {
+    if !env.is_controller(&env.caller()) {
+        return Err("Only admins can restore snapshots".to_string());
+    }
+
+    snapshot::finish_restore()
+}
@@ -0,0 +1,439 @@
+//! Timed auctions, the alternative to fixed-price listings.
+//!
+//! English auctions take rising bids until they close, and a late bid pushes the close out so the
+//! auction cannot be sniped. Dutch auctions start high and drop in steps until someone accepts the
+//! current price. Bids are escrow holds on the bidders' registry wallets: the functions here keep
+//! the books, and the endpoints in `lib.rs` place, release and settle the holds in between.
+
+use candid::{CandidType, Principal};
+use ic_stable_structures::Storable;
+use registry_common::env::Environment;
+use registry_common::validation::{self, ListingFields};
+use serde::{Serialize, Deserialize as SerdeDeserialize};
+use std::borrow::Cow;
+
+use crate::{get_validation_limits, listings, AUCTIONS, LISTING_ID_COUNTER};
+
+pub const AUCTION_ID_KEY: u8 = 1; // Key of the auction id counter in LISTING_ID_COUNTER
+const MAX_AUCTION_DURATION_NS: u64 = 30 * 86_400_000_000_000;
+const MAX_EXTENSION_NS: u64 = 3_600_000_000_000;
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug, PartialEq)]
+pub enum AuctionFormat {
+    English {
+        min_increment: u64, // Smallest raise over the highest bid, in e8s
+        extension: u64, // A bid closer than this to the end moves the end this far out, in ns
+    },
+    Dutch {
+        price_drop: u64, // in e8s
+        drop_interval: u64, // in ns
+    },
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug, PartialEq)]
+pub enum AuctionStatus {
+    Open,
+    Settling, // The winning bid is being settled with the registry
+    Sold,
+    Unsold, // Closed without a bid at or above the reserve
+    Cancelled,
+    Failed, // The registry refused the sale; the winning bid is refunded
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug)]
+pub struct Bid {
+    pub bidder: Principal,
+    pub amount: u64, // in e8s
+    pub hold_id: u64, // Escrow hold in the registry
+    pub placed_at: u64,
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone)]
+pub struct Auction {
+    pub id: u64,
+    pub asset_id: u64,
+    pub seller: Principal,
+    pub format: AuctionFormat,
+    pub start_price: u64, // English: the opening bid. Dutch: the price the schedule starts at
+    pub reserve_price: u64, // English: the lowest price that sells. Dutch: the floor of the schedule
+    pub starts_at: u64,
+    pub ends_at: u64, // English auctions move this out on late bids
+    pub status: AuctionStatus,
+    pub highest_bid: Option<Bid>,
+    pub bid_count: u32,
+    pub pending_refunds: Vec<Bid>, // Bids whose holds still have to be released
+    pub sale_price: Option<u64>,
+    pub settlement_error: Option<String>,
+    pub title: String,
+    pub description: String,
+    pub category: String,
+    pub tags: Vec<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl Storable for Auction {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    const BOUND: ic_stable_structures::storable::Bound = ic_stable_structures::storable::Bound::Unbounded;
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize)]
+pub struct AuctionInput {
+    pub asset_id: u64,
+    pub format: AuctionFormat,
+    pub start_price: u64,
+    pub reserve_price: u64,
+    pub starts_at: Option<u64>, // None starts the auction immediately
+    pub ends_at: u64,
+    pub title: String,
+    pub description: String,
+    pub category: String,
+    pub tags: Vec<String>,
+}
+
+impl Auction {
+    /// The price a Dutch auction asks at `now`, or the lowest acceptable bid of an English one.
+    pub fn minimum_bid(&self, now: u64) -> u64 {
+        match &self.format {
+            AuctionFormat::English { min_increment, .. } => match &self.highest_bid {
+                Some(bid) => bid.amount.saturating_add(*min_increment),
+                None => self.start_price,
+            },
+            AuctionFormat::Dutch { price_drop, drop_interval } => {
+                let steps = now.saturating_sub(self.starts_at) / drop_interval;
+                self.start_price
+                    .saturating_sub(steps.saturating_mul(*price_drop))
+                    .max(self.reserve_price)
+            },
+        }
+    }
+}
+
+/// Checks a new auction before the registry is asked whether the caller may sell the asset.
+pub fn check_auction(env: &impl Environment, input: &AuctionInput) -> Result<(), String> {
+    if env.caller() == Principal::anonymous() {
+        return Err("Anonymous users cannot create auctions".to_string());
+    }
+
+    validate_auction_input(input, env.time())?;
+    listings::ensure_not_on_market(env, input.asset_id)
+}
+
+/// Creates the auction once the registry has confirmed the caller owns the asset.
+pub fn create_auction(env: &impl Environment, input: AuctionInput) -> Result<Auction, String> {
+    let seller = env.caller();
+    let now = env.time();
+
+    // The asset may have gone on the market while the registry was being asked
+    check_auction(env, &input)?;
+
+    let id = next_auction_id();
+    let auction = Auction {
+        id,
+        asset_id: input.asset_id,
+        seller,
+        format: input.format,
+        start_price: input.start_price,
+        reserve_price: input.reserve_price,
+        starts_at: input.starts_at.unwrap_or(now),
+        ends_at: input.ends_at,
+        status: AuctionStatus::Open,
+        highest_bid: None,
+        bid_count: 0,
+        pending_refunds: Vec::new(),
+        sale_price: None,
+        settlement_error: None,
+        title: input.title,
+        description: input.description,
+        category: input.category,
+        tags: input.tags,
+        created_at: now,
+        updated_at: now,
+    };
+    store(&auction);
+
+    Ok(auction)
+}
+
+/// Auctions can only be cancelled before the first bid.
+pub fn cancel_auction(env: &impl Environment, auction_id: u64) -> Result<Auction, String> {
+    let mut auction = get_auction(auction_id).ok_or("Auction not found")?;
+
+    if auction.seller != env.caller() {
+        return Err("Only the seller can cancel the auction".to_string());
+    }
+
+    if auction.status != AuctionStatus::Open {
+        return Err(format!("Auction is {:?}", auction.status));
+    }
+
+    if auction.highest_bid.is_some() {
+        return Err("Auctions with bids cannot be cancelled".to_string());
+    }
+
+    auction.status = AuctionStatus::Cancelled;
+    auction.updated_at = env.time();
+    store(&auction);
+
+    Ok(auction)
+}
+
+/// Checks a bid before its funds are held. Returns the auction it is for.
+pub fn check_bid(env: &impl Environment, auction_id: u64, amount: u64) -> Result<Auction, String> {
+    let bidder = env.caller();
+    let now = env.time();
+
+    if bidder == Principal::anonymous() {
+        return Err("Anonymous users cannot bid".to_string());
+    }
+
+    let auction = get_auction(auction_id).ok_or("Auction not found")?;
+
+    if auction.status != AuctionStatus::Open {
+        return Err(format!("Auction is {:?}", auction.status));
+    }
+
+    if now < auction.starts_at {
+        return Err("Auction has not started yet".to_string());
+    }
+
+    // The settlement timer may not have run yet
+    if now >= auction.ends_at {
+        return Err("Auction has ended".to_string());
+    }
+
+    if auction.seller == bidder {
+        return Err("Cannot bid on your own auction".to_string());
+    }
+
+    let minimum = auction.minimum_bid(now);
+    if amount < minimum {
+        return Err(format!("Bid must be at least {} e8s", minimum));
+    }
+
+    Ok(auction)
+}
+
+/// Records a bid whose funds are now held. The auction takes over the hold either way: a bid that
+/// no longer wins because the auction moved on while the funds were held is queued for refund, as
+/// is the bid it outbids. A Dutch bid ends the auction and leaves it `Settling`.
+pub fn record_bid(env: &impl Environment, auction_id: u64, amount: u64, hold_id: u64) -> Result<Auction, String> {
+    let now = env.time();
+    let bid = Bid {
+        bidder: env.caller(),
+        amount,
+        hold_id,
+        placed_at: now,
+    };
+
+    let mut auction = match check_bid(env, auction_id, amount) {
+        Ok(auction) => auction,
+        Err(bid_err) => {
+            if let Some(mut auction) = get_auction(auction_id) {
+                auction.pending_refunds.push(bid);
+                store(&auction);
+            }
+            return Err(bid_err);
+        },
+    };
+
+    match &auction.format {
+        AuctionFormat::English { extension, .. } => {
+            if auction.ends_at - now < *extension {
+                auction.ends_at = now + extension;
+            }
+        },
+        AuctionFormat::Dutch { .. } => {
+            auction.sale_price = Some(auction.minimum_bid(now));
+            auction.ends_at = now;
+            auction.status = AuctionStatus::Settling;
+        },
+    }
+
+    if let Some(outbid) = auction.highest_bid.replace(bid) {
+        auction.pending_refunds.push(outbid);
+    }
+    auction.bid_count += 1;
+    auction.updated_at = now;
+    store(&auction);
+
+    Ok(auction)
+}
+
+/// Open auctions whose end has passed.
+pub fn due_for_settlement(env: &impl Environment) -> Vec<u64> {
+    let now = env.time();
+    AUCTIONS.with(|auctions| {
+        auctions
+            .borrow()
+            .iter()
+            .filter(|(_, auction)| auction.status == AuctionStatus::Open && auction.ends_at <= now)
+            .map(|(id, _)| id)
+            .collect()
+    })
+}
+
+/// Auctions whose settlement was started but never recorded, for instance because the settle
+/// call trapped. The caller skips any that are still being settled.
+pub fn stalled_settlements() -> Vec<Auction> {
+    AUCTIONS.with(|auctions| {
+        auctions
+            .borrow()
+            .iter()
+            .map(|(_, auction)| auction)
+            .filter(|auction| auction.status == AuctionStatus::Settling)
+            .collect()
+    })
+}
+
+/// Closes an auction that has ended. Returns it `Settling` if the highest bid meets the reserve;
+/// otherwise it closes `Unsold` and the bid is queued for refund.
+pub fn close(env: &impl Environment, auction_id: u64) -> Result<Auction, String> {
+    let mut auction = get_auction(auction_id).ok_or("Auction not found")?;
+
+    if auction.status != AuctionStatus::Open || auction.ends_at > env.time() {
+        return Err("Auction is not due for settlement".to_string());
+    }
+
+    match auction.highest_bid.clone() {
+        Some(bid) if bid.amount >= auction.reserve_price => {
+            auction.sale_price = Some(bid.amount);
+            auction.status = AuctionStatus::Settling;
+        },
+        highest_bid => {
+            auction.pending_refunds.extend(highest_bid);
+            auction.status = AuctionStatus::Unsold;
+        },
+    }
+
+    auction.updated_at = env.time();
+    store(&auction);
+    Ok(auction)
+}
+
+/// Records the outcome of the registry settlement. A failed sale refunds the winning bid.
+pub fn complete_settlement(env: &impl Environment, auction_id: u64, settlement: Result<(), String>) -> Result<Auction, String> {
+    let mut auction = get_auction(auction_id).ok_or("Auction not found")?;
+
+    if auction.status != AuctionStatus::Settling {
+        return Err(format!("Auction is {:?}", auction.status));
+    }
+
+    match settlement {
+        Ok(()) => auction.status = AuctionStatus::Sold,
+        Err(settlement_err) => {
+            auction.pending_refunds.extend(auction.highest_bid.clone());
+            auction.status = AuctionStatus::Failed;
+            auction.settlement_error = Some(settlement_err);
+        },
+    }
+
+    auction.updated_at = env.time();
+    store(&auction);
+    Ok(auction)
+}
+
+/// Bids waiting for their holds to be released, as (auction id, bid).
+pub fn pending_refunds() -> Vec<(u64, Bid)> {
+    AUCTIONS.with(|auctions| {
+        auctions
+            .borrow()
+            .iter()
+            .flat_map(|(id, auction)| auction.pending_refunds.into_iter().map(move |bid| (id, bid)))
+            .collect()
+    })
+}
+
+pub fn refund_released(auction_id: u64, hold_id: u64) {
+    if let Some(mut auction) = get_auction(auction_id) {
+        auction.pending_refunds.retain(|bid| bid.hold_id != hold_id);
+        store(&auction);
+    }
+}
+
+pub fn get_auction(auction_id: u64) -> Option<Auction> {
+    AUCTIONS.with(|auctions| auctions.borrow().get(&auction_id))
+}
+
+/// Auctions that are open for bids now.
+pub fn active_auctions(env: &impl Environment) -> Vec<Auction> {
+    let now = env.time();
+    AUCTIONS.with(|auctions| {
+        auctions
+            .borrow()
+            .iter()
+            .map(|(_, auction)| auction)
+            .filter(|auction| auction.status == AuctionStatus::Open && auction.starts_at <= now && now < auction.ends_at)
+            .collect()
+    })
+}
+
+pub fn auctions_by(seller: Principal) -> Vec<Auction> {
+    AUCTIONS.with(|auctions| {
+        auctions
+            .borrow()
+            .iter()
+            .map(|(_, auction)| auction)
+            .filter(|auction| auction.seller == seller)
+            .collect()
+    })
+}
+
+fn validate_auction_input(input: &AuctionInput, now: u64) -> Result<(), String> {
+    let fields = ListingFields {
+        price: input.start_price,
+        title: &input.title,
+        description: &input.description,
+        category: &input.category,
+        tags: &input.tags,
+    };
+    validation::validate_listing(&fields, &get_validation_limits())?;
+
+    let starts_at = input.starts_at.unwrap_or(now);
+    if starts_at < now || input.ends_at <= starts_at || input.ends_at - starts_at > MAX_AUCTION_DURATION_NS {
+        return Err("Auctions must start now or later and run for at most 30 days".to_string());
+    }
+
+    match &input.format {
+        AuctionFormat::English { min_increment, extension } => {
+            if *min_increment == 0 {
+                return Err("The minimum bid increment must be greater than zero".to_string());
+            }
+            if *extension > MAX_EXTENSION_NS {
+                return Err("The anti-sniping extension must be at most one hour".to_string());
+            }
+        },
+        AuctionFormat::Dutch { price_drop, drop_interval } => {
+            if *price_drop == 0 || *drop_interval == 0 {
+                return Err("The price drop and its interval must be greater than zero".to_string());
+            }
+            if input.reserve_price == 0 || input.reserve_price >= input.start_price {
+                return Err("The floor price must be positive and below the start price".to_string());
+            }
+        },
+    }
+
+    Ok(())
+}
+
+fn next_auction_id() -> u64 {
+    LISTING_ID_COUNTER.with(|counter| {
+        let mut counter = counter.borrow_mut();
+        let next_id = counter.get(&AUCTION_ID_KEY).unwrap_or(0) + 1;
+        counter.insert(AUCTION_ID_KEY, next_id);
+        next_id
+    })
+}
+
+fn store(auction: &Auction) {
+    AUCTIONS.with(|auctions| {
+        auctions.borrow_mut().insert(auction.id, auction.clone());
+    });
+}
@@ -0,0 +1,114 @@
+//! Platform fees on listing sales.
+//!
+//! The fee is a percentage of the sale price with a flat minimum, optionally overridden per
+//! category, and is deducted from the seller's proceeds when the registry settles the sale.
+//! Nothing is charged until an admin sets a fee collector.
+
+use candid::{CandidType, Principal};
+use serde::{Serialize, Deserialize as SerdeDeserialize};
+
+use crate::registry::{MarketplaceFee, SaleSettlement};
+use crate::CONFIG;
+
+const FEE_CONFIG_KEY: &str = "fee_config";
+const MAX_BASIS_POINTS: u16 = 5_000;
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct FeeConfig {
+    pub basis_points: u16, // 250 = 2.5% of the sale price
+    pub minimum_fee: u64, // in e8s
+    pub category_fees: Vec<CategoryFee>,
+    pub fee_collector: Option<Principal>,
+}
+
+/// Replaces the default percentage for listings in one category. The minimum still applies.
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug, PartialEq)]
+pub struct CategoryFee {
+    pub category: String,
+    pub basis_points: u16,
+}
+
+/// How the gross price of a completed sale was split.
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug, PartialEq)]
+pub struct SaleBreakdown {
+    pub gross: u64,
+    pub fee: u64,
+    pub royalty: u64,
+    pub net_to_seller: u64,
+}
+
+impl FeeConfig {
+    /// Checks the configuration before an admin stores it.
+    pub fn check(&self) -> Result<(), String> {
+        let mut rates = std::iter::once(self.basis_points).chain(self.category_fees.iter().map(|fee| fee.basis_points));
+        if rates.any(|basis_points| basis_points > MAX_BASIS_POINTS) {
+            return Err(format!("Fees cannot exceed {} basis points", MAX_BASIS_POINTS));
+        }
+
+        if self.category_fees.iter().any(|fee| fee.category.trim().is_empty()) {
+            return Err("Category fees need a category".to_string());
+        }
+
+        if self.fee_collector == Some(Principal::anonymous()) {
+            return Err("The fee collector cannot be the anonymous principal".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// The fee on a sale at `price` in `category`, or zero when no collector is set. Never more
+    /// than what is left for the seller after the parcel's `royalty`, so the minimum cannot make
+    /// a listing unsellable.
+    pub fn fee_for(&self, price: u64, category: &str, royalty: u64) -> u64 {
+        if self.fee_collector.is_none() {
+            return 0;
+        }
+
+        let basis_points = self.category_fees
+            .iter()
+            .find(|fee| fee.category.eq_ignore_ascii_case(category))
+            .map_or(self.basis_points, |fee| fee.basis_points);
+        let percentage = (price as u128 * basis_points as u128 / 10_000) as u64;
+
+        percentage.max(self.minimum_fee).min(price.saturating_sub(royalty))
+    }
+
+    /// The fee to charge on a sale, paid to the collector, if there is one.
+    pub fn collector_fee(&self, price: u64, category: &str, royalty: u64) -> Option<MarketplaceFee> {
+        let collector = self.fee_collector?;
+        let amount = self.fee_for(price, category, royalty);
+
+        (amount > 0).then_some(MarketplaceFee { amount, collector })
+    }
+}
+
+impl From<&SaleSettlement> for SaleBreakdown {
+    fn from(sale: &SaleSettlement) -> Self {
+        SaleBreakdown {
+            gross: sale.price,
+            fee: sale.marketplace_fee.unwrap_or(0),
+            royalty: sale.royalty_amount,
+            net_to_seller: sale.seller_proceeds,
+        }
+    }
+}
+
+pub fn fee_config() -> FeeConfig {
+    CONFIG.with(|config| {
+        config
+            .borrow()
+            .get(&FEE_CONFIG_KEY.to_string())
+            .and_then(|value| serde_json::from_str(&value).ok())
+            .unwrap_or_default()
+    })
+}
+
+pub fn store_fee_config(fees: &FeeConfig) -> Result<(), String> {
+    let value = serde_json::to_string(fees).map_err(|e| format!("Failed to encode fees: {}", e))?;
+
+    CONFIG.with(|config| {
+        config.borrow_mut().insert(FEE_CONFIG_KEY.to_string(), value);
+    });
+
+    Ok(())
+}
@@ -0,0 +1,71 @@
+//! Guards that keep overlapping async flows away from the same work.
+//!
+//! Endpoints and the settlement timer interleave at every inter-canister call, so work that spans
+//! an await is claimed first and released when the guard drops. The claims live on the heap: if
+//! a callback traps, the future and its guard are dropped during cleanup, and an upgrade, which
+//! waits for outstanding calls, starts with none.
+
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeSet;
+use std::thread::LocalKey;
+
+thread_local! {
+    static SWEEP_RUNNING: Cell<bool> = const { Cell::new(false) };
+    static SETTLING_AUCTIONS: RefCell<BTreeSet<u64>> = const { RefCell::new(BTreeSet::new()) };
+    static RELEASING_HOLDS: RefCell<BTreeSet<u64>> = const { RefCell::new(BTreeSet::new()) };
+    static PURCHASING_LISTINGS: RefCell<BTreeSet<u64>> = const { RefCell::new(BTreeSet::new()) };
+}
+
+/// Held while the settlement timer's sweep runs, so a slow sweep is not overlapped by the next.
+pub struct SweepGuard;
+
+impl SweepGuard {
+    pub fn acquire() -> Option<Self> {
+        // `then`, not `then_some`: a guard built for a failed acquire would release on drop
+        SWEEP_RUNNING.with(|running| (!running.replace(true)).then(|| SweepGuard))
+    }
+}
+
+impl Drop for SweepGuard {
+    fn drop(&mut self) {
+        SWEEP_RUNNING.with(|running| running.set(false));
+    }
+}
+
+/// A claim on one id in a set of in-flight work.
+pub struct Claim {
+    set: &'static LocalKey<RefCell<BTreeSet<u64>>>,
+    id: u64,
+}
+
+impl Claim {
+    fn acquire(set: &'static LocalKey<RefCell<BTreeSet<u64>>>, id: u64) -> Option<Self> {
+        set.with(|claimed| claimed.borrow_mut().insert(id)).then(|| Claim { set, id })
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        self.set.with(|claimed| claimed.borrow_mut().remove(&self.id));
+    }
+}
+
+/// Claims the release of an escrow hold. `None` if another flow is already releasing it.
+pub fn release_hold(hold_id: u64) -> Option<Claim> {
+    Claim::acquire(&RELEASING_HOLDS, hold_id)
+}
+
+/// Claims the settlement of an auction. `None` if it is already being settled.
+pub fn settle_auction(auction_id: u64) -> Option<Claim> {
+    Claim::acquire(&SETTLING_AUCTIONS, auction_id)
+}
+
+/// Claims a listing for a purchase in flight. `None` if it is already being bought.
+pub fn purchase_listing(listing_id: u64) -> Option<Claim> {
+    Claim::acquire(&PURCHASING_LISTINGS, listing_id)
+}
+
+/// Whether a purchase of the listing is in flight. The seller cannot cancel or relist meanwhile.
+pub fn purchasing(listing_id: u64) -> bool {
+    PURCHASING_LISTINGS.with(|claimed| claimed.borrow().contains(&listing_id))
+}
@@ -1,18 +1,38 @@
 use candid::{CandidType, Principal};
-use ic_cdk::api::time;
-use ic_cdk::{caller, query, update, call};
+use ic_cdk::{init, inspect_message, post_upgrade, query, update};
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
 use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap, Storable};
+use registry_common::env::CanisterEnv;
+use registry_common::snapshot::SnapshotManifest;
+use registry_common::validation::{self, ListingFields, ValidationLimits};
 use serde::{Serialize, Deserialize as SerdeDeserialize};
 use std::cell::RefCell;
 use std::borrow::Cow;
+use std::time::Duration;
+
+mod admin;
+mod auctions;
+mod fees;
+mod guards;
+mod listings;
+mod registry;
+mod snapshot;
+#[cfg(test)]
+mod tests;
+
+use auctions::{Auction, AuctionInput};
+use fees::{FeeConfig, SaleBreakdown};
+
+// How often expired listings are taken down, ended auctions settled and outstanding refunds retried
+const SETTLEMENT_INTERVAL: Duration = Duration::from_secs(60);
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 type ListingStore = StableBTreeMap<u64, Listing, Memory>;
 type TransactionStore = StableBTreeMap<u64, Transaction, Memory>;
-type ListingIdCounter = StableBTreeMap<u8, u64, Memory>;
+type ListingIdCounter = StableBTreeMap<u8, u64, Memory>; // Id counters: 0 for listings, 1 for auctions
 type TransactionIdCounter = StableBTreeMap<u8, u64, Memory>;
 type ConfigStore = StableBTreeMap<String, String, Memory>;
+type AuctionStore = StableBTreeMap<u64, Auction, Memory>;
 
 #[derive(CandidType, Serialize, SerdeDeserialize, Clone)]
 pub struct Listing {
@@ -27,6 +47,20 @@ pub struct Listing {
     pub description: String,
     pub category: String,
     pub tags: Vec<String>,
+    // Optional so that listings created before listing windows still decode
+    pub starts_at: Option<u64>, // None lists the asset immediately
+    pub expires_at: Option<u64>, // None keeps the listing up until it is sold or cancelled
+}
+
+impl Listing {
+    /// Whether buyers can see and buy the listing at `now`.
+    pub fn is_live(&self, now: u64) -> bool {
+        self.is_active && self.starts_at.is_none_or(|starts_at| starts_at <= now) && !self.has_expired(now)
+    }
+
+    pub fn has_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
 }
 
 impl Storable for Listing {
@@ -51,6 +85,10 @@ pub struct Transaction {
     pub price: u64,
     pub transaction_time: u64,
     pub status: TransactionStatus,
+    // Optional so that transactions recorded before payments were collected still decode
+    pub hold_id: Option<u64>, // Escrow hold in the registry with the buyer's payment
+    pub steps: Option<Vec<TransactionStep>>,
+    pub breakdown: Option<SaleBreakdown>, // Set once the sale settles
 }
 
 impl Storable for Transaction {
@@ -73,6 +111,23 @@ pub enum TransactionStatus {
     Cancelled,
 }
 
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug, PartialEq)]
+pub enum TransactionStepKind {
+    PaymentHeld,
+    PaymentFailed,
+    Settled, // The asset moved to the buyer and the seller was paid
+    SettlementFailed,
+    Refunded,
+    RefundFailed, // Retried by the settlement timer
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug)]
+pub struct TransactionStep {
+    pub kind: TransactionStepKind,
+    pub timestamp: u64,
+    pub detail: Option<String>,
+}
+
 #[derive(CandidType, Serialize, SerdeDeserialize)]
 pub struct ListingInput {
     pub asset_id: u64,
@@ -81,6 +136,8 @@ pub struct ListingInput {
     pub description: String,
     pub category: String,
     pub tags: Vec<String>,
+    pub starts_at: Option<u64>, // A future time schedules the listing; None lists it now
+    pub expires_at: Option<u64>,
 }
 
 #[derive(CandidType, Serialize, SerdeDeserialize)]
@@ -89,6 +146,7 @@ pub struct MarketplaceStats {
     pub active_listings: u64,
     pub total_transactions: u64,
     pub total_volume: u64, // in e8s
+    pub total_fees: u64, // Fee revenue from completed sales, in e8s
 }
 
 thread_local! {
@@ -124,6 +182,56 @@ thread_local! {
             MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4))),
         )
     );
+
+    static AUCTIONS: RefCell<AuctionStore> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(5))),
+        )
+    );
+}
+
+#[init]
+fn init() {
+    start_timers();
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    // Timers do not survive upgrades and have to be re-armed
+    start_timers();
+}
+
+fn start_timers() {
+    ic_cdk_timers::set_timer_interval(SETTLEMENT_INTERVAL, || ic_cdk::spawn(sweep_and_settle()));
+}
+
+// Drop clearly invalid ingress before it is executed and charged for.
+// Inter-canister calls skip this hook, so the endpoints validate again.
+#[inspect_message]
+fn inspect_message() {
+    let method = ic_cdk::api::call::method_name();
+
+    if method == "create_listing" {
+        let (listing_input,): (ListingInput,) = ic_cdk::api::call::arg_data(Default::default());
+        if validate_listing_input(&listing_input).is_err() {
+            return;
+        }
+    }
+
+    ic_cdk::api::call::accept_message();
+}
+
+fn validate_listing_input(listing_input: &ListingInput) -> Result<(), String> {
+    let fields = ListingFields {
+        price: listing_input.price,
+        title: &listing_input.title,
+        description: &listing_input.description,
+        category: &listing_input.category,
+        tags: &listing_input.tags,
+    };
+
+    validation::validate_listing(&fields, &get_validation_limits())?;
+    Ok(())
 }
 
 fn get_next_listing_id() -> u64 {
@@ -147,36 +255,11 @@ fn get_next_transaction_id() -> u64 {
 }
 
 #[update]
-fn create_listing(listing_input: ListingInput) -> Result<Listing, String> {
-    let principal = caller();
-    
-    if principal == Principal::anonymous() {
-        return Err("Anonymous users cannot create listings".to_string());
-    }
+async fn create_listing(listing_input: ListingInput) -> Result<Listing, String> {
+    listings::check_listing(&CanisterEnv, &listing_input)?;
+    registry::check_listing(listing_input.asset_id, ic_cdk::caller()).await?;
 
-    let listing_id = get_next_listing_id();
-    let current_time = time();
-
-    let listing = Listing {
-        id: listing_id,
-        asset_id: listing_input.asset_id,
-        seller: principal,
-        price: listing_input.price,
-        created_at: current_time,
-        updated_at: current_time,
-        is_active: true,
-        title: listing_input.title,
-        description: listing_input.description,
-        category: listing_input.category,
-        tags: listing_input.tags,
-    };
-
-    LISTINGS.with(|listings| {
-        let mut listings = listings.borrow_mut();
-        listings.insert(listing_id, listing.clone());
-    });
-
-    Ok(listing)
+    listings::create_listing(&CanisterEnv, listing_input)
 }
 
 #[query]
@@ -188,14 +271,7 @@ fn get_listing(listing_id: u64) -> Option<Listing> {
 
 #[query]
 fn get_marketplace_listings() -> Vec<Listing> {
-    LISTINGS.with(|listings| {
-        listings
-            .borrow()
-            .iter()
-            .filter(|(_, listing)| listing.is_active)
-            .map(|(_, listing)| listing)
-            .collect()
-    })
+    listings::live_listings(&CanisterEnv)
 }
 
 #[query]
@@ -210,188 +286,55 @@ fn get_user_listings(seller: Principal) -> Vec<Listing> {
     })
 }
 
+/// Buys a listed asset. The price is held from the buyer's registry wallet first; the registry
+/// then moves the asset and pays the seller, less the platform fee, in one step. If that fails,
+/// the payment is refunded.
 #[update]
 async fn buy_asset(listing_id: u64) -> Result<Transaction, String> {
-    let buyer = caller();
-    
-    if buyer == Principal::anonymous() {
-        return Err("Anonymous users cannot buy assets".to_string());
-    }
+    // Fail before reserving the listing if the registry is not configured
+    get_asset_canister_principal()?;
 
-    // Get the asset canister principal
-    let asset_canister_principal = get_asset_canister_principal()?;
-
-    // Get the listing and validate it
-    let (listing, transaction_id) = LISTINGS.with(|listings| {
-        let mut listings = listings.borrow_mut();
-        
-        match listings.get(&listing_id) {
-            Some(mut listing) => {
-                if !listing.is_active {
-                    return Err("Listing is not active".to_string());
-                }
-                
-                if listing.seller == buyer {
-                    return Err("Cannot buy your own asset".to_string());
-                }
-
-                // Deactivate the listing temporarily
-                listing.is_active = false;
-                listing.updated_at = time();
-                listings.insert(listing_id, listing.clone());
-
-                // Get next transaction ID
-                let transaction_id = get_next_transaction_id();
-                
-                Ok((listing, transaction_id))
-            },
-            None => Err("Listing not found".to_string()),
-        }
-    })?;
-
-    // Create initial transaction record with Pending status
-    let mut transaction = Transaction {
-        id: transaction_id,
-        asset_id: listing.asset_id,
-        listing_id,
-        seller: listing.seller,
-        buyer,
-        price: listing.price,
-        transaction_time: time(),
-        status: TransactionStatus::Pending,
+    let _purchase = guards::purchase_listing(listing_id).ok_or("This listing is already being bought")?;
+    let (listing, transaction) = listings::begin_purchase(&CanisterEnv, listing_id)?;
+
+    let hold_id = match registry::hold_payment(transaction.buyer, transaction.price, listing.asset_id, transaction.id).await {
+        Ok(hold_id) => hold_id,
+        Err(hold_err) => return listings::fail_payment(&CanisterEnv, transaction, hold_err),
     };
+    let transaction = listings::record_payment(&CanisterEnv, transaction, hold_id);
 
-    TRANSACTIONS.with(|transactions| {
-        let mut transactions = transactions.borrow_mut();
-        transactions.insert(transaction_id, transaction.clone());
-    });
+    let transaction_id = transaction.id;
+    let settlement = settle_purchase(&listing, hold_id).await;
 
-    // Define a struct to match the Asset return type from the asset canister
-    #[derive(CandidType, Serialize, SerdeDeserialize)]
-    struct AssetResult {
-        id: u64,
-        name: String,
-        description: String,
-        owner: Principal,
-        file_hash: String,
-        file_url: String,
-        file_type: String,
-        file_size: u64,
-        price: u64,
-        is_for_sale: bool,
-        created_at: u64,
-        updated_at: u64,
-        category: String,
-        tags: Vec<String>,
-        preview_image_url: Option<String>,
-    }
+    // Claimed before a failed transaction becomes visible to the timer's refund retries
+    let refund_claim = guards::release_hold(hold_id);
+    let completed = listings::complete_purchase(&CanisterEnv, transaction, settlement.map(|land| land.last_sale().map(SaleBreakdown::from)));
 
-    // Now attempt to transfer ownership via inter-canister call
-    let transfer_result: Result<(Result<AssetResult, String>,), _> = call(
-        asset_canister_principal,
-        "marketplace_transfer_asset", 
-        (listing.asset_id, listing.seller, buyer),
-    ).await;
-
-    match transfer_result {
-        Ok((Ok(_asset),)) => {
-            // Transfer successful, update transaction status
-            transaction.status = TransactionStatus::Completed;
-            TRANSACTIONS.with(|transactions| {
-                let mut transactions = transactions.borrow_mut();
-                transactions.insert(transaction_id, transaction.clone());
-            });
-            Ok(transaction)
-        },
-        Ok((Err(transfer_err),)) => {
-            // Transfer failed, mark transaction as failed and reactivate listing
-            transaction.status = TransactionStatus::Failed;
-            TRANSACTIONS.with(|transactions| {
-                let mut transactions = transactions.borrow_mut();
-                transactions.insert(transaction_id, transaction.clone());
-            });
-
-            // Reactivate the listing
-            LISTINGS.with(|listings| {
-                let mut listings = listings.borrow_mut();
-                if let Some(mut reactivated_listing) = listings.get(&listing_id) {
-                    reactivated_listing.is_active = true;
-                    listings.insert(listing_id, reactivated_listing);
-                }
-            });
+    if completed.is_err() && refund_claim.is_some() {
+        let refund = registry::release_hold(hold_id).await;
+        listings::record_refund(&CanisterEnv, transaction_id, refund);
+    }
 
-            Err(format!("Failed to transfer asset ownership: {}", transfer_err))
-        },
-        Err(call_err) => {
-            // Inter-canister call failed
-            transaction.status = TransactionStatus::Failed;
-            TRANSACTIONS.with(|transactions| {
-                let mut transactions = transactions.borrow_mut();
-                transactions.insert(transaction_id, transaction.clone());
-            });
+    completed
+}
 
-            // Reactivate the listing
-            LISTINGS.with(|listings| {
-                let mut listings = listings.borrow_mut();
-                if let Some(mut reactivated_listing) = listings.get(&listing_id) {
-                    reactivated_listing.is_active = true;
-                    listings.insert(listing_id, reactivated_listing);
-                }
-            });
+// The fee is sized against the parcel's current royalty, which only the registry knows
+async fn settle_purchase(listing: &Listing, hold_id: u64) -> Result<registry::LandParcel, String> {
+    let land = registry::get_land(listing.asset_id).await?;
+    let royalty = land.royalty_on(listing.price, listing.seller);
+    let fee = fees::fee_config().collector_fee(listing.price, &listing.category, royalty);
 
-            Err(format!("Inter-canister call failed: {:?}", call_err))
-        }
-    }
+    registry::settle(hold_id, listing.seller, listing.price, fee).await
 }
 
 #[update]
 fn update_listing_price(listing_id: u64, new_price: u64) -> Result<Listing, String> {
-    let principal = caller();
-    
-    LISTINGS.with(|listings| {
-        let mut listings = listings.borrow_mut();
-        
-        match listings.get(&listing_id) {
-            Some(mut listing) => {
-                if listing.seller != principal {
-                    return Err("Only the seller can update the listing price".to_string());
-                }
-                
-                if !listing.is_active {
-                    return Err("Cannot update price of inactive listing".to_string());
-                }
-                
-                listing.price = new_price;
-                listing.updated_at = time();
-                listings.insert(listing_id, listing.clone());
-                Ok(listing)
-            },
-            None => Err("Listing not found".to_string()),
-        }
-    })
+    listings::update_listing_price(&CanisterEnv, listing_id, new_price)
 }
 
 #[update]
 fn cancel_listing(listing_id: u64) -> Result<Listing, String> {
-    let principal = caller();
-    
-    LISTINGS.with(|listings| {
-        let mut listings = listings.borrow_mut();
-        
-        match listings.get(&listing_id) {
-            Some(mut listing) => {
-                if listing.seller != principal {
-                    return Err("Only the seller can cancel the listing".to_string());
-                }
-                
-                listing.is_active = false;
-                listing.updated_at = time();
-                listings.insert(listing_id, listing.clone());
-                Ok(listing)
-            },
-            None => Err("Listing not found".to_string()),
-        }
-    })
+    listings::cancel_listing(&CanisterEnv, listing_id)
 }
 
 #[query]
@@ -432,37 +375,12 @@ fn get_user_sales(seller: Principal) -> Vec<Transaction> {
 
 #[query]
 fn search_listings(query: String) -> Vec<Listing> {
-    let query_lower = query.to_lowercase();
-    
-    LISTINGS.with(|listings| {
-        listings
-            .borrow()
-            .iter()
-            .filter(|(_, listing)| {
-                listing.is_active && (
-                    listing.title.to_lowercase().contains(&query_lower) ||
-                    listing.description.to_lowercase().contains(&query_lower) ||
-                    listing.category.to_lowercase().contains(&query_lower) ||
-                    listing.tags.iter().any(|tag| tag.to_lowercase().contains(&query_lower))
-                )
-            })
-            .map(|(_, listing)| listing)
-            .collect()
-    })
+    listings::search_listings(&CanisterEnv, query)
 }
 
 #[query]
 fn get_listings_by_category(category: String) -> Vec<Listing> {
-    LISTINGS.with(|listings| {
-        listings
-            .borrow()
-            .iter()
-            .filter(|(_, listing)| {
-                listing.is_active && listing.category.to_lowercase() == category.to_lowercase()
-            })
-            .map(|(_, listing)| listing)
-            .collect()
-    })
+    listings::listings_in_category(&CanisterEnv, category)
 }
 
 #[query]
@@ -479,15 +397,17 @@ fn get_marketplace_stats() -> MarketplaceStats {
             .count() as u64
     });
 
-    let (total_transactions, total_volume) = TRANSACTIONS.with(|transactions| {
+    let (total_transactions, total_volume, total_fees) = TRANSACTIONS.with(|transactions| {
         let transactions = transactions.borrow();
         let total_count = transactions.len();
-        let total_vol = transactions
+        let (total_vol, total_fee) = transactions
             .iter()
             .filter(|(_, transaction)| matches!(transaction.status, TransactionStatus::Completed))
-            .map(|(_, transaction)| transaction.price)
-            .sum();
-        (total_count, total_vol)
+            .fold((0, 0), |(volume, fees), (_, transaction)| {
+                let fee = transaction.breakdown.map_or(0, |breakdown| breakdown.fee);
+                (volume + transaction.price, fees + fee)
+            });
+        (total_count, total_vol, total_fee)
     });
 
     MarketplaceStats {
@@ -495,25 +415,160 @@ fn get_marketplace_stats() -> MarketplaceStats {
         active_listings,
         total_transactions,
         total_volume,
+        total_fees,
     }
 }
 
 #[update]
-fn set_asset_canister_id(canister_id: String) -> Result<String, String> {
-    let principal = caller();
-    
-    // In a production environment, you might want to restrict this to admin users
-    // For now, we'll allow any authenticated user to set it
-    if principal == Principal::anonymous() {
-        return Err("Anonymous users cannot set canister ID".to_string());
+async fn create_auction(auction_input: AuctionInput) -> Result<Auction, String> {
+    auctions::check_auction(&CanisterEnv, &auction_input)?;
+    registry::check_listing(auction_input.asset_id, ic_cdk::caller()).await?;
+
+    auctions::create_auction(&CanisterEnv, auction_input)
+}
+
+#[update]
+fn cancel_auction(auction_id: u64) -> Result<Auction, String> {
+    auctions::cancel_auction(&CanisterEnv, auction_id)
+}
+
+/// Bids on an auction. The amount is held in the caller's registry wallet until they are outbid or
+/// the auction closes; a bid on a Dutch auction buys the asset at the current price.
+#[update]
+async fn place_bid(auction_id: u64, amount: u64) -> Result<Auction, String> {
+    let auction = auctions::check_bid(&CanisterEnv, auction_id, amount)?;
+    let hold_id = registry::hold_bid(ic_cdk::caller(), amount, auction.asset_id, auction_id).await?;
+
+    let recorded = auctions::record_bid(&CanisterEnv, auction_id, amount, hold_id);
+    // Claimed before the next await, so the timer does not settle the sale at the same time
+    let settlement_claim = recorded
+        .as_ref()
+        .ok()
+        .filter(|auction| auction.status == auctions::AuctionStatus::Settling)
+        .and_then(|auction| guards::settle_auction(auction.id));
+    release_pending_refunds(auction_id).await;
+
+    let auction = recorded?;
+    let Some(claim) = settlement_claim else {
+        return Ok(auction);
+    };
+
+    let auction = settle_auction(auction, claim).await?;
+    match auction.settlement_error {
+        Some(settlement_err) => Err(settlement_err),
+        None => Ok(auction),
     }
+}
 
-    CONFIG.with(|config| {
-        let mut config = config.borrow_mut();
-        config.insert("asset_canister_id".to_string(), canister_id.clone());
-    });
+#[query]
+fn get_auction(auction_id: u64) -> Option<Auction> {
+    auctions::get_auction(auction_id)
+}
 
-    Ok(canister_id)
+#[query]
+fn get_active_auctions() -> Vec<Auction> {
+    auctions::active_auctions(&CanisterEnv)
+}
+
+#[query]
+fn get_user_auctions(seller: Principal) -> Vec<Auction> {
+    auctions::auctions_by(seller)
+}
+
+async fn sweep_and_settle() {
+    // A sweep still waiting on the registry is left to finish
+    let Some(_sweep) = guards::SweepGuard::acquire() else {
+        return;
+    };
+
+    listings::expire_listings(&CanisterEnv);
+
+    for auction_id in auctions::due_for_settlement(&CanisterEnv) {
+        let Ok(auction) = auctions::close(&CanisterEnv, auction_id) else {
+            continue;
+        };
+        if auction.status != auctions::AuctionStatus::Settling {
+            continue;
+        }
+        if let Some(claim) = guards::settle_auction(auction.id) {
+            let _ = settle_auction(auction, claim).await;
+        }
+    }
+
+    for auction in auctions::stalled_settlements() {
+        if let Some(claim) = guards::settle_auction(auction.id) {
+            retry_settlement(auction, claim).await;
+        }
+    }
+
+    // Retries refunds whose release failed earlier
+    for (auction_id, bid) in auctions::pending_refunds() {
+        if let Some(Ok(())) = release_hold(bid.hold_id).await {
+            auctions::refund_released(auction_id, bid.hold_id);
+        }
+    }
+
+    for (transaction_id, hold_id) in listings::pending_refunds() {
+        if let Some(refund) = release_hold(hold_id).await {
+            listings::record_refund(&CanisterEnv, transaction_id, refund);
+        }
+    }
+}
+
+// `_claim` keeps other flows off the auction until the outcome is recorded
+async fn settle_auction(auction: Auction, _claim: guards::Claim) -> Result<Auction, String> {
+    let settlement = match (&auction.highest_bid, auction.sale_price) {
+        (Some(bid), Some(price)) => registry::settle(bid.hold_id, auction.seller, price, None).await.map(|_land| ()),
+        _ => Err("Auction has no winning bid".to_string()),
+    };
+
+    let settled = auctions::complete_settlement(&CanisterEnv, auction.id, settlement);
+    release_pending_refunds(auction.id).await;
+    settled
+}
+
+// The registry may have completed the sale before the settle call was interrupted, in which case
+// the winning hold is gone and settling again would fail the auction after the parcel moved
+async fn retry_settlement(auction: Auction, claim: guards::Claim) {
+    let Some(winner) = auction.highest_bid.as_ref().map(|bid| bid.bidder) else {
+        let _ = settle_auction(auction, claim).await;
+        return;
+    };
+
+    match registry::get_land(auction.asset_id).await {
+        Ok(land) if land.owner == winner => {
+            let _ = auctions::complete_settlement(&CanisterEnv, auction.id, Ok(()));
+            release_pending_refunds(auction.id).await;
+        },
+        Ok(_) => {
+            let _ = settle_auction(auction, claim).await;
+        },
+        // Left `Settling` for the next sweep
+        Err(_) => {},
+    }
+}
+
+async fn release_pending_refunds(auction_id: u64) {
+    let pending = auctions::get_auction(auction_id).map(|auction| auction.pending_refunds).unwrap_or_default();
+
+    // Failures stay queued for the settlement timer
+    for bid in pending {
+        if let Some(Ok(())) = release_hold(bid.hold_id).await {
+            auctions::refund_released(auction_id, bid.hold_id);
+        }
+    }
+}
+
+// Releases a hold unless another flow is already releasing it, in which case it returns `None`
+// and leaves the bookkeeping to that flow
+async fn release_hold(hold_id: u64) -> Option<Result<(), String>> {
+    let _claim = guards::release_hold(hold_id)?;
+    Some(registry::release_hold(hold_id).await)
+}
+
+#[update]
+fn set_asset_canister_id(canister_id: String) -> Result<String, String> {
+    admin::set_asset_canister_id(&CanisterEnv, canister_id)
 }
 
 #[query]
@@ -523,6 +578,57 @@ fn get_asset_canister_id() -> Option<String> {
     })
 }
 
+#[query]
+fn get_validation_limits() -> ValidationLimits {
+    CONFIG.with(|config| {
+        config
+            .borrow()
+            .get(&"validation_limits".to_string())
+            .and_then(|value| serde_json::from_str(&value).ok())
+            .unwrap_or_default()
+    })
+}
+
+#[update]
+fn set_validation_limits(limits: ValidationLimits) -> Result<ValidationLimits, String> {
+    admin::set_validation_limits(&CanisterEnv, limits)
+}
+
+#[query]
+fn get_fee_config() -> FeeConfig {
+    fees::fee_config()
+}
+
+#[update]
+fn set_fee_config(fees: FeeConfig) -> Result<FeeConfig, String> {
+    admin::set_fee_config(&CanisterEnv, fees)
+}
+
+#[update]
+fn create_snapshot() -> Result<SnapshotManifest, String> {
+    admin::create_snapshot(&CanisterEnv)
+}
+
+#[query]
+fn get_snapshot_chunk(snapshot_id: u64, index: u32) -> Result<Vec<u8>, String> {
+    admin::get_snapshot_chunk(&CanisterEnv, snapshot_id, index)
+}
+
+#[update]
+fn begin_restore(manifest: SnapshotManifest) -> Result<(), String> {
+    admin::begin_restore(&CanisterEnv, manifest)
+}
+
+#[update]
+fn upload_restore_chunk(index: u32, chunk: Vec<u8>) -> Result<u32, String> {
+    admin::upload_restore_chunk(&CanisterEnv, index, chunk)
+}
+
+#[update]
+fn finish_restore() -> Result<SnapshotManifest, String> {
+    admin::finish_restore(&CanisterEnv)
+}
+
 fn get_asset_canister_principal() -> Result<Principal, String> {
     CONFIG.with(|config| {
         match config.borrow().get(&"asset_canister_id".to_string()) {
@@ -0,0 +1,375 @@
+//! Listing management and the local half of purchases.
+//!
+//! Every function takes the calling environment explicitly, so the flows can be exercised
+//! natively in tests. The endpoints in `lib.rs` forward to these and perform the inter-canister
+//! calls: the registry's ownership check before `create_listing`, and for a purchase the payment
+//! hold, the settlement and, if that fails, the refund. Each of those steps is recorded on the
+//! transaction.
+//!
+//! A listing may be scheduled to go live later and may expire. Buyers only see and buy listings
+//! inside their window; the settlement timer deactivates expired ones.
+
+use candid::Principal;
+use registry_common::env::Environment;
+use registry_common::validation;
+
+use crate::{get_next_listing_id, get_next_transaction_id, validate_listing_input};
+use crate::auctions::AuctionStatus;
+use crate::fees::SaleBreakdown;
+use crate::guards;
+use crate::{Listing, ListingInput, Transaction, TransactionStatus, TransactionStep, TransactionStepKind};
+use crate::{AUCTIONS, LISTINGS, TRANSACTIONS};
+
+/// Checks a new listing before the registry is asked whether the caller may sell the asset.
+pub fn check_listing(env: &impl Environment, listing_input: &ListingInput) -> Result<(), String> {
+    if env.caller() == Principal::anonymous() {
+        return Err("Anonymous users cannot create listings".to_string());
+    }
+
+    validate_listing_input(listing_input)?;
+    validate_window(listing_input, env.time())?;
+    ensure_not_on_market(env, listing_input.asset_id)
+}
+
+/// Creates the listing once the registry has confirmed the caller owns the asset.
+pub fn create_listing(env: &impl Environment, listing_input: ListingInput) -> Result<Listing, String> {
+    let principal = env.caller();
+
+    // The asset may have been listed while the registry was being asked
+    check_listing(env, &listing_input)?;
+
+    let listing_id = get_next_listing_id();
+    let current_time = env.time();
+
+    let listing = Listing {
+        id: listing_id,
+        asset_id: listing_input.asset_id,
+        seller: principal,
+        price: listing_input.price,
+        created_at: current_time,
+        updated_at: current_time,
+        is_active: true,
+        title: listing_input.title,
+        description: listing_input.description,
+        category: listing_input.category,
+        tags: listing_input.tags,
+        starts_at: listing_input.starts_at,
+        expires_at: listing_input.expires_at,
+    };
+
+    LISTINGS.with(|listings| {
+        let mut listings = listings.borrow_mut();
+        listings.insert(listing_id, listing.clone());
+    });
+
+    Ok(listing)
+}
+
+/// An asset can only be on offer once at a time, either listed or at auction. Scheduled listings
+/// and listings with a purchase in flight count; expired ones the timer has not taken down yet do
+/// not.
+pub fn ensure_not_on_market(env: &impl Environment, asset_id: u64) -> Result<(), String> {
+    let now = env.time();
+    let listed = LISTINGS.with(|listings| {
+        listings.borrow().iter().any(|(_, listing)| {
+            listing.asset_id == asset_id && ((listing.is_active && !listing.has_expired(now)) || guards::purchasing(listing.id))
+        })
+    });
+    let auctioned = AUCTIONS.with(|auctions| {
+        auctions.borrow().iter().any(|(_, auction)| {
+            auction.asset_id == asset_id && matches!(auction.status, AuctionStatus::Open | AuctionStatus::Settling)
+        })
+    });
+
+    if listed || auctioned {
+        return Err("This asset is already on the market".to_string());
+    }
+
+    Ok(())
+}
+
+/// Listings buyers can see now.
+pub fn live_listings(env: &impl Environment) -> Vec<Listing> {
+    filter_live(env, |_| true)
+}
+
+pub fn search_listings(env: &impl Environment, query: String) -> Vec<Listing> {
+    let query_lower = query.to_lowercase();
+
+    filter_live(env, |listing| {
+        listing.title.to_lowercase().contains(&query_lower) ||
+        listing.description.to_lowercase().contains(&query_lower) ||
+        listing.category.to_lowercase().contains(&query_lower) ||
+        listing.tags.iter().any(|tag| tag.to_lowercase().contains(&query_lower))
+    })
+}
+
+pub fn listings_in_category(env: &impl Environment, category: String) -> Vec<Listing> {
+    filter_live(env, |listing| listing.category.to_lowercase() == category.to_lowercase())
+}
+
+/// Deactivates listings whose window has closed. Returns their ids.
+pub fn expire_listings(env: &impl Environment) -> Vec<u64> {
+    let now = env.time();
+
+    LISTINGS.with(|listings| {
+        let mut listings = listings.borrow_mut();
+        let expired: Vec<Listing> = listings
+            .iter()
+            .map(|(_, listing)| listing)
+            .filter(|listing| listing.is_active && listing.has_expired(now))
+            .collect();
+
+        expired
+            .into_iter()
+            .map(|mut listing| {
+                listing.is_active = false;
+                listing.updated_at = now;
+                listings.insert(listing.id, listing.clone());
+                listing.id
+            })
+            .collect()
+    })
+}
+
+pub fn update_listing_price(env: &impl Environment, listing_id: u64, new_price: u64) -> Result<Listing, String> {
+    let principal = env.caller();
+    
+    LISTINGS.with(|listings| {
+        let mut listings = listings.borrow_mut();
+        
+        match listings.get(&listing_id) {
+            Some(mut listing) => {
+                if listing.seller != principal {
+                    return Err("Only the seller can update the listing price".to_string());
+                }
+                
+                if !listing.is_active {
+                    return Err("Cannot update price of inactive listing".to_string());
+                }
+
+                validation::validate_price(new_price)?;
+                
+                listing.price = new_price;
+                listing.updated_at = env.time();
+                listings.insert(listing_id, listing.clone());
+                Ok(listing)
+            },
+            None => Err("Listing not found".to_string()),
+        }
+    })
+}
+
+pub fn cancel_listing(env: &impl Environment, listing_id: u64) -> Result<Listing, String> {
+    let principal = env.caller();
+    
+    LISTINGS.with(|listings| {
+        let mut listings = listings.borrow_mut();
+        
+        match listings.get(&listing_id) {
+            Some(mut listing) => {
+                if listing.seller != principal {
+                    return Err("Only the seller can cancel the listing".to_string());
+                }
+
+                // A failed settlement would put the cancelled listing back on the market
+                if guards::purchasing(listing_id) {
+                    return Err("A purchase of this listing is in progress".to_string());
+                }
+                
+                listing.is_active = false;
+                listing.updated_at = env.time();
+                listings.insert(listing_id, listing.clone());
+                Ok(listing)
+            },
+            None => Err("Listing not found".to_string()),
+        }
+    })
+}
+
+/// Reserves the listing for the caller and records a pending transaction. The caller holds a
+/// `guards::purchase_listing` claim until the purchase completes, so the seller cannot cancel a
+/// reserved listing and have it reactivated by a failed settlement.
+pub fn begin_purchase(env: &impl Environment, listing_id: u64) -> Result<(Listing, Transaction), String> {
+    let buyer = env.caller();
+    
+    if buyer == Principal::anonymous() {
+        return Err("Anonymous users cannot buy assets".to_string());
+    }
+
+    // Get the listing and validate it
+    let (listing, transaction_id) = LISTINGS.with(|listings| {
+        let mut listings = listings.borrow_mut();
+        
+        match listings.get(&listing_id) {
+            Some(mut listing) => {
+                if !listing.is_active {
+                    return Err("Listing is not active".to_string());
+                }
+
+                if !listing.is_live(env.time()) {
+                    return Err("Listing is outside its sale window".to_string());
+                }
+                
+                if listing.seller == buyer {
+                    return Err("Cannot buy your own asset".to_string());
+                }
+
+                // Deactivate the listing temporarily
+                listing.is_active = false;
+                listing.updated_at = env.time();
+                listings.insert(listing_id, listing.clone());
+
+                // Get next transaction ID
+                let transaction_id = get_next_transaction_id();
+                
+                Ok((listing, transaction_id))
+            },
+            None => Err("Listing not found".to_string()),
+        }
+    })?;
+
+    // Create initial transaction record with Pending status
+    let transaction = Transaction {
+        id: transaction_id,
+        asset_id: listing.asset_id,
+        listing_id,
+        seller: listing.seller,
+        buyer,
+        price: listing.price,
+        transaction_time: env.time(),
+        status: TransactionStatus::Pending,
+        hold_id: None,
+        steps: Some(Vec::new()),
+        breakdown: None,
+    };
+
+    TRANSACTIONS.with(|transactions| {
+        let mut transactions = transactions.borrow_mut();
+        transactions.insert(transaction_id, transaction.clone());
+    });
+
+    Ok((listing, transaction))
+}
+
+/// The buyer's payment could not be held. The listing is released again.
+pub fn fail_payment(env: &impl Environment, mut transaction: Transaction, hold_err: String) -> Result<Transaction, String> {
+    push_step(env, &mut transaction, TransactionStepKind::PaymentFailed, Some(hold_err.clone()));
+    transaction.status = TransactionStatus::Failed;
+    store(&transaction);
+    reactivate_listing(transaction.listing_id);
+
+    Err(hold_err)
+}
+
+pub fn record_payment(env: &impl Environment, mut transaction: Transaction, hold_id: u64) -> Transaction {
+    transaction.hold_id = Some(hold_id);
+    push_step(env, &mut transaction, TransactionStepKind::PaymentHeld, None);
+    store(&transaction);
+    transaction
+}
+
+/// Records the outcome of the settlement, with the registry's split of the price when it reports
+/// one. A failed settlement reactivates the listing; the caller then refunds the held payment.
+pub fn complete_purchase(env: &impl Environment, mut transaction: Transaction, settlement: Result<Option<SaleBreakdown>, String>) -> Result<Transaction, String> {
+    match settlement {
+        Ok(breakdown) => {
+            push_step(env, &mut transaction, TransactionStepKind::Settled, None);
+            transaction.status = TransactionStatus::Completed;
+            transaction.breakdown = breakdown;
+            store(&transaction);
+            Ok(transaction)
+        },
+        Err(settle_err) => {
+            push_step(env, &mut transaction, TransactionStepKind::SettlementFailed, Some(settle_err.clone()));
+            transaction.status = TransactionStatus::Failed;
+            store(&transaction);
+            reactivate_listing(transaction.listing_id);
+            Err(settle_err)
+        },
+    }
+}
+
+pub fn record_refund(env: &impl Environment, transaction_id: u64, refund: Result<(), String>) {
+    let Some(mut transaction) = TRANSACTIONS.with(|transactions| transactions.borrow().get(&transaction_id)) else {
+        return;
+    };
+
+    match refund {
+        Ok(()) => push_step(env, &mut transaction, TransactionStepKind::Refunded, None),
+        Err(refund_err) => {
+            // Retries update the last failure rather than piling up steps
+            let steps = transaction.steps.get_or_insert_with(Vec::new);
+            if steps.last().is_some_and(|step| step.kind == TransactionStepKind::RefundFailed) {
+                steps.pop();
+            }
+            push_step(env, &mut transaction, TransactionStepKind::RefundFailed, Some(refund_err));
+        },
+    }
+    store(&transaction);
+}
+
+/// Failed purchases whose payment is still held, as (transaction id, hold id).
+pub fn pending_refunds() -> Vec<(u64, u64)> {
+    TRANSACTIONS.with(|transactions| {
+        transactions
+            .borrow()
+            .iter()
+            .filter(|(_, transaction)| matches!(transaction.status, TransactionStatus::Failed))
+            .filter_map(|(id, transaction)| {
+                let refunded = transaction.steps.unwrap_or_default().iter().any(|step| step.kind == TransactionStepKind::Refunded);
+                transaction.hold_id.filter(|_| !refunded).map(|hold_id| (id, hold_id))
+            })
+            .collect()
+    })
+}
+
+fn validate_window(listing_input: &ListingInput, now: u64) -> Result<(), String> {
+    if listing_input.starts_at.is_some_and(|starts_at| starts_at < now) {
+        return Err("Listings can only be scheduled to start in the future".to_string());
+    }
+
+    let starts_at = listing_input.starts_at.unwrap_or(now);
+    if listing_input.expires_at.is_some_and(|expires_at| expires_at <= starts_at) {
+        return Err("Listings must expire after they start".to_string());
+    }
+
+    Ok(())
+}
+
+fn filter_live(env: &impl Environment, matches: impl Fn(&Listing) -> bool) -> Vec<Listing> {
+    let now = env.time();
+
+    LISTINGS.with(|listings| {
+        listings
+            .borrow()
+            .iter()
+            .map(|(_, listing)| listing)
+            .filter(|listing| listing.is_live(now) && matches(listing))
+            .collect()
+    })
+}
+
+fn push_step(env: &impl Environment, transaction: &mut Transaction, kind: TransactionStepKind, detail: Option<String>) {
+    transaction.steps.get_or_insert_with(Vec::new).push(TransactionStep {
+        kind,
+        timestamp: env.time(),
+        detail,
+    });
+}
+
+fn store(transaction: &Transaction) {
+    TRANSACTIONS.with(|transactions| {
+        transactions.borrow_mut().insert(transaction.id, transaction.clone());
+    });
+}
+
+fn reactivate_listing(listing_id: u64) {
+    LISTINGS.with(|listings| {
+        let mut listings = listings.borrow_mut();
+        if let Some(mut reactivated_listing) = listings.get(&listing_id) {
+            reactivated_listing.is_active = true;
+            listings.insert(listing_id, reactivated_listing);
+        }
+    });
+}
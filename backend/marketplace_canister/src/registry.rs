@@ -0,0 +1,139 @@
+//! Calls into the land registry, which owns the parcels sold here and holds the wallets and escrow
+//! behind auction bids.
+
+use candid::{CandidType, Principal};
+use ic_cdk::call;
+use serde::{Serialize, Deserialize as SerdeDeserialize};
+
+use crate::get_asset_canister_principal;
+
+// The registry's `LandParcel`, reduced to the fields the marketplace reads. Candid skips the rest
+// when decoding.
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug)]
+pub struct LandParcel {
+    pub id: u64,
+    pub owner: Principal,
+    pub history: Vec<LandTransfer>,
+    pub royalty: Option<RoyaltyConfig>,
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug)]
+pub struct RoyaltyConfig {
+    pub recipient: Principal,
+    pub basis_points: u16,
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug)]
+pub struct LandTransfer {
+    pub sale: Option<SaleSettlement>,
+}
+
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug)]
+pub struct SaleSettlement {
+    pub price: u64,
+    pub royalty_amount: u64,
+    pub seller_proceeds: u64,
+    pub marketplace_fee: Option<u64>,
+}
+
+/// The commission taken from the seller's proceeds, paid to `collector`'s registry wallet.
+#[derive(CandidType, Serialize, SerdeDeserialize, Clone, Debug)]
+pub struct MarketplaceFee {
+    pub amount: u64,
+    pub collector: Principal,
+}
+
+impl LandParcel {
+    /// The royalty the registry takes out of a sale by `seller` at `price`. Registrants selling
+    /// their own parcel pay none.
+    pub fn royalty_on(&self, price: u64, seller: Principal) -> u64 {
+        match &self.royalty {
+            Some(royalty) if royalty.recipient != seller => (price as u128 * royalty.basis_points as u128 / 10_000) as u64,
+            _ => 0,
+        }
+    }
+
+    /// The split of the sale that last moved the parcel.
+    pub fn last_sale(&self) -> Option<&SaleSettlement> {
+        self.history.last().and_then(|transfer| transfer.sale.as_ref())
+    }
+}
+
+/// Confirms that the seller owns the parcel and that it is verified and free to be listed.
+pub async fn check_listing(land_id: u64, seller: Principal) -> Result<LandParcel, String> {
+    let registry = get_asset_canister_principal()?;
+    let result: Result<(Result<LandParcel, String>,), _> =
+        call(registry, "marketplace_check_listing", (land_id, seller)).await;
+
+    match result {
+        Ok((Ok(land),)) => Ok(land),
+        Ok((Err(check_err),)) => Err(format!("The asset cannot be listed: {}", check_err)),
+        Err(call_err) => Err(format!("Inter-canister call failed: {:?}", call_err)),
+    }
+}
+
+/// Looks up a parcel, for instance to find out whether an interrupted settlement went through.
+pub async fn get_land(land_id: u64) -> Result<LandParcel, String> {
+    let registry = get_asset_canister_principal()?;
+    let result: Result<(Option<LandParcel>,), _> = call(registry, "get_land_details", (land_id,)).await;
+
+    match result {
+        Ok((Some(land),)) => Ok(land),
+        Ok((None,)) => Err("Land parcel not found".to_string()),
+        Err(call_err) => Err(format!("Inter-canister call failed: {:?}", call_err)),
+    }
+}
+
+/// Holds the price of a listing from the buyer's registry wallet. Returns the hold id.
+pub async fn hold_payment(buyer: Principal, amount: u64, asset_id: u64, transaction_id: u64) -> Result<u64, String> {
+    let registry = get_asset_canister_principal()?;
+    let result: Result<(Result<u64, String>,), _> =
+        call(registry, "marketplace_hold_payment", (buyer, amount, asset_id, transaction_id)).await;
+
+    match result {
+        Ok((Ok(hold_id),)) => Ok(hold_id),
+        Ok((Err(hold_err),)) => Err(format!("Failed to collect the payment: {}", hold_err)),
+        Err(call_err) => Err(format!("Inter-canister call failed: {:?}", call_err)),
+    }
+}
+
+/// Holds `amount` from the bidder's registry wallet. Returns the hold id.
+pub async fn hold_bid(bidder: Principal, amount: u64, asset_id: u64, auction_id: u64) -> Result<u64, String> {
+    let registry = get_asset_canister_principal()?;
+    let result: Result<(Result<u64, String>,), _> =
+        call(registry, "marketplace_hold_bid", (bidder, amount, asset_id, auction_id)).await;
+
+    match result {
+        Ok((Ok(hold_id),)) => Ok(hold_id),
+        Ok((Err(hold_err),)) => Err(format!("Failed to hold the bid: {}", hold_err)),
+        Err(call_err) => Err(format!("Inter-canister call failed: {:?}", call_err)),
+    }
+}
+
+/// Returns a held payment or bid to the owner's wallet.
+pub async fn release_hold(hold_id: u64) -> Result<(), String> {
+    let registry = get_asset_canister_principal()?;
+    let result: Result<(Result<u64, String>,), _> = call(registry, "marketplace_release_hold", (hold_id,)).await;
+
+    match result {
+        Ok((Ok(_balance),)) => Ok(()),
+        // Already released, for instance by a settlement that failed after releasing
+        Ok((Err(release_err),)) if release_err == "Escrow hold not found" => Ok(()),
+        Ok((Err(release_err),)) => Err(format!("Failed to release the held funds: {}", release_err)),
+        Err(call_err) => Err(format!("Inter-canister call failed: {:?}", call_err)),
+    }
+}
+
+/// Sells the asset to the owner of the hold at `price`: the registry moves the parcel and pays the
+/// seller, less any fee, in one step. On failure the hold is left in place.
+pub async fn settle(hold_id: u64, seller: Principal, price: u64, fee: Option<MarketplaceFee>) -> Result<LandParcel, String> {
+    let registry = get_asset_canister_principal()?;
+    let result: Result<(Result<LandParcel, String>,), _> =
+        call(registry, "marketplace_settle", (hold_id, seller, price, fee)).await;
+
+    match result {
+        Ok((Ok(land),)) => Ok(land),
+        Ok((Err(settle_err),)) => Err(format!("Failed to settle the sale: {}", settle_err)),
+        Err(call_err) => Err(format!("Inter-canister call failed: {:?}", call_err)),
+    }
+}
@@ -0,0 +1,159 @@
+use candid::CandidType;
+use registry_common::env::Environment;
+use registry_common::snapshot::{Restore, Snapshot, SnapshotManifest, SnapshotSection};
+use serde::{Serialize, Deserialize as SerdeDeserialize};
+use std::cell::RefCell;
+
+use crate::auctions::{Auction, AUCTION_ID_KEY};
+use crate::{Listing, Transaction};
+use crate::{AUCTIONS, CONFIG, LISTINGS, LISTING_ID_COUNTER, TRANSACTIONS, TRANSACTION_ID_COUNTER};
+
+const CANISTER_NAME: &str = "marketplace";
+
+#[derive(CandidType, Serialize, SerdeDeserialize)]
+struct MarketplaceState {
+    last_listing_id: u64,
+    last_transaction_id: u64,
+    listings: Vec<Listing>,
+    transactions: Vec<Transaction>,
+    config: Vec<(String, String)>,
+    // Optional so that snapshots taken before auctions existed still restore
+    last_auction_id: Option<u64>,
+    auctions: Option<Vec<Auction>>,
+}
+
+thread_local! {
+    // Held on the heap only; an upgrade discards an unfinished download or restore
+    static SNAPSHOT: RefCell<Option<Snapshot>> = const { RefCell::new(None) };
+    static RESTORE: RefCell<Option<Restore>> = const { RefCell::new(None) };
+}
+
+/// Captures the whole marketplace state in one message and keeps it ready for download.
+pub fn create(env: &impl Environment) -> Result<SnapshotManifest, String> {
+    let state = MarketplaceState {
+        last_listing_id: LISTING_ID_COUNTER.with(|counter| counter.borrow().get(&0).unwrap_or(0)),
+        last_transaction_id: TRANSACTION_ID_COUNTER.with(|counter| counter.borrow().get(&0).unwrap_or(0)),
+        listings: LISTINGS.with(|listings| listings.borrow().iter().map(|(_, listing)| listing).collect()),
+        transactions: TRANSACTIONS.with(|transactions| {
+            transactions.borrow().iter().map(|(_, transaction)| transaction).collect()
+        }),
+        config: CONFIG.with(|config| config.borrow().iter().collect()),
+        last_auction_id: LISTING_ID_COUNTER.with(|counter| counter.borrow().get(&AUCTION_ID_KEY)),
+        auctions: Some(AUCTIONS.with(|auctions| auctions.borrow().iter().map(|(_, auction)| auction).collect())),
+    };
+
+    let sections = vec![
+        section("listings", state.listings.len()),
+        section("transactions", state.transactions.len()),
+        section("config", state.config.len()),
+        section("auctions", state.auctions.as_ref().map_or(0, Vec::len)),
+    ];
+
+    let bytes = candid::encode_one(&state).map_err(|e| format!("Failed to encode snapshot: {}", e))?;
+    let snapshot = Snapshot::new(CANISTER_NAME, env.time(), bytes, sections);
+    let manifest = snapshot.manifest().clone();
+
+    SNAPSHOT.with(|current| *current.borrow_mut() = Some(snapshot));
+    Ok(manifest)
+}
+
+pub fn chunk(snapshot_id: u64, index: u32) -> Result<Vec<u8>, String> {
+    SNAPSHOT.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .ok_or("No snapshot has been created")?
+            .chunk(snapshot_id, index)
+    })
+}
+
+/// Starts a restore. Only allowed into a marketplace that holds no listings or transactions yet.
+pub fn begin_restore(manifest: SnapshotManifest) -> Result<(), String> {
+    if !is_empty() {
+        return Err("State can only be restored into an empty marketplace".to_string());
+    }
+
+    let restore = Restore::new(manifest, CANISTER_NAME)?;
+    RESTORE.with(|current| *current.borrow_mut() = Some(restore));
+    Ok(())
+}
+
+/// Returns the number of chunks still missing.
+pub fn upload_chunk(index: u32, chunk: Vec<u8>) -> Result<u32, String> {
+    RESTORE.with(|current| {
+        current
+            .borrow_mut()
+            .as_mut()
+            .ok_or("No restore is in progress")?
+            .push_chunk(index, chunk)
+    })
+}
+
+/// Verifies the uploaded snapshot and writes it into stable memory.
+pub fn finish_restore() -> Result<SnapshotManifest, String> {
+    let restore = RESTORE.with(|current| current.borrow_mut().take())
+        .ok_or("No restore is in progress")?;
+    let manifest = restore.manifest().clone();
+
+    // Checked again in case listings were created while the chunks were uploading
+    if !is_empty() {
+        return Err("State can only be restored into an empty marketplace".to_string());
+    }
+
+    let bytes = restore.finish()?;
+    let state: MarketplaceState = candid::decode_one(&bytes).map_err(|e| format!("Failed to decode snapshot: {}", e))?;
+
+    LISTING_ID_COUNTER.with(|counter| {
+        counter.borrow_mut().insert(0, state.last_listing_id);
+    });
+    TRANSACTION_ID_COUNTER.with(|counter| {
+        counter.borrow_mut().insert(0, state.last_transaction_id);
+    });
+    LISTINGS.with(|listings| {
+        let mut listings = listings.borrow_mut();
+        for listing in state.listings {
+            listings.insert(listing.id, listing);
+        }
+    });
+    TRANSACTIONS.with(|transactions| {
+        let mut transactions = transactions.borrow_mut();
+        for transaction in state.transactions {
+            transactions.insert(transaction.id, transaction);
+        }
+    });
+    CONFIG.with(|config| {
+        let mut config = config.borrow_mut();
+        for (key, value) in state.config {
+            config.insert(key, value);
+        }
+    });
+    if let Some(last_auction_id) = state.last_auction_id {
+        LISTING_ID_COUNTER.with(|counter| {
+            counter.borrow_mut().insert(AUCTION_ID_KEY, last_auction_id);
+        });
+    }
+    AUCTIONS.with(|auctions| {
+        let mut auctions = auctions.borrow_mut();
+        for auction in state.auctions.unwrap_or_default() {
+            auctions.insert(auction.id, auction);
+        }
+    });
+
+    Ok(manifest)
+}
+
+// Configuration may already have been set on the target canister; the snapshot's values win.
+fn is_empty() -> bool {
+    LISTING_ID_COUNTER.with(|counter| counter.borrow().is_empty())
+        && TRANSACTION_ID_COUNTER.with(|counter| counter.borrow().is_empty())
+        && LISTINGS.with(|listings| listings.borrow().is_empty())
+        && TRANSACTIONS.with(|transactions| transactions.borrow().is_empty())
+        && AUCTIONS.with(|auctions| auctions.borrow().is_empty())
+}
+
+fn section(name: &str, entries: usize) -> SnapshotSection {
+    SnapshotSection {
+        name: name.to_string(),
+        entries: entries as u64,
+    }
+}
@@ -0,0 +1,391 @@
+// Native tests of the listing and purchase flows. Every test runs on its own thread and
+// therefore starts with empty stable structures.
+
+use candid::Principal;
+use registry_common::env::{Environment, TestEnv};
+
+use crate::admin;
+use crate::auctions::{self, AuctionFormat, AuctionInput, AuctionStatus};
+use crate::fees::{self, CategoryFee, FeeConfig, SaleBreakdown};
+use crate::guards;
+use crate::listings;
+use crate::registry::{LandParcel, RoyaltyConfig};
+use crate::{get_listing, get_marketplace_stats, ListingInput, Transaction, TransactionStatus, TransactionStepKind, TRANSACTIONS};
+
+fn principal(id: u8) -> Principal {
+    Principal::from_slice(&[id; 29])
+}
+
+fn admin() -> Principal {
+    principal(1)
+}
+
+fn seller() -> Principal {
+    principal(2)
+}
+
+fn buyer() -> Principal {
+    principal(3)
+}
+
+fn setup() -> TestEnv {
+    let env = TestEnv::new(1_700_000_000_000_000_000);
+    env.add_controller(admin());
+    env
+}
+
+fn listing_input() -> ListingInput {
+    ListingInput {
+        asset_id: 7,
+        price: 1_000_000_000,
+        title: "Harbour plot".to_string(),
+        description: "Parcel next to the virtual harbour".to_string(),
+        category: "land".to_string(),
+        tags: vec!["waterfront".to_string()],
+        starts_at: None,
+        expires_at: None,
+    }
+}
+
+#[test]
+fn sellers_manage_their_listings() {
+    let env = setup();
+    let listing = listings::create_listing(env.act_as(seller()), listing_input()).unwrap();
+    assert!(listing.is_active);
+    assert_eq!(listing.created_at, env.time());
+
+    assert!(listings::update_listing_price(env.act_as(buyer()), listing.id, 1).is_err());
+    let free = ListingInput { price: 0, ..listing_input() };
+    let create_error = listings::create_listing(env.act_as(seller()), free).err();
+    assert_eq!(listings::update_listing_price(env.act_as(seller()), listing.id, 0).err(), create_error);
+    assert!(create_error.unwrap().contains("price"));
+    env.advance(10);
+    let updated = listings::update_listing_price(env.act_as(seller()), listing.id, 2_000_000_000).unwrap();
+    assert_eq!(updated.price, 2_000_000_000);
+    assert_eq!(updated.updated_at, env.time());
+
+    assert!(listings::cancel_listing(env.act_as(buyer()), listing.id).is_err());
+    let cancelled = listings::cancel_listing(env.act_as(seller()), listing.id).unwrap();
+    assert!(!cancelled.is_active);
+}
+
+#[test]
+fn listings_are_only_on_offer_inside_their_window() {
+    let env = setup();
+    let input = ListingInput {
+        starts_at: Some(env.time() + 10 * MINUTE),
+        expires_at: Some(env.time() + 70 * MINUTE),
+        ..listing_input()
+    };
+    let listing = listings::create_listing(env.act_as(seller()), input).unwrap();
+    assert!(listing.is_active);
+
+    // Scheduled: hidden from buyers, but the asset cannot be listed twice
+    assert!(listings::live_listings(&env).is_empty());
+    assert!(listings::search_listings(&env, "harbour".to_string()).is_empty());
+    assert!(listings::begin_purchase(env.act_as(buyer()), listing.id).is_err(), "not live yet");
+    assert!(listings::check_listing(env.act_as(seller()), &listing_input()).is_err());
+
+    env.advance(10 * MINUTE);
+    assert_eq!(listings::live_listings(&env).len(), 1);
+    assert_eq!(listings::search_listings(&env, "harbour".to_string()).len(), 1);
+    assert!(listings::expire_listings(&env).is_empty());
+
+    // Expired listings drop out of search before the sweeper takes them down
+    env.advance(60 * MINUTE);
+    assert!(listings::live_listings(&env).is_empty());
+    assert!(listings::begin_purchase(env.act_as(buyer()), listing.id).is_err(), "expired");
+    assert_eq!(listings::expire_listings(&env), vec![listing.id]);
+    let expired = get_listing(listing.id).unwrap();
+    assert!(!expired.is_active);
+    assert_eq!(expired.updated_at, env.time());
+
+    listings::create_listing(env.act_as(seller()), listing_input()).unwrap();
+}
+
+#[test]
+fn invalid_listings_are_rejected() {
+    let env = setup();
+    let input = ListingInput { price: 0, ..listing_input() };
+
+    assert!(listings::create_listing(env.act_as(seller()), input).is_err());
+    assert!(listings::create_listing(env.act_as(Principal::anonymous()), listing_input()).is_err());
+
+    let past = ListingInput { starts_at: Some(env.time() - 1), ..listing_input() };
+    assert!(listings::create_listing(env.act_as(seller()), past).is_err());
+    let inverted = ListingInput { starts_at: Some(env.time() + 10), expires_at: Some(env.time() + 10), ..listing_input() };
+    assert!(listings::create_listing(env.act_as(seller()), inverted).is_err());
+}
+
+#[test]
+fn assets_are_on_the_market_once_at_a_time() {
+    let env = setup();
+    let listing = listings::create_listing(env.act_as(seller()), listing_input()).unwrap();
+
+    assert!(listings::check_listing(env.act_as(seller()), &listing_input()).is_err(), "already listed");
+    let format = AuctionFormat::English { min_increment: 100, extension: 0 };
+    assert!(auctions::check_auction(env.act_as(seller()), &auction_input(&env, format.clone())).is_err());
+
+    listings::cancel_listing(env.act_as(seller()), listing.id).unwrap();
+    auctions::create_auction(env.act_as(seller()), auction_input(&env, format)).unwrap();
+    assert!(listings::create_listing(env.act_as(seller()), listing_input()).is_err(), "at auction");
+}
+
+#[test]
+fn completed_purchase_is_recorded() {
+    let env = setup();
+    let listing = listings::create_listing(env.act_as(seller()), listing_input()).unwrap();
+
+    assert!(listings::begin_purchase(env.act_as(seller()), listing.id).is_err(), "cannot buy own asset");
+
+    let (reserved, transaction) = listings::begin_purchase(env.act_as(buyer()), listing.id).unwrap();
+    assert!(!reserved.is_active);
+    assert!(matches!(transaction.status, TransactionStatus::Pending));
+    assert!(listings::begin_purchase(env.act_as(principal(4)), listing.id).is_err(), "listing is reserved");
+
+    let transaction = listings::record_payment(&env, transaction, 41);
+    let breakdown = SaleBreakdown { gross: listing.price, fee: 25_000_000, royalty: 0, net_to_seller: 975_000_000 };
+    let transaction = listings::complete_purchase(&env, transaction, Ok(Some(breakdown.clone()))).unwrap();
+    assert!(matches!(transaction.status, TransactionStatus::Completed));
+    assert_eq!(transaction.breakdown, Some(breakdown));
+    assert_eq!(transaction.buyer, buyer());
+    assert_eq!(transaction.hold_id, Some(41));
+    assert_eq!(step_kinds(&transaction), vec![TransactionStepKind::PaymentHeld, TransactionStepKind::Settled]);
+    assert!(listings::pending_refunds().is_empty());
+
+    let stats = get_marketplace_stats();
+    assert_eq!(stats.total_transactions, 1);
+    assert_eq!(stats.total_volume, listing.price);
+    assert_eq!(stats.total_fees, 25_000_000);
+    assert_eq!(stats.active_listings, 0);
+}
+
+#[test]
+fn failed_transfer_reactivates_the_listing_and_refunds_the_buyer() {
+    let env = setup();
+    let listing = listings::create_listing(env.act_as(seller()), listing_input()).unwrap();
+    let purchase = guards::purchase_listing(listing.id).unwrap();
+    assert!(guards::purchase_listing(listing.id).is_none());
+    let (_, transaction) = listings::begin_purchase(env.act_as(buyer()), listing.id).unwrap();
+    let transaction = listings::record_payment(&env, transaction, 42);
+
+    // The seller can neither withdraw the reserved listing nor list the asset again meanwhile
+    assert!(listings::cancel_listing(env.act_as(seller()), listing.id).is_err());
+    assert!(listings::check_listing(env.act_as(seller()), &listing_input()).is_err());
+
+    let result = listings::complete_purchase(&env, transaction.clone(), Err("asset not found".to_string()));
+    assert_eq!(result.err().as_deref(), Some("asset not found"));
+    drop(purchase);
+
+    assert!(get_listing(listing.id).unwrap().is_active);
+    let stored = stored_transaction(transaction.id);
+    assert!(matches!(stored.status, TransactionStatus::Failed));
+    assert_eq!(get_marketplace_stats().total_volume, 0);
+
+    // A failed refund is retried until it goes through, by one flow at a time
+    assert_eq!(listings::pending_refunds(), vec![(transaction.id, 42)]);
+    let in_flight = guards::release_hold(42).unwrap();
+    assert!(guards::release_hold(42).is_none());
+    drop(in_flight);
+    listings::record_refund(&env, transaction.id, Err("registry stopped".to_string()));
+    listings::record_refund(&env, transaction.id, Err("registry stopped".to_string()));
+    assert_eq!(listings::pending_refunds(), vec![(transaction.id, 42)]);
+    listings::record_refund(&env, transaction.id, Ok(()));
+    assert!(listings::pending_refunds().is_empty());
+
+    assert_eq!(step_kinds(&stored_transaction(transaction.id)), vec![
+        TransactionStepKind::PaymentHeld,
+        TransactionStepKind::SettlementFailed,
+        TransactionStepKind::RefundFailed,
+        TransactionStepKind::Refunded,
+    ]);
+}
+
+#[test]
+fn unpaid_purchases_release_the_listing() {
+    let env = setup();
+    let listing = listings::create_listing(env.act_as(seller()), listing_input()).unwrap();
+    let (_, transaction) = listings::begin_purchase(env.act_as(buyer()), listing.id).unwrap();
+
+    assert!(listings::fail_payment(&env, transaction.clone(), "Insufficient funds".to_string()).is_err());
+    assert!(get_listing(listing.id).unwrap().is_active);
+    assert_eq!(step_kinds(&stored_transaction(transaction.id)), vec![TransactionStepKind::PaymentFailed]);
+    assert!(listings::pending_refunds().is_empty(), "nothing was held");
+}
+
+fn stored_transaction(transaction_id: u64) -> Transaction {
+    TRANSACTIONS.with(|transactions| transactions.borrow().get(&transaction_id)).unwrap()
+}
+
+fn step_kinds(transaction: &Transaction) -> Vec<TransactionStepKind> {
+    transaction.steps.clone().unwrap_or_default().into_iter().map(|step| step.kind).collect()
+}
+
+const MINUTE: u64 = 60_000_000_000;
+
+fn auction_input(env: &TestEnv, format: AuctionFormat) -> AuctionInput {
+    AuctionInput {
+        asset_id: 7,
+        format,
+        start_price: 1_000,
+        reserve_price: 500,
+        starts_at: None,
+        ends_at: env.time() + 60 * MINUTE,
+        title: "Harbour plot".to_string(),
+        description: "Parcel next to the virtual harbour".to_string(),
+        category: "land".to_string(),
+        tags: vec![],
+    }
+}
+
+#[test]
+fn english_auctions_extend_on_late_bids_and_refund_outbid_bidders() {
+    let env = setup();
+    let format = AuctionFormat::English { min_increment: 100, extension: 5 * MINUTE };
+    let input = AuctionInput { reserve_price: 1_500, ..auction_input(&env, format) };
+    let auction = auctions::create_auction(env.act_as(seller()), input).unwrap();
+
+    assert!(auctions::check_bid(env.act_as(seller()), auction.id, 1_000).is_err(), "own auction");
+    assert!(auctions::check_bid(env.act_as(buyer()), auction.id, 999).is_err(), "below the opening bid");
+    auctions::record_bid(env.act_as(buyer()), auction.id, 1_000, 11).unwrap();
+
+    let rival = principal(4);
+    assert!(auctions::check_bid(env.act_as(rival), auction.id, 1_050).is_err(), "below the increment");
+    let auction = auctions::record_bid(env.act_as(rival), auction.id, 1_100, 12).unwrap();
+    assert_eq!(auction.pending_refunds.iter().map(|bid| bid.hold_id).collect::<Vec<_>>(), vec![11]);
+    assert!(auctions::cancel_auction(env.act_as(seller()), auction.id).is_err(), "has bids");
+
+    // A bid in the last minutes pushes the end out
+    env.advance(58 * MINUTE);
+    let auction = auctions::record_bid(env.act_as(buyer()), auction.id, 1_600, 13).unwrap();
+    assert_eq!(auction.ends_at, env.time() + 5 * MINUTE);
+    assert_eq!(auction.bid_count, 3);
+
+    // A bid the auction moved past while its funds were held is refunded
+    assert!(auctions::record_bid(env.act_as(rival), auction.id, 1_200, 14).is_err());
+    auctions::refund_released(auction.id, 11);
+    let pending: Vec<u64> = auctions::pending_refunds().into_iter().map(|(_, bid)| bid.hold_id).collect();
+    assert_eq!(pending, vec![12, 14]);
+
+    assert!(auctions::due_for_settlement(&env).is_empty());
+    env.advance(5 * MINUTE);
+    assert_eq!(auctions::due_for_settlement(&env), vec![auction.id]);
+    let closing = auctions::close(&env, auction.id).unwrap();
+    assert_eq!(closing.status, AuctionStatus::Settling);
+    assert_eq!(closing.sale_price, Some(1_600));
+
+    let sold = auctions::complete_settlement(&env, auction.id, Ok(())).unwrap();
+    assert_eq!(sold.status, AuctionStatus::Sold);
+    assert_eq!(sold.highest_bid.unwrap().bidder, buyer());
+}
+
+#[test]
+fn dutch_auctions_sell_at_the_current_step_price() {
+    let env = setup();
+    let format = AuctionFormat::Dutch { price_drop: 200, drop_interval: 10 * MINUTE };
+    let auction = auctions::create_auction(env.act_as(seller()), auction_input(&env, format)).unwrap();
+    assert_eq!(auction.minimum_bid(env.time()), 1_000);
+    assert_eq!(auction.minimum_bid(env.time() + 25 * MINUTE), 600);
+    assert_eq!(auction.minimum_bid(env.time() + 50 * MINUTE), 500, "never below the floor");
+
+    env.advance(25 * MINUTE);
+    assert!(auctions::check_bid(env.act_as(buyer()), auction.id, 599).is_err());
+    let bought = auctions::record_bid(env.act_as(buyer()), auction.id, 700, 21).unwrap();
+    assert_eq!(bought.status, AuctionStatus::Settling);
+    assert_eq!(bought.sale_price, Some(600));
+    assert!(auctions::check_bid(env.act_as(principal(4)), auction.id, 1_000).is_err(), "already sold");
+
+    let failed = auctions::complete_settlement(&env, auction.id, Err("parcel is frozen".to_string())).unwrap();
+    assert_eq!(failed.status, AuctionStatus::Failed);
+    assert_eq!(failed.pending_refunds[0].hold_id, 21);
+}
+
+#[test]
+fn interrupted_settlements_are_found_and_claimed_once() {
+    let env = setup();
+    let format = AuctionFormat::Dutch { price_drop: 200, drop_interval: 10 * MINUTE };
+    let auction = auctions::create_auction(env.act_as(seller()), auction_input(&env, format)).unwrap();
+    auctions::record_bid(env.act_as(buyer()), auction.id, 1_000, 51).unwrap();
+
+    // Left `Settling`, as after a settle call that trapped
+    let stalled: Vec<u64> = auctions::stalled_settlements().into_iter().map(|auction| auction.id).collect();
+    assert_eq!(stalled, vec![auction.id]);
+
+    let claim = guards::settle_auction(auction.id).unwrap();
+    assert!(guards::settle_auction(auction.id).is_none(), "already being settled");
+    assert!(guards::settle_auction(auction.id).is_none(), "a refused claim leaves the holder's in place");
+    drop(claim);
+    assert!(guards::settle_auction(auction.id).is_some());
+
+    let sweep = guards::SweepGuard::acquire().unwrap();
+    assert!(guards::SweepGuard::acquire().is_none(), "the previous sweep is still running");
+    assert!(guards::SweepGuard::acquire().is_none());
+    drop(sweep);
+    assert!(guards::SweepGuard::acquire().is_some());
+
+    auctions::complete_settlement(&env, auction.id, Ok(())).unwrap();
+    assert!(auctions::stalled_settlements().is_empty());
+}
+
+#[test]
+fn unsold_auctions_refund_bids_below_the_reserve() {
+    let env = setup();
+    let format = AuctionFormat::English { min_increment: 100, extension: 0 };
+    let input = AuctionInput { reserve_price: 5_000, ..auction_input(&env, format) };
+    let auction = auctions::create_auction(env.act_as(seller()), input).unwrap();
+    auctions::record_bid(env.act_as(buyer()), auction.id, 1_000, 31).unwrap();
+
+    assert!(auctions::close(&env, auction.id).is_err(), "still running");
+    env.advance(60 * MINUTE);
+    assert!(auctions::check_bid(env.act_as(principal(4)), auction.id, 6_000).is_err(), "ended");
+
+    let closed = auctions::close(&env, auction.id).unwrap();
+    assert_eq!(closed.status, AuctionStatus::Unsold);
+    assert_eq!(closed.pending_refunds[0].hold_id, 31);
+}
+
+#[test]
+fn validation_limits_are_admin_only() {
+    let env = setup();
+    let limits = crate::get_validation_limits();
+
+    assert!(admin::set_validation_limits(env.act_as(seller()), limits.clone()).is_err());
+    assert!(admin::set_validation_limits(env.act_as(admin()), limits).is_ok());
+}
+
+#[test]
+fn fees_are_admin_configured_and_need_a_collector() {
+    let env = setup();
+    let config = FeeConfig {
+        basis_points: 250,
+        minimum_fee: 1_000,
+        category_fees: vec![CategoryFee { category: "Premium".to_string(), basis_points: 500 }],
+        fee_collector: None,
+    };
+    assert_eq!(config.fee_for(1_000_000, "land", 0), 0, "no collector");
+
+    let config = FeeConfig { fee_collector: Some(admin()), ..config };
+    assert_eq!(config.fee_for(1_000_000, "land", 0), 25_000);
+    assert_eq!(config.fee_for(1_000_000, "premium", 0), 50_000);
+    assert_eq!(config.fee_for(10_000, "land", 0), 1_000, "minimum fee");
+    assert_eq!(config.fee_for(500, "land", 0), 500, "never more than the price");
+    // The minimum never eats into the royalty, which would make the registry refuse the sale
+    assert_eq!(config.fee_for(1_200, "land", 600), 600);
+    assert_eq!(config.fee_for(1_200, "land", 1_200), 0);
+    assert!(config.collector_fee(1_200, "land", 1_200).is_none());
+
+    let royalty = Some(RoyaltyConfig { recipient: principal(5), basis_points: 500 });
+    let land = LandParcel { id: 7, owner: seller(), history: vec![], royalty };
+    assert_eq!(land.royalty_on(1_200, seller()), 60);
+    assert_eq!(land.royalty_on(1_200, principal(5)), 0, "the registrant pays no royalty");
+
+    let invalid = FeeConfig { basis_points: 5_001, ..config.clone() };
+    assert!(admin::set_fee_config(env.act_as(admin()), invalid).is_err());
+    let anonymous = FeeConfig { fee_collector: Some(Principal::anonymous()), ..config.clone() };
+    assert!(admin::set_fee_config(env.act_as(admin()), anonymous).is_err());
+
+    assert!(admin::set_fee_config(env.act_as(seller()), config.clone()).is_err());
+    assert_eq!(fees::fee_config(), FeeConfig::default());
+    admin::set_fee_config(env.act_as(admin()), config.clone()).unwrap();
+    assert_eq!(fees::fee_config(), config);
+}
@@ -0,0 +1,87 @@
+//! The parts of the execution environment canister logic depends on.
+//!
+//! Canister logic takes an `Environment` instead of calling `ic_cdk` directly, so the same code
+//! runs on the replica with `CanisterEnv` and in native `cargo test` runs with `TestEnv`.
+
+use candid::Principal;
+use std::cell::{Cell, RefCell};
+
+pub trait Environment {
+    /// Current time in nanoseconds since the Unix epoch.
+    fn time(&self) -> u64;
+    /// Principal that sent the message being executed.
+    fn caller(&self) -> Principal;
+    fn is_controller(&self, principal: &Principal) -> bool;
+    /// Principal of the canister itself, used as the actor of timer-driven actions.
+    fn canister_id(&self) -> Principal;
+}
+
+/// The real environment, backed by the system API.
+pub struct CanisterEnv;
+
+impl Environment for CanisterEnv {
+    fn time(&self) -> u64 {
+        ic_cdk::api::time()
+    }
+
+    fn caller(&self) -> Principal {
+        ic_cdk::caller()
+    }
+
+    fn is_controller(&self, principal: &Principal) -> bool {
+        ic_cdk::api::is_controller(principal)
+    }
+
+    fn canister_id(&self) -> Principal {
+        ic_cdk::id()
+    }
+}
+
+/// A scripted environment for native tests. The clock only moves when told to.
+pub struct TestEnv {
+    now: Cell<u64>,
+    caller: Cell<Principal>,
+    controllers: RefCell<Vec<Principal>>,
+}
+
+impl TestEnv {
+    pub fn new(now: u64) -> Self {
+        TestEnv {
+            now: Cell::new(now),
+            caller: Cell::new(Principal::anonymous()),
+            controllers: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Makes subsequent calls come from `caller`.
+    pub fn act_as(&self, caller: Principal) -> &Self {
+        self.caller.set(caller);
+        self
+    }
+
+    pub fn advance(&self, nanos: u64) {
+        self.now.set(self.now.get() + nanos);
+    }
+
+    pub fn add_controller(&self, principal: Principal) {
+        self.controllers.borrow_mut().push(principal);
+    }
+}
+
+impl Environment for TestEnv {
+    fn time(&self) -> u64 {
+        self.now.get()
+    }
+
+    fn caller(&self) -> Principal {
+        self.caller.get()
+    }
+
+    fn is_controller(&self, principal: &Principal) -> bool {
+        self.controllers.borrow().contains(principal)
+    }
+
+    fn canister_id(&self) -> Principal {
+        Principal::from_slice(&[0xff; 10])
+    }
+}
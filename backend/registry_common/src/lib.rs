@@ -0,0 +1,6 @@
+//! Code shared by the land registry, marketplace and auth canisters.
+
+pub mod env;
+pub mod snapshot;
+pub mod title;
+pub mod validation;
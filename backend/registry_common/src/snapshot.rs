@@ -0,0 +1,214 @@
+//! Chunked state snapshots for backing up a canister and restoring it into a fresh one.
+//!
+//! A canister serializes its whole state into one buffer within a single message, so the snapshot
+//! is consistent. The buffer is then downloaded in chunks that each fit in a message. Restores upload
+//! the chunks in order and only apply them once the total length and checksum match the manifest.
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Kept well under the 2 MiB ingress and response limits.
+pub const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Bumped whenever a canister's snapshot layout changes incompatibly.
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SnapshotSection {
+    pub name: String,
+    pub entries: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SnapshotManifest {
+    pub snapshot_id: u64, // Creation time in nanoseconds
+    pub canister: String, // Which canister produced it, so snapshots cannot be restored into another
+    pub format_version: u32,
+    pub created_at: u64,
+    pub total_bytes: u64,
+    pub chunk_size: u32,
+    pub chunk_count: u32,
+    pub sha256: String, // Hex digest of the whole snapshot
+    pub sections: Vec<SnapshotSection>, // Entry counts, for checking a restore at a glance
+}
+
+/// A finished snapshot held in heap memory until it has been downloaded.
+pub struct Snapshot {
+    manifest: SnapshotManifest,
+    bytes: Vec<u8>,
+}
+
+impl Snapshot {
+    pub fn new(canister: &str, created_at: u64, bytes: Vec<u8>, sections: Vec<SnapshotSection>) -> Self {
+        let manifest = SnapshotManifest {
+            snapshot_id: created_at,
+            canister: canister.to_string(),
+            format_version: FORMAT_VERSION,
+            created_at,
+            total_bytes: bytes.len() as u64,
+            chunk_size: CHUNK_SIZE as u32,
+            chunk_count: bytes.len().div_ceil(CHUNK_SIZE) as u32,
+            sha256: sha256_hex(&bytes),
+            sections,
+        };
+
+        Snapshot { manifest, bytes }
+    }
+
+    pub fn manifest(&self) -> &SnapshotManifest {
+        &self.manifest
+    }
+
+    pub fn chunk(&self, snapshot_id: u64, index: u32) -> Result<Vec<u8>, String> {
+        if snapshot_id != self.manifest.snapshot_id {
+            return Err("Snapshot has been replaced by a newer one".to_string());
+        }
+
+        if index >= self.manifest.chunk_count {
+            return Err(format!("Chunk {} is out of range, the snapshot has {} chunks", index, self.manifest.chunk_count));
+        }
+
+        let start = index as usize * CHUNK_SIZE;
+        let end = (start + CHUNK_SIZE).min(self.bytes.len());
+        Ok(self.bytes[start..end].to_vec())
+    }
+}
+
+/// A restore in progress, collecting uploaded chunks until they can be verified.
+pub struct Restore {
+    manifest: SnapshotManifest,
+    bytes: Vec<u8>,
+    next_chunk: u32,
+}
+
+impl Restore {
+    pub fn new(manifest: SnapshotManifest, canister: &str) -> Result<Self, String> {
+        if manifest.canister != canister {
+            return Err(format!("Snapshot was taken from the {} canister, not {}", manifest.canister, canister));
+        }
+
+        if manifest.format_version != FORMAT_VERSION {
+            return Err(format!("Unsupported snapshot format version {}", manifest.format_version));
+        }
+
+        if manifest.chunk_size == 0 || manifest.chunk_size as usize > CHUNK_SIZE {
+            return Err(format!("Chunk size must be between 1 and {} bytes", CHUNK_SIZE));
+        }
+
+        let expected_chunks = (manifest.total_bytes).div_ceil(manifest.chunk_size as u64);
+        if expected_chunks != manifest.chunk_count as u64 {
+            return Err("Manifest chunk count does not match its total size".to_string());
+        }
+
+        Ok(Restore {
+            bytes: Vec::with_capacity(manifest.total_bytes as usize),
+            manifest,
+            next_chunk: 0,
+        })
+    }
+
+    pub fn manifest(&self) -> &SnapshotManifest {
+        &self.manifest
+    }
+
+    /// Appends the next chunk. Chunks must arrive in order. Returns the number of chunks still missing.
+    pub fn push_chunk(&mut self, index: u32, chunk: Vec<u8>) -> Result<u32, String> {
+        if index != self.next_chunk {
+            return Err(format!("Expected chunk {} but received chunk {}", self.next_chunk, index));
+        }
+
+        if index >= self.manifest.chunk_count {
+            return Err("All chunks have already been uploaded".to_string());
+        }
+
+        let is_last = index + 1 == self.manifest.chunk_count;
+        let remaining_bytes = self.manifest.total_bytes - self.bytes.len() as u64;
+        let expected_len = if is_last { remaining_bytes } else { self.manifest.chunk_size as u64 };
+        if chunk.len() as u64 != expected_len {
+            return Err(format!("Chunk {} should be {} bytes but is {}", index, expected_len, chunk.len()));
+        }
+
+        self.bytes.extend_from_slice(&chunk);
+        self.next_chunk += 1;
+        Ok(self.manifest.chunk_count - self.next_chunk)
+    }
+
+    /// Returns the snapshot bytes once every chunk has arrived and the checksum matches.
+    pub fn finish(self) -> Result<Vec<u8>, String> {
+        if self.next_chunk != self.manifest.chunk_count {
+            return Err(format!("Only {} of {} chunks have been uploaded", self.next_chunk, self.manifest.chunk_count));
+        }
+
+        if sha256_hex(&self.bytes) != self.manifest.sha256 {
+            return Err("Snapshot checksum does not match the manifest".to_string());
+        }
+
+        Ok(self.bytes)
+    }
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot_of(len: usize) -> Snapshot {
+        let bytes = (0..len).map(|i| (i % 251) as u8).collect();
+        Snapshot::new("registry", 42, bytes, Vec::new())
+    }
+
+    fn restore_all(snapshot: &Snapshot) -> Result<Vec<u8>, String> {
+        let manifest = snapshot.manifest().clone();
+        let mut restore = Restore::new(manifest.clone(), "registry")?;
+        for index in 0..manifest.chunk_count {
+            restore.push_chunk(index, snapshot.chunk(manifest.snapshot_id, index)?)?;
+        }
+        restore.finish()
+    }
+
+    #[test]
+    fn chunks_round_trip() {
+        let snapshot = snapshot_of(2 * CHUNK_SIZE + 17);
+        assert_eq!(snapshot.manifest().chunk_count, 3);
+        assert_eq!(restore_all(&snapshot).unwrap(), snapshot.bytes);
+    }
+
+    #[test]
+    fn empty_snapshot_has_no_chunks() {
+        let snapshot = snapshot_of(0);
+        assert_eq!(snapshot.manifest().chunk_count, 0);
+        assert_eq!(restore_all(&snapshot).unwrap(), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn tampered_chunks_fail_the_checksum() {
+        let snapshot = snapshot_of(100);
+        let mut restore = Restore::new(snapshot.manifest().clone(), "registry").unwrap();
+        let mut chunk = snapshot.chunk(42, 0).unwrap();
+        chunk[0] ^= 1;
+        restore.push_chunk(0, chunk).unwrap();
+        assert!(restore.finish().is_err());
+    }
+
+    #[test]
+    fn chunks_must_arrive_in_order() {
+        let snapshot = snapshot_of(CHUNK_SIZE + 1);
+        let mut restore = Restore::new(snapshot.manifest().clone(), "registry").unwrap();
+        assert!(restore.push_chunk(1, snapshot.chunk(42, 1).unwrap()).is_err());
+        assert!(restore.push_chunk(0, vec![0; 10]).is_err(), "wrong length");
+    }
+
+    #[test]
+    fn snapshots_only_restore_into_the_same_canister() {
+        let snapshot = snapshot_of(10);
+        assert!(Restore::new(snapshot.manifest().clone(), "marketplace").is_err());
+        assert!(snapshot.chunk(41, 0).is_err(), "stale snapshot id");
+    }
+}
@@ -0,0 +1,132 @@
+//! Title certificates: signed statements that a principal owned a parcel at a given time.
+//!
+//! The registry signs the SHA-256 hash of a canonical encoding of the claims with its threshold
+//! ECDSA (secp256k1) key. Anyone holding the registry's public key can check a certificate offline
+//! with [`verify_certificate`], without trusting whoever presents it.
+
+use candid::{CandidType, Principal};
+use k256::ecdsa::signature::hazmat::PrehashVerifier;
+use k256::ecdsa::{Signature, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::snapshot::sha256_hex;
+
+/// Prefix of the canonical encoding, bumped if the encoding ever changes.
+pub const TITLE_FORMAT: &str = "vlr-title-v1";
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TitleClaims {
+    pub registry: Principal, // Canister that issued the certificate
+    pub land_id: u64,
+    pub owner: Principal,
+    pub geometry_hash: String, // See `geometry_hash`
+    pub status: String,
+    pub issued_at: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TitleCertificate {
+    pub claims: TitleClaims,
+    pub key_name: String,
+    pub public_key: Vec<u8>, // SEC1 compressed secp256k1 key
+    pub signature: Vec<u8>, // 64 byte r || s over `claims.message_hash()`
+}
+
+impl TitleClaims {
+    /// One `|`-separated line. None of the fields can contain a `|`: principals are textual ids,
+    /// the geometry hash is hex and the status is a variant name.
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        format!(
+            "{}|{}|{}|{}|{}|{}|{}",
+            TITLE_FORMAT,
+            self.registry,
+            self.land_id,
+            self.owner,
+            self.geometry_hash,
+            self.status,
+            self.issued_at,
+        )
+        .into_bytes()
+    }
+
+    pub fn message_hash(&self) -> [u8; 32] {
+        Sha256::digest(self.canonical_bytes()).into()
+    }
+}
+
+/// Hex SHA-256 of a parcel's coordinates and size, so certificates commit to the geometry without
+/// repeating it.
+pub fn geometry_hash(coordinates: &str, size: f64) -> String {
+    sha256_hex(format!("{}|{}", coordinates, size).as_bytes())
+}
+
+/// Checks that the certificate was signed by `trusted_public_key`. The key must come from a
+/// trusted source, such as the registry's `get_title_public_key` query, not from the certificate.
+pub fn verify_certificate(certificate: &TitleCertificate, trusted_public_key: &[u8]) -> Result<(), String> {
+    if certificate.public_key != trusted_public_key {
+        return Err("Certificate was signed with a different key".to_string());
+    }
+
+    let key = VerifyingKey::from_sec1_bytes(trusted_public_key).map_err(|_| "Invalid public key".to_string())?;
+    let signature = Signature::from_slice(&certificate.signature).map_err(|_| "Malformed signature".to_string())?;
+    // k256 only accepts low-S signatures; malleability does not matter for a certificate
+    let signature = signature.normalize_s().unwrap_or(signature);
+
+    key.verify_prehash(&certificate.claims.message_hash(), &signature)
+        .map_err(|_| "Signature does not match the certificate".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k256::ecdsa::signature::hazmat::PrehashSigner;
+    use k256::ecdsa::SigningKey;
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_slice(&[7; 32]).unwrap()
+    }
+
+    fn certificate(key: &SigningKey) -> TitleCertificate {
+        let claims = TitleClaims {
+            registry: Principal::from_slice(&[9; 10]),
+            land_id: 42,
+            owner: Principal::from_slice(&[3; 29]),
+            geometry_hash: geometry_hash("40.7128, -74.0060", 1000.0),
+            status: "Verified".to_string(),
+            issued_at: 1_700_000_000_000_000_000,
+        };
+        let signature: Signature = key.sign_prehash(&claims.message_hash()).unwrap();
+
+        TitleCertificate {
+            claims,
+            key_name: "test_key".to_string(),
+            public_key: key.verifying_key().to_encoded_point(true).as_bytes().to_vec(),
+            signature: signature.to_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn valid_certificates_verify() {
+        let key = signing_key();
+        let certificate = certificate(&key);
+        assert_eq!(verify_certificate(&certificate, &certificate.public_key.clone()), Ok(()));
+    }
+
+    #[test]
+    fn altered_claims_fail() {
+        let key = signing_key();
+        let mut certificate = certificate(&key);
+        let public_key = certificate.public_key.clone();
+
+        certificate.claims.owner = Principal::from_slice(&[4; 29]);
+        assert!(verify_certificate(&certificate, &public_key).is_err());
+    }
+
+    #[test]
+    fn only_the_trusted_key_is_accepted() {
+        let certificate = certificate(&SigningKey::from_slice(&[8; 32]).unwrap());
+        let trusted = signing_key().verifying_key().to_encoded_point(true).as_bytes().to_vec();
+        assert!(verify_certificate(&certificate, &trusted).is_err());
+    }
+}
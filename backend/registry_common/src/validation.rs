@@ -0,0 +1,283 @@
+//! Input validation shared by the land registry and marketplace canisters.
+//!
+//! Canisters call these before storing anything and from `inspect_message`, so that clearly
+//! invalid ingress is dropped before it is charged for.
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ValidationLimits {
+    pub max_land_size: f64, // in square meters or virtual units
+    pub max_coordinates_len: u32,
+    pub max_description_len: u32,
+    pub max_metadata_len: u32,
+    pub max_title_len: u32,
+    pub max_category_len: u32,
+    pub max_tags: u32,
+    pub max_tag_len: u32,
+}
+
+impl Default for ValidationLimits {
+    fn default() -> Self {
+        ValidationLimits {
+            max_land_size: 1_000_000_000.0,
+            max_coordinates_len: 256,
+            max_description_len: 4_096,
+            max_metadata_len: 8_192,
+            max_title_len: 200,
+            max_category_len: 64,
+            max_tags: 20,
+            max_tag_len: 32,
+        }
+    }
+}
+
+impl ValidationLimits {
+    /// Checks that the limits themselves are usable before an admin stores them.
+    pub fn check(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+
+        if !self.max_land_size.is_finite() || self.max_land_size <= 0.0 {
+            errors.push("max_land_size", "must be a positive finite number");
+        }
+
+        let lengths = [
+            ("max_coordinates_len", self.max_coordinates_len),
+            ("max_description_len", self.max_description_len),
+            ("max_metadata_len", self.max_metadata_len),
+            ("max_title_len", self.max_title_len),
+            ("max_category_len", self.max_category_len),
+            ("max_tags", self.max_tags),
+            ("max_tag_len", self.max_tag_len),
+        ];
+        for (field, value) in lengths {
+            if value == 0 {
+                errors.push(field, "must be greater than zero");
+            }
+        }
+
+        errors.into_result()
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ValidationErrors(pub Vec<FieldError>);
+
+impl ValidationErrors {
+    pub fn push(&mut self, field: &str, message: impl Into<String>) {
+        self.0.push(FieldError {
+            field: field.to_string(),
+            message: message.into(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn into_result(self) -> Result<(), ValidationErrors> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid input: ")?;
+        for (index, error) in self.0.iter().enumerate() {
+            if index > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{} {}", error.field, error.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<ValidationErrors> for String {
+    fn from(errors: ValidationErrors) -> Self {
+        errors.to_string()
+    }
+}
+
+/// The parts of a land registration that are subject to validation.
+pub struct LandFields<'a> {
+    pub coordinates: &'a str,
+    pub size: f64,
+    pub description: &'a str,
+    pub metadata: &'a str,
+}
+
+/// The parts of a marketplace listing that are subject to validation.
+pub struct ListingFields<'a> {
+    pub price: u64,
+    pub title: &'a str,
+    pub description: &'a str,
+    pub category: &'a str,
+    pub tags: &'a [String],
+}
+
+pub fn validate_land(land: &LandFields, limits: &ValidationLimits) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::default();
+
+    check_required_text(&mut errors, "coordinates", land.coordinates, limits.max_coordinates_len);
+    check_optional_text(&mut errors, "description", land.description, limits.max_description_len);
+    check_optional_text(&mut errors, "metadata", land.metadata, limits.max_metadata_len);
+
+    // NaN fails every comparison, so test for the valid range instead of the invalid one
+    if !(land.size.is_finite() && land.size > 0.0) {
+        errors.push("size", "must be a positive finite number");
+    } else if land.size > limits.max_land_size {
+        errors.push("size", format!("must not exceed {}", limits.max_land_size));
+    }
+
+    errors.into_result()
+}
+
+pub fn validate_listing(listing: &ListingFields, limits: &ValidationLimits) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::default();
+
+    check_price(&mut errors, listing.price);
+
+    check_required_text(&mut errors, "title", listing.title, limits.max_title_len);
+    check_optional_text(&mut errors, "description", listing.description, limits.max_description_len);
+    check_required_text(&mut errors, "category", listing.category, limits.max_category_len);
+
+    if listing.tags.len() > limits.max_tags as usize {
+        errors.push("tags", format!("must not contain more than {} entries", limits.max_tags));
+    }
+    if listing.tags.iter().any(|tag| tag.trim().is_empty()) {
+        errors.push("tags", "must not contain empty entries");
+    }
+    if listing.tags.iter().any(|tag| tag.chars().count() > limits.max_tag_len as usize) {
+        errors.push("tags", format!("entries must not be longer than {} characters", limits.max_tag_len));
+    }
+
+    errors.into_result()
+}
+
+/// Validates a price on its own, for when only the price of a listing changes.
+pub fn validate_price(price: u64) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::default();
+    check_price(&mut errors, price);
+    errors.into_result()
+}
+
+fn check_price(errors: &mut ValidationErrors, price: u64) {
+    if price == 0 {
+        errors.push("price", "must be greater than zero");
+    }
+}
+
+fn check_required_text(errors: &mut ValidationErrors, field: &str, value: &str, max_len: u32) {
+    if value.trim().is_empty() {
+        errors.push(field, "must not be empty");
+    } else {
+        check_optional_text(errors, field, value, max_len);
+    }
+}
+
+fn check_optional_text(errors: &mut ValidationErrors, field: &str, value: &str, max_len: u32) {
+    if value.chars().count() > max_len as usize {
+        errors.push(field, format!("must not be longer than {} characters", max_len));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn land(coordinates: &str, size: f64) -> LandFields<'_> {
+        LandFields {
+            coordinates,
+            size,
+            description: "Waterfront parcel",
+            metadata: "",
+        }
+    }
+
+    fn listing(tags: &[String]) -> ListingFields<'_> {
+        ListingFields {
+            price: 1_000,
+            title: "Harbour plot",
+            description: "",
+            category: "land",
+            tags,
+        }
+    }
+
+    fn fields(result: Result<(), ValidationErrors>) -> Vec<String> {
+        result.err().unwrap_or_default().0.into_iter().map(|error| error.field).collect()
+    }
+
+    #[test]
+    fn land_sizes_must_be_positive_finite_and_within_the_limit() {
+        let limits = ValidationLimits::default();
+        assert_eq!(validate_land(&land("40.7, -74.0", 1_000.0), &limits), Ok(()));
+
+        for size in [f64::NAN, f64::INFINITY, 0.0, -1.0, limits.max_land_size + 1.0] {
+            assert_eq!(fields(validate_land(&land("40.7, -74.0", size), &limits)), vec!["size"], "size {}", size);
+        }
+        assert_eq!(validate_land(&land("40.7, -74.0", limits.max_land_size), &limits), Ok(()));
+    }
+
+    #[test]
+    fn coordinates_are_required_and_bounded() {
+        let limits = ValidationLimits { max_coordinates_len: 8, ..Default::default() };
+
+        assert_eq!(fields(validate_land(&land("", 1.0), &limits)), vec!["coordinates"]);
+        assert_eq!(fields(validate_land(&land("   ", 1.0), &limits)), vec!["coordinates"]);
+        assert_eq!(fields(validate_land(&land("40.71, -74.01", 1.0), &limits)), vec!["coordinates"]);
+        // Lengths count characters, not bytes
+        assert_eq!(validate_land(&land("ÅÅÅÅÅÅÅÅ", 1.0), &limits), Ok(()));
+    }
+
+    #[test]
+    fn every_invalid_field_is_reported() {
+        let limits = ValidationLimits::default();
+        let errors = validate_land(&land(" ", f64::NAN), &limits).unwrap_err();
+
+        assert_eq!(fields(Err(errors.clone())), vec!["coordinates", "size"]);
+        assert_eq!(errors.to_string(), "Invalid input: coordinates must not be empty; size must be a positive finite number");
+    }
+
+    #[test]
+    fn listing_tags_are_limited_in_count_and_length() {
+        let limits = ValidationLimits { max_tags: 2, max_tag_len: 5, ..Default::default() };
+        let tags = |values: &[&str]| values.iter().map(|tag| tag.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(validate_listing(&listing(&tags(&["sea", "plot"])), &limits), Ok(()));
+        assert_eq!(fields(validate_listing(&listing(&tags(&["a", "b", "c"])), &limits)), vec!["tags"]);
+        assert_eq!(fields(validate_listing(&listing(&tags(&["harbour"])), &limits)), vec!["tags"]);
+        assert_eq!(fields(validate_listing(&listing(&tags(&[" "])), &limits)), vec!["tags"]);
+    }
+
+    #[test]
+    fn listings_need_a_price_title_and_category() {
+        let limits = ValidationLimits::default();
+        let invalid = ListingFields { price: 0, title: "", category: " ", ..listing(&[]) };
+
+        assert_eq!(fields(validate_listing(&invalid, &limits)), vec!["price", "title", "category"]);
+    }
+
+    #[test]
+    fn limits_are_checked_before_they_are_stored() {
+        assert_eq!(ValidationLimits::default().check(), Ok(()));
+
+        let limits = ValidationLimits { max_land_size: f64::NAN, max_tags: 0, ..Default::default() };
+        assert_eq!(fields(limits.check()), vec!["max_land_size", "max_tags"]);
+        let limits = ValidationLimits { max_land_size: -1.0, ..Default::default() };
+        assert_eq!(fields(limits.check()), vec!["max_land_size"]);
+    }
+}